@@ -1,32 +1,181 @@
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer as _, Serialize};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
-use std::path::Path;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::OnceLock;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 use walkdir::WalkDir;
 
 const DEFAULT_DATA_REPO: &str = "https://github.com/barrins-project/mtg_decklist_cache.git";
 const SCRYFALL_BULK_API: &str = "https://api.scryfall.com/bulk-data";
 const SCRYFALL_CACHE_MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60; // 7 days
+const SCRYFALL_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const SCRYFALL_READ_TIMEOUT: Duration = Duration::from_secs(60);
+const SCRYFALL_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const SCRYFALL_MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+const SCRYFALL_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const DECKLIST_FETCH_INTERVAL: Duration = Duration::from_millis(500);
+const DECKLIST_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const DEBUG_WEIGHT_AGES: [i64; 7] = [0, 7, 30, 45, 90, 180, 365];
+
+/// Expected mainboard size and tolerance by format substring, checked in order.
+/// Formats not listed are assumed to be 60-card constructed.
+const EXPECTED_DECK_SIZES: &[(&str, u32, u32)] = &[
+    ("commander", 100, 0),
+    ("edh", 100, 0),
+    ("limited", 40, 5),
+    ("draft", 40, 5),
+    ("sealed", 40, 5),
+];
+const DEFAULT_DECK_SIZE: (u32, u32) = (60, 5);
+const COLOR_BUCKETS: [&str; 8] = [
+    "White",
+    "Blue",
+    "Black",
+    "Red",
+    "Green",
+    "Multicolor",
+    "Colorless",
+    "Unknown",
+];
+const RARITY_BUCKETS: [&str; 6] = ["Common", "Uncommon", "Rare", "Mythic", "Special", "Unknown"];
+/// Basic land names dropped by --exclude-basics, lowercase
+const BASIC_LANDS: [&str; 11] = [
+    "plains",
+    "island",
+    "swamp",
+    "mountain",
+    "forest",
+    "wastes",
+    "snow-covered plains",
+    "snow-covered island",
+    "snow-covered swamp",
+    "snow-covered mountain",
+    "snow-covered forest",
+];
+/// Short names accepted by --formats, mapped to the format they stand for
+const FORMAT_ALIASES: &[(&str, &str)] = &[
+    ("pio", "pioneer"),
+    ("std", "standard"),
+    ("edh", "commander"),
+    ("leg", "legacy"),
+    ("mod", "modern"),
+    ("pau", "pauper"),
+    ("vin", "vintage"),
+];
+/// Primary card types for --by-type, in priority order: a card with several
+/// types (e.g. "Artifact Creature") goes to the first one it has
+const TYPE_BUCKETS: [&str; 9] = [
+    "Creature",
+    "Planeswalker",
+    "Battle",
+    "Instant",
+    "Sorcery",
+    "Artifact",
+    "Enchantment",
+    "Land",
+    "Unknown",
+];
+const MANA_VALUE_BUCKETS: [&str; 9] = ["0", "1", "2", "3", "4", "5", "6", "7+", "N/A"];
+
+/// Set by --quiet to silence progress messages
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Set by --scryfall-retries
+static SCRYFALL_RETRIES: AtomicU32 = AtomicU32::new(3);
+
+/// Set by --scryfall-file to use local bulk data instead of the cache
+static SCRYFALL_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Files skipped because they could not be read or parsed, from any command
+static UNPARSEABLE_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn note_unparseable(path: &Path) {
+    UNPARSEABLE_FILES.lock().unwrap().push(path.to_path_buf());
+}
+
+/// Summary of the unparseable files, listing them (sorted) when `verbose`
+fn unparseable_report(paths: &[PathBuf], verbose: bool) -> Vec<String> {
+    // A file can be read by several passes (e.g. --bootstrap) but is reported once
+    let paths: BTreeSet<&PathBuf> = paths.iter().collect();
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    let mut lines = vec![format!("Warning: Skipped {} unparseable files", paths.len())];
+    if verbose {
+        lines.extend(paths.iter().map(|p| format!("  {}", p.display())));
+    }
+    lines
+}
+
+/// Write one progress line to `out`, unless `quiet`. Errors and warnings
+/// bypass this and always go to stderr.
+fn write_progress(out: &mut dyn Write, quiet: bool, message: std::fmt::Arguments) {
+    if !quiet {
+        let _ = writeln!(out, "{}", message);
+    }
+}
+
+/// Print a progress line to stderr unless --quiet is set
+macro_rules! progress {
+    () => {
+        progress!("")
+    };
+    ($($arg:tt)*) => {
+        write_progress(&mut std::io::stderr(), quiet(), format_args!($($arg)*))
+    };
+}
 
 #[derive(Parser)]
 #[command(name = "top_cards")]
 #[command(about = "MTG tournament deck analysis tool")]
+#[command(after_help = "Without a subcommand, runs top-cards; see `top_cards top-cards --help` for its options.")]
 struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Comma-separated list of formats
+    /// Top-cards flags given without a subcommand, for the implicit top-cards run
+    #[command(flatten)]
+    top_cards: TopCardsArgs,
+
+    /// Comma-separated list of formats (case-insensitive; aliases like pio, std, edh accepted)
     #[arg(short, long, default_value = "Standard,Modern,Pioneer,Legacy", global = true)]
     formats: String,
 
-    /// Base directory to search (defaults to ./data when --fetch is used)
+    /// Whether --formats was given on the command line or in the config file
+    #[arg(skip)]
+    formats_set: bool,
+
+    /// Match formats by whole name instead of substring, so Modern excludes Premodern
+    #[arg(long, global = true)]
+    exact_format: bool,
+
+    /// Take the format of files without one, such as bare arrays of decks,
+    /// from the directory above the date (e.g. modern/2025/01/10/); such
+    /// files are skipped otherwise
+    #[arg(long, global = true)]
+    infer_format: bool,
+
+    /// Base directory to search, or several comma-separated (defaults to --data-dir when --fetch is used)
     #[arg(short, long, global = true)]
     dir: Option<String>,
 
@@ -34,17 +183,123 @@ struct Args {
     #[arg(short, long, default_value = "1825", global = true)]
     max_age: i64,
 
+    /// Skip events with fewer Swiss rounds (inferred from deck count if not recorded)
+    #[arg(long, global = true)]
+    min_rounds: Option<u32>,
+
+    /// Skip decks with fewer mainboard cards, e.g. partial scrapes
+    /// (60 if given without a value)
+    #[arg(long, value_name = "CARDS", num_args = 0..=1, default_missing_value = "60", global = true)]
+    min_deck_size: Option<u32>,
+
+    /// What to do with files dated in the future: skip them, clamp their weight to 1, or allow them
+    #[arg(long, value_enum, default_value = "skip", global = true)]
+    future_dates: FutureDates,
+
+    /// Only process files whose path under the search directory matches this glob (repeatable)
+    #[arg(long = "include-glob", value_name = "PATTERN", global = true)]
+    include_globs: Vec<String>,
+
+    /// Skip files whose path under the search directory matches this glob, before opening them (repeatable)
+    #[arg(long = "exclude-glob", value_name = "PATTERN", global = true)]
+    exclude_globs: Vec<String>,
+
+    /// Process a random subset of the files, keeping each with this probability
+    /// (0-1), for quick approximate runs
+    #[arg(long, value_name = "FRACTION", value_parser = parse_fraction, global = true)]
+    sample: Option<f64>,
+
+    /// Seed for --sample; the same seed keeps the same files (default: random, printed)
+    #[arg(long, requires = "sample", global = true)]
+    seed: Option<u64>,
+
+    /// Succeed with empty output instead of exiting with code 2 when no input files are found
+    #[arg(long, global = true)]
+    allow_empty: bool,
+
+    /// Only report how many files are in scope by date and format, and
+    /// their formats, reading just each file's tournament, then exit
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Read newline-separated JSON file paths from this file ("-" for stdin)
+    /// instead of walking --dir, e.g. the output of git diff --name-only
+    #[arg(long, value_name = "PATH", global = true)]
+    files_from: Option<String>,
+
+    /// Only include decks piloted by this player (repeatable, case-insensitive)
+    #[arg(long = "player", value_name = "NAME", global = true)]
+    players: Vec<String>,
+
+    /// Only include decks whose color identity (from Scryfall, all zones) fits
+    /// within these colors, e.g. R for mono-red or C for colorless
+    #[arg(long, alias = "color-identity-filter", value_name = "WUBRG", value_parser = parse_color_mask, global = true)]
+    colors: Option<u8>,
+
+    /// With --colors, require the deck's color identity to be exactly those colors
+    #[arg(long, requires = "colors", global = true)]
+    colors_exact: bool,
+
+    /// With --colors, what to do with decks running cards Scryfall doesn't know
+    #[arg(long, value_enum, default_value = "skip", requires = "colors", global = true)]
+    unknown_colors: UnknownColors,
+
+    /// Scryfall color identities for --colors, loaded in main
+    #[arg(skip)]
+    color_identities: Arc<HashMap<String, u8>>,
+
     /// Fetch/update the data repository before processing
     #[arg(short = 'F', long, global = true)]
     fetch: bool,
 
-    /// Directory for the data repository (default: ./data)
+    /// Directory for the data repository, or several comma-separated; --fetch
+    /// updates only the first (default: ./data)
     #[arg(long, default_value = "./data", global = true)]
     data_dir: String,
 
     /// Git URL for the data repository
     #[arg(long, default_value = DEFAULT_DATA_REPO, global = true)]
     data_repo: String,
+
+    /// Branch of the data repository to use (default: the remote's default branch)
+    #[arg(long, global = true)]
+    data_branch: Option<String>,
+
+    /// On a fresh clone, only check out this subdirectory of the data repository
+    #[arg(long, value_name = "SUBPATH", requires = "fetch", global = true)]
+    sparse: Option<String>,
+
+    /// After fetching, check the data repository with git fsck and fail if it looks incomplete
+    #[arg(long, requires = "fetch", global = true)]
+    verify_fetch: bool,
+
+    /// Config file with default flags (default: ~/.config/mtg_top_cards/config.toml)
+    #[arg(long, global = true)]
+    config: Option<String>,
+
+    /// Scryfall bulk data to use for card data, e.g. default_cards for more prices.
+    /// Bulks other than oracle_cards list every printing, and the last printing
+    /// of a name wins in card lookups.
+    #[arg(long, value_enum, default_value_t = ScryfallBulk::OracleCards, global = true)]
+    scryfall_bulk: ScryfallBulk,
+
+    /// Local Scryfall bulk data JSON to use instead of downloading, e.g. a
+    /// pinned snapshot for offline runs
+    #[arg(long, global = true)]
+    scryfall_file: Option<String>,
+
+    /// Suppress progress messages on stderr; errors and warnings are still printed
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// List the paths of files skipped as unparseable
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Times to retry a Scryfall request after a timeout, 429 or 5xx response,
+    /// backing off exponentially (or as long as Retry-After asks)
+    #[arg(long, default_value = "3", global = true)]
+    scryfall_retries: u32,
 }
 
 #[derive(clap::Subcommand)]
@@ -53,6 +308,22 @@ enum Commands {
     TopCards(TopCardsArgs),
     /// Search for decks containing specific cards
     SearchDecks(SearchDecksArgs),
+    /// Find cards most often played alongside seed cards
+    CoOccur(CoOccurArgs),
+    /// Compare two players' card choices side by side
+    ComparePlayers(ComparePlayersArgs),
+    /// List the players with the most decks, their best finish and most played card
+    PlayerStats(PlayerStatsArgs),
+    /// Share of decks running card A that also run card B
+    Conditional(ConditionalArgs),
+    /// Show the card-by-card difference between two decklists
+    DeckDiff(DeckDiffArgs),
+    /// List a card's top finishes over time, oldest first
+    Timeline(TimelineArgs),
+    /// Count in-scope decks and files per day, week or month
+    Coverage(CoverageArgs),
+    /// Compare two saved top-cards outputs (plain text or --jsonl)
+    Diff(DiffArgs),
 }
 
 #[derive(clap::Args)]
@@ -61,10 +332,19 @@ struct TopCardsArgs {
     #[arg(short, long, default_value = "5000")]
     num: usize,
 
+    /// Whether --num was given on the command line or in the config file
+    #[arg(skip)]
+    num_set: bool,
+
     /// Output file (default: stdout)
     #[arg(short, long)]
     output: Option<String>,
 
+    /// Also write a JSON manifest of the run (parameters, files and decks
+    /// counted, Scryfall data date, timestamp) to this file
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<String>,
+
     /// Half-life in days for time decay
     #[arg(short = 'l', long, default_value = "45")]
     half_life: f64,
@@ -73,22 +353,239 @@ struct TopCardsArgs {
     #[arg(short = 'w', long)]
     no_weight: bool,
 
+    /// Shape of the time decay; --half-life applies to exp, --window to linear and step
+    #[arg(long, value_enum, default_value = "exp", conflicts_with_all = ["no_weight", "rank_decay"])]
+    weight_function: WeightFunction,
+
+    /// Age in days at which linear and step weights reach 0
+    #[arg(long, default_value = "90")]
+    window: f64,
+
+    /// Weight tournaments by recency rank (1/N for the Nth most recent) instead of age
+    #[arg(long, conflicts_with = "no_weight")]
+    rank_decay: bool,
+
     /// Resolve back faces of double-faced cards via Scryfall
     #[arg(long, default_value = "true")]
     resolve_faces: bool,
+
+    /// Print the weight applied at representative ages before processing
+    #[arg(long)]
+    debug_weights: bool,
+
+    /// Output the share of decks running each card instead of weighted copies
+    #[arg(long)]
+    percent: bool,
+
+    /// What each deck contributes to a card's count; --percent always counts presence
+    #[arg(long, value_enum, default_value = "copies")]
+    count_mode: CountMode,
+
+    /// Report per format how many decks deviate from the expected deck size
+    #[arg(long)]
+    size_sanity: bool,
+
+    /// Reparse every file instead of using the processed-file cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Experimental: resample decks N times and report rank mean and standard deviation
+    #[arg(long, value_name = "N")]
+    bootstrap: Option<usize>,
+
+    /// Output weighted totals per color (via Scryfall) instead of individual cards
+    #[arg(long)]
+    by_color: bool,
+
+    /// Output weighted totals per rarity (via Scryfall) instead of individual cards.
+    /// Rarity comes from each card's representative printing in the oracle-cards
+    /// bulk data, so reprints at another rarity are not reflected
+    #[arg(long, conflicts_with = "by_color")]
+    by_rarity: bool,
+
+    /// Output a weighted mana value histogram (via Scryfall) instead of individual cards
+    #[arg(long, conflicts_with_all = ["by_color", "by_rarity"])]
+    mana_curve: bool,
+
+    /// Output weighted totals per primary card type (via Scryfall) instead of individual cards
+    #[arg(long, conflicts_with_all = ["by_color", "by_rarity", "mana_curve"])]
+    by_type: bool,
+
+    /// Report the weighted share of lands vs spells among all counted cards (via Scryfall)
+    #[arg(long)]
+    land_ratio: bool,
+
+    /// Fetch decklists for decks that only have a source URL (slow, network heavy)
+    #[arg(long)]
+    resolve_urls: bool,
+
+    /// Scale each event by its size, min(decks, --size-cap) / --size-cap; multiplies
+    /// with the time decay weight
+    #[arg(long)]
+    size_weight: bool,
+
+    /// Deck count at which an event gets full weight under --size-weight
+    #[arg(long, default_value = "64", requires = "size_weight")]
+    size_cap: u64,
+
+    /// Keep running totals in this file and only process files dated after the
+    /// previous run. Changing --half-life, --no-weight, --percent or --formats
+    /// starts the totals over
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["rank_decay", "cross_format_stability", "show_raw"])]
+    since_file: Option<String>,
+
+    /// Drop cards whose name is shorter than this many characters (empty names are always dropped)
+    #[arg(long, default_value = "2")]
+    min_name_length: usize,
+
+    /// Skip repeated decks (same player and mainboard) within a file
+    #[arg(long)]
+    dedupe: bool,
+
+    /// Count each distinct list (mainboard and sideboard) once across all files,
+    /// keeping its most recent appearance
+    #[arg(long, conflicts_with_all = ["rank_decay", "since_file", "cross_format_stability", "resolve_urls", "dedupe"])]
+    unique_decks: bool,
+
+    /// Rank the top cards by how evenly their deck share spreads across formats
+    /// (lowest standard deviation first, i.e. consensus cards)
+    #[arg(long)]
+    cross_format_stability: bool,
+
+    /// With --cross-format-stability, put format-specific cards (highest deviation) first
+    #[arg(long, requires = "cross_format_stability")]
+    specialist: bool,
+
+    /// Rank each requested format separately, writing one section per --formats entry
+    #[arg(long, conflicts_with_all = [
+        "cross_format_stability", "rank_decay", "since_file", "unique_decks", "show_raw", "jsonl",
+        "template", "by_color", "by_rarity", "mana_curve", "by_type", "with_price", "sort", "bootstrap",
+    ])]
+    split_formats: bool,
+
+    /// Leave basic lands (including Wastes and snow-covered basics) out of the ranking
+    #[arg(long)]
+    exclude_basics: bool,
+
+    /// List every distinct card name with its total count, alphabetically;
+    /// only the top --num cards are kept if --num is set explicitly
+    #[arg(long, conflicts_with_all = [
+        "by_color", "by_rarity", "mana_curve", "by_type", "split_formats", "cross_format_stability", "sort",
+    ])]
+    distinct_only: bool,
+
+    /// Merge alternate card names (double-faced full or back face names, printed
+    /// names) into their canonical Scryfall name before ranking
+    #[arg(long, alias = "normalize-names")]
+    canonicalize: bool,
+
+    /// Count snow-covered basics (and differently cased basics) under the
+    /// plain basic land name, e.g. Snow-Covered Mountain as Mountain
+    #[arg(long)]
+    fold_basics: bool,
+
+    /// Count mainboards only, skipping sideboards while parsing (faster on large datasets)
+    #[arg(long)]
+    skip_sideboard: bool,
+
+    /// Count only the N best placed decks of each event (file order when
+    /// results are missing), after --dedupe; --size-weight still sees the full event
+    #[arg(long, value_name = "N")]
+    max_decks_per_event: Option<usize>,
+
+    /// Only count decks that link to a source URL, leaving out manually
+    /// entered or incomplete entries
+    #[arg(long)]
+    require_url: bool,
+
+    /// Multiply each deck's contribution by its match wins + 1, taken from the
+    /// deck's wins or match record (decks with neither count once)
+    #[arg(long)]
+    win_weight: bool,
+
+    /// Which part of each deck to count: both, main (mainboard and command
+    /// zone, like --skip-sideboard) or side (sideboard only)
+    #[arg(long, value_enum, default_value = "both", conflicts_with = "skip_sideboard")]
+    board: Board,
+
+    /// Add the unweighted count after the weighted one, to audit the time decay
+    #[arg(long)]
+    show_raw: bool,
+
+    /// After the weighted copies, add the share of decks running each card and
+    /// its average copies when present, telling 4-of staples from 1-of toolbox cards
+    #[arg(long, conflicts_with_all = [
+        "percent", "count_mode", "jsonl", "template", "split_formats", "cross_format_stability",
+        "by_color", "by_rarity", "mana_curve", "by_type", "since_file",
+    ])]
+    detailed: bool,
+
+    /// Add the first and last date each card was seen in scope, as YYYY-MM-DD
+    #[arg(long, conflicts_with_all = [
+        "unique_decks", "since_file", "split_formats", "cross_format_stability",
+        "by_color", "by_rarity", "mana_curve", "by_type",
+    ])]
+    dates: bool,
+
+    /// Reorder the output after picking the top cards (default: ranking order).
+    /// Back faces stay right after their front faces
+    #[arg(long, value_enum)]
+    sort: Option<SortOrder>,
+
+    /// Write one JSON object per line ({"name": ..., "weight": ...}) instead of plain text
+    #[arg(long, conflicts_with = "template")]
+    jsonl: bool,
+
+    /// Prefix each plain-text line with its rank and right-align the counts,
+    /// e.g. "  1.  1234.56  Lightning Bolt". For display only: the diff
+    /// subcommand can't read it
+    #[arg(long, conflicts_with_all = ["jsonl", "template", "split_formats"])]
+    ranked: bool,
+
+    /// Write just the card names, one per line, in output order, e.g. to feed
+    /// a card name dictionary to another tool
+    #[arg(long, alias = "card-list", conflicts_with_all = [
+        "jsonl", "template", "ranked", "percent", "show_raw", "detailed", "dates", "with_price",
+        "split_formats", "cross_format_stability", "by_color", "by_rarity", "mana_curve", "by_type",
+    ])]
+    names_only: bool,
+
+    /// Append each card's USD price (via Scryfall, "-" if unknown). Oracle-cards
+    /// bulk data prices one representative printing, so many cards have none; try
+    /// --scryfall-bulk default_cards
+    #[arg(long, conflicts_with_all = ["template", "by_color", "by_rarity", "mana_curve", "by_type"])]
+    with_price: bool,
+
+    /// Write the top-card names not found in Scryfall to this file, one per line
+    #[arg(long, value_name = "PATH")]
+    report_unknown: Option<String>,
+
+    /// Custom line format with placeholders {rank}, {name}, {count}, {count:.3},
+    /// {share} (deck share %, use with --percent) and {type} (via Scryfall)
+    #[arg(long)]
+    template: Option<String>,
 }
 
 #[derive(clap::Args)]
 struct SearchDecksArgs {
     /// Cards to search for, format: "4 Lightning Bolt" or "Lightning Bolt"
     /// Multiple cards can be specified, all must match (AND logic)
+    /// Prefix a card with "!" to exclude decks running it
     #[arg(required = true)]
     cards: Vec<String>,
 
+    /// Exclude decks running this card (repeatable)
+    #[arg(short = 'x', long)]
+    exclude: Vec<String>,
+
     /// Require exact count match (default: at least N copies)
     #[arg(short, long)]
     exact: bool,
 
+    /// Treat counts as upper bounds: "3 Lightning Bolt" matches 1 to 3 copies
+    #[arg(long, conflicts_with = "exact")]
+    at_most: bool,
+
     /// Maximum number of decks to show
     #[arg(short, long, default_value = "50")]
     num: usize,
@@ -96,6 +593,200 @@ struct SearchDecksArgs {
     /// Include sideboard in search
     #[arg(short, long)]
     sideboard: bool,
+
+    /// Match decks containing any of the cards (OR logic) instead of all of them.
+    /// Counts then match partially, so "4 Scalding Tarn" also finds decks running
+    /// 2; --at-most bounds still apply
+    #[arg(long, conflicts_with = "exact")]
+    match_any: bool,
+
+    /// Only show decks that link to a source URL
+    #[arg(long)]
+    require_url: bool,
+
+    /// Match cards whose name contains the given text, e.g. "Ragavan"
+    #[arg(long)]
+    fuzzy: bool,
+
+    /// Ignore accents and punctuation in card names, e.g. "Lim-Dul's Vault"
+    /// finds "Lim-Dûl's Vault"
+    #[arg(long, conflicts_with = "regex")]
+    loose: bool,
+
+    /// Treat card names as case-insensitive regexes matching the whole name,
+    /// e.g. "Urza's .*"; counts apply to the total over all matching cards
+    #[arg(long, conflicts_with = "fuzzy")]
+    regex: bool,
+
+    /// Match cards in the command zone only, e.g. to find decks led by a general;
+    /// with partners, each partner is matched like any other card
+    #[arg(long, conflicts_with = "sideboard")]
+    commander_only: bool,
+
+    /// After the decks, print the number of matches and the average copies of
+    /// each searched card (over all matches, before --num)
+    #[arg(long)]
+    summary: bool,
+
+    /// Label each deck with the first archetype in this TOML file whose
+    /// signature cards it runs
+    #[arg(long, value_name = "PATH")]
+    archetypes: Option<String>,
+
+    /// In Commander formats, label decks no --archetypes entry matches by their
+    /// command zone, with partners and backgrounds sorted into one key, e.g.
+    /// "Thrasios, Triton Hero + Tymna the Weaver"
+    #[arg(long)]
+    merge_partner_commanders: bool,
+}
+
+#[derive(clap::Args)]
+struct CoOccurArgs {
+    /// Seed cards, format: "4 Lightning Bolt" or "Lightning Bolt"
+    /// Only decks containing all seed cards are counted
+    #[arg(required = true)]
+    seeds: Vec<String>,
+
+    /// Number of cards to output
+    #[arg(short, long, default_value = "50")]
+    num: usize,
+
+    /// Half-life in days for time decay
+    #[arg(short = 'l', long, default_value = "45")]
+    half_life: f64,
+
+    /// Disable time-based weighting
+    #[arg(short = 'w', long)]
+    no_weight: bool,
+
+    /// Include sideboard when matching seed cards
+    #[arg(short, long)]
+    sideboard: bool,
+
+    /// Also write a CSV matrix of weighted co-play counts among the top cards
+    #[arg(long)]
+    matrix: Option<String>,
+
+    /// Number of top cards in the --matrix rows and columns
+    #[arg(long, default_value = "100", requires = "matrix")]
+    matrix_size: usize,
+}
+
+#[derive(clap::Args)]
+struct ComparePlayersArgs {
+    /// First player (case-insensitive)
+    player_a: String,
+
+    /// Second player (case-insensitive)
+    player_b: String,
+
+    /// Number of cards to output per section
+    #[arg(short, long, default_value = "20")]
+    num: usize,
+
+    /// Half-life in days for time decay
+    #[arg(short = 'l', long, default_value = "45")]
+    half_life: f64,
+
+    /// Disable time-based weighting
+    #[arg(short = 'w', long)]
+    no_weight: bool,
+}
+
+#[derive(clap::Args)]
+struct PlayerStatsArgs {
+    /// Number of players to output
+    #[arg(short, long, default_value = "20")]
+    num: usize,
+}
+
+#[derive(clap::Args)]
+struct ConditionalArgs {
+    /// Card every counted deck must run, format: "4 Lightning Bolt" or "Lightning Bolt"
+    card_a: String,
+
+    /// Card whose share among those decks is reported
+    card_b: String,
+
+    /// Include sideboard when matching cards
+    #[arg(short, long)]
+    sideboard: bool,
+}
+
+#[derive(clap::Args)]
+struct DeckDiffArgs {
+    /// Old deck: "event.json#N" (Nth deck, default 1), "event.json@Player", or a plain-text "deck.txt"
+    deck_a: String,
+
+    /// New deck, in the same forms
+    deck_b: String,
+}
+
+#[derive(clap::Args)]
+struct DiffArgs {
+    /// Older top-cards output
+    before: String,
+
+    /// Newer top-cards output
+    after: String,
+
+    /// Number of changes to show
+    #[arg(short, long, default_value = "50")]
+    num: usize,
+}
+
+#[derive(clap::Args)]
+struct TimelineArgs {
+    /// Card to track, e.g. "Lightning Bolt"
+    card: String,
+
+    /// Only count finishes at this placement or better (e.g. 8 for top 8)
+    #[arg(short, long, default_value = "8")]
+    placement: u32,
+
+    /// Include sideboard when matching the card
+    #[arg(short, long)]
+    sideboard: bool,
+}
+
+#[derive(clap::Args)]
+struct CoverageArgs {
+    /// Period each row covers
+    #[arg(short, long, value_enum, default_value_t = Bucket::Day)]
+    bucket: Bucket,
+}
+
+/// Defaults loaded from the config file. Command-line flags override these,
+/// and these override the built-in defaults.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    formats: Option<String>,
+    dir: Option<String>,
+    max_age: Option<i64>,
+    min_rounds: Option<u32>,
+    min_deck_size: Option<u32>,
+    players: Option<Vec<String>>,
+    data_dir: Option<String>,
+    data_repo: Option<String>,
+    data_branch: Option<String>,
+    #[serde(rename = "top-cards")]
+    top_cards: CommandConfig,
+    #[serde(rename = "search-decks")]
+    search_decks: CommandConfig,
+    #[serde(rename = "co-occur")]
+    co_occur: CommandConfig,
+    #[serde(rename = "compare-players")]
+    compare_players: CommandConfig,
+}
+
+/// Per-command defaults; keys a command doesn't have are ignored
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct CommandConfig {
+    num: Option<usize>,
+    half_life: Option<f64>,
+    no_weight: Option<bool>,
 }
 
 /// Parsed card search criterion
@@ -103,10 +794,37 @@ struct SearchDecksArgs {
 struct CardCriterion {
     name: String,
     count: Option<u32>,
+    /// Decks satisfying this criterion are rejected instead of matched
+    excluded: bool,
+    /// Compiled name pattern with --regex; matching cards' counts are summed
+    pattern: Option<Regex>,
+}
+
+/// How deck contents are compared against card criteria
+#[derive(Debug, Clone, Default)]
+struct MatchOptions {
+    /// Require exact count match instead of at least N copies
+    exact: bool,
+    /// Require at most N copies (but at least one) instead of at least N
+    at_most: bool,
+    /// Count sideboard copies towards the total
+    include_sideboard: bool,
+    /// Match if any criterion matches (OR) instead of all of them (AND)
+    match_any: bool,
+    /// Skip decks without a source URL
+    require_url: bool,
+    /// Match card names containing the criterion instead of equal to it
+    fuzzy: bool,
+    /// Compare names without accents and punctuation
+    loose: bool,
+    /// Skip decks without a placement at or above this one (1 is best)
+    max_placement: Option<u32>,
+    /// Match against the command zone only, ignoring both boards
+    commander_only: bool,
 }
 
 // Scryfall API types
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug)]
 struct ScryfallBulkDataEntry {
     #[serde(rename = "type")]
     data_type: String,
@@ -121,31 +839,100 @@ struct ScryfallBulkDataResponse {
 #[derive(Deserialize)]
 struct ScryfallCardFace {
     name: String,
+    printed_name: Option<String>,
+    colors: Option<Vec<String>>,
+    type_line: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ScryfallPrices {
+    usd: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct ScryfallCard {
-    #[allow(dead_code)]
     name: Option<String>,
+    printed_name: Option<String>,
     layout: Option<String>,
     card_faces: Option<Vec<ScryfallCardFace>>,
+    colors: Option<Vec<String>>,
+    color_identity: Option<Vec<String>>,
+    rarity: Option<String>,
+    type_line: Option<String>,
+    cmc: Option<f64>,
+    prices: Option<ScryfallPrices>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 struct Tournament {
     format: Option<String>,
     #[serde(default)]
     name: Option<String>,
     #[serde(default)]
     date: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_rounds")]
+    rounds: Option<u32>,
 }
 
+/// A card entry; some scrapers write `{"quantity": 4, "card": "..."}`
 #[derive(Deserialize, Serialize, Clone)]
 struct Card {
+    #[serde(alias = "quantity", deserialize_with = "deserialize_count")]
     count: u32,
+    #[serde(alias = "card")]
     name: String,
 }
 
+/// Deserialize a card count written either as a number or a numeric string
+fn deserialize_count<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => n
+            .as_u64()
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or_else(|| D::Error::custom(format!("invalid card count {}", n))),
+        serde_json::Value::String(s) => s
+            .trim()
+            .parse()
+            .map_err(|_| D::Error::custom(format!("invalid card count {:?}", s))),
+        other => Err(D::Error::custom(format!("expected card count, got {:?}", other))),
+    }
+}
+
+/// Deserialize a Swiss round count. Some dumps write `rounds` as an array of
+/// per-round results instead, which includes playoff rounds, so anything but
+/// a number reads as unknown.
+fn deserialize_rounds<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<serde_json::Value> = Option::deserialize(deserializer)?;
+    Ok(value.as_ref().and_then(round_count))
+}
+
+fn round_count(value: &serde_json::Value) -> Option<u32> {
+    value.as_u64().and_then(|n| u32::try_from(n).ok())
+}
+
+/// The tournament of a file with its fields at the top level. Top-level keys
+/// are only consulted without a nested tournament, and values of the wrong
+/// type read as missing.
+fn top_level_tournament(fields: &serde_json::Map<String, serde_json::Value>) -> Tournament {
+    let string = |key: &str| fields.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+    Tournament {
+        format: string("format"),
+        name: string("name"),
+        date: string("date"),
+        rounds: fields.get("rounds").and_then(round_count),
+    }
+}
+
+/// Top-level keys that may hold tournament fields
+const TOP_LEVEL_TOURNAMENT_KEYS: [&str; 4] = ["format", "name", "date", "rounds"];
+
 /// Deserialize a value that can be either a string or an integer into Option<String>
 fn deserialize_string_or_int<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
@@ -169,1103 +956,9578 @@ struct Deck {
     result: Option<String>,
     #[serde(default, alias = "anchor_uri")]
     url: Option<String>,
+    #[serde(alias = "cards")]
     mainboard: Option<Vec<Card>>,
     sideboard: Option<Vec<Card>>,
+    /// Command zone of Commander decks: the general, or a partner pair
+    #[serde(default, alias = "commanders")]
+    commander: Option<Vec<Card>>,
+    /// Matches won, if the source records them
+    #[serde(default, alias = "match_wins")]
+    wins: Option<u32>,
+    /// Match record like "5-2" or "5-2-1", wins first
+    #[serde(default, alias = "record")]
+    match_record: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct DecklistFile {
-    tournament: Tournament,
-    decks: Option<Vec<Deck>>,
+impl Deck {
+    /// Multiplier for --win-weight: wins + 1, from `wins` or else the match
+    /// record, and 1 when neither is known
+    fn win_weight(&self) -> u32 {
+        let record_wins = || {
+            let record = self.match_record.as_deref()?;
+            record.split(['-', '/']).next()?.trim().parse().ok()
+        };
+        self.wins.or_else(record_wins).map_or(1, |wins| wins + 1)
+    }
 }
 
-/// A matching deck with tournament context
-#[derive(Serialize)]
-struct DeckMatch {
-    tournament: Tournament,
-    file_date: String,
+/// A deck read without its sideboard, which serde skips over without
+/// allocating; see --skip-sideboard
+#[derive(Deserialize)]
+struct MainboardDeck {
+    #[serde(default)]
     player: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_string_or_int")]
     result: Option<String>,
+    #[serde(default, alias = "anchor_uri")]
     url: Option<String>,
-    mainboard: Vec<Card>,
-    sideboard: Vec<Card>,
-    matched_cards: Vec<CardMatchInfo>,
+    #[serde(alias = "cards")]
+    mainboard: Option<Vec<Card>>,
+    #[serde(default, alias = "commanders")]
+    commander: Option<Vec<Card>>,
+    #[serde(default, alias = "match_wins")]
+    wins: Option<u32>,
+    #[serde(default, alias = "record")]
+    match_record: Option<String>,
 }
 
-/// Info about a matched card criterion
-#[derive(Serialize)]
-struct CardMatchInfo {
-    name: String,
-    requested: Option<u32>,
-    found_main: u32,
-    found_side: u32,
+impl From<MainboardDeck> for Deck {
+    fn from(deck: MainboardDeck) -> Deck {
+        Deck {
+            player: deck.player,
+            result: deck.result,
+            url: deck.url,
+            mainboard: deck.mainboard,
+            sideboard: None,
+            commander: deck.commander,
+            wins: deck.wins,
+            match_record: deck.match_record,
+        }
+    }
 }
 
-// Regex for extracting date from path
-fn date_regex() -> &'static Regex {
-    static RE: OnceLock<Regex> = OnceLock::new();
-    RE.get_or_init(|| Regex::new(r"/(\d{4})/(\d{2})/(\d{2})/").unwrap())
+#[derive(Deserialize)]
+#[serde(from = "AnyDecklistFile")]
+struct DecklistFile {
+    tournament: Tournament,
+    decks: Option<Vec<Deck>>,
 }
 
-fn days_since_epoch(year: i64, month: i64, day: i64) -> i64 {
-    // Approximate days since epoch
-    (year - 1970) * 365 + (year - 1969) / 4 + (month - 1) * 30 + day
+/// A decklist file as written: the tournament may be nested under
+/// `tournament` or `event`, or its fields may sit at the top level
+#[derive(Deserialize)]
+struct RawDecklistFile {
+    #[serde(default, alias = "event")]
+    tournament: Option<Tournament>,
+    #[serde(flatten)]
+    top_level: serde_json::Map<String, serde_json::Value>,
+    decks: Option<Vec<Deck>>,
 }
 
-fn today_days() -> i64 {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
-    now / 86400
+/// A decklist file object, or a bare array of decks with no tournament
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyDecklistFile {
+    Object(RawDecklistFile),
+    Bare(Vec<Deck>),
 }
 
-fn extract_date_from_path(path: &str) -> Option<(i64, i64, i64)> {
-    let caps = date_regex().captures(path)?;
-    let year: i64 = caps.get(1)?.as_str().parse().ok()?;
-    let month: i64 = caps.get(2)?.as_str().parse().ok()?;
-    let day: i64 = caps.get(3)?.as_str().parse().ok()?;
-    Some((year, month, day))
+impl From<AnyDecklistFile> for DecklistFile {
+    fn from(raw: AnyDecklistFile) -> DecklistFile {
+        match raw {
+            AnyDecklistFile::Object(raw) => DecklistFile {
+                tournament: raw.tournament.unwrap_or_else(|| top_level_tournament(&raw.top_level)),
+                decks: raw.decks,
+            },
+            AnyDecklistFile::Bare(decks) => DecklistFile {
+                tournament: Tournament::default(),
+                decks: Some(decks),
+            },
+        }
+    }
 }
 
-/// Fetch or update the data repository using sparse checkout
-fn fetch_data_repo(data_dir: &str, repo_url: &str) -> Result<(), String> {
-    let data_path = Path::new(data_dir);
+/// Just the tournament of a decklist file, for --dry-run; the decks are
+/// skipped without being built
+#[derive(Deserialize)]
+struct TournamentHeader {
+    #[serde(default, alias = "event")]
+    tournament: Option<Tournament>,
+    #[serde(default)]
+    format: Option<serde_json::Value>,
+    #[serde(default)]
+    name: Option<serde_json::Value>,
+    #[serde(default)]
+    date: Option<serde_json::Value>,
+    #[serde(default)]
+    rounds: Option<serde_json::Value>,
+}
 
-    if data_path.join(".git").exists() {
-        // Repository exists, update it
-        eprintln!("Updating data repository in {}...", data_dir);
-        let output = Command::new("git")
-            .args(["pull", "--ff-only"])
-            .current_dir(data_dir)
-            .stdout(std::process::Stdio::null())
-            .status()
-            .map_err(|e| format!("Failed to run git pull: {}", e))?;
+/// Scryfall bulk data type, for --scryfall-bulk
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+enum ScryfallBulk {
+    /// One card per Oracle name
+    #[default]
+    #[value(name = "oracle_cards")]
+    OracleCards,
+    /// One card per unique artwork
+    #[value(name = "unique_artwork")]
+    UniqueArtwork,
+    /// Every English or only-language printing
+    #[value(name = "default_cards")]
+    DefaultCards,
+    /// Every printing in every language
+    #[value(name = "all_cards")]
+    AllCards,
+}
 
-        if !output.success() {
-            return Err("git pull failed".to_string());
+impl ScryfallBulk {
+    /// The `type` of this bulk in the Scryfall bulk data index
+    fn data_type(self) -> &'static str {
+        match self {
+            ScryfallBulk::OracleCards => "oracle_cards",
+            ScryfallBulk::UniqueArtwork => "unique_artwork",
+            ScryfallBulk::DefaultCards => "default_cards",
+            ScryfallBulk::AllCards => "all_cards",
         }
-    } else {
-        // Shallow clone (only recent history)
-        eprintln!("Cloning data repository to {}...", data_dir);
+    }
+}
 
-        // Create parent directory if needed
-        if let Some(parent) = data_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| format!("Failed to create directory: {}", e))?;
-        }
+/// How a tournament's weight falls off with age, for --weight-function
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+enum WeightFunction {
+    /// Halve the weight every --half-life days
+    #[default]
+    Exp,
+    /// Fall linearly from 1 today to 0 at --window days
+    Linear,
+    /// 1 within --window days, 0 beyond
+    Step,
+}
 
-        let status = Command::new("git")
-            .args(["clone", "--depth=1", repo_url, data_dir])
-            .status()
-            .map_err(|e| format!("Failed to run git clone: {}", e))?;
+/// Part of each deck counted by top-cards with --board
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+enum Board {
+    /// Mainboard, command zone and sideboard
+    #[default]
+    Both,
+    /// Mainboard and command zone
+    Main,
+    /// Sideboard only
+    Side,
+}
 
-        if !status.success() {
-            return Err("git clone failed".to_string());
-        }
-    }
+/// Period grouped into one row by coverage --bucket
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+enum Bucket {
+    /// One row per date, e.g. 2025-03-14
+    #[default]
+    Day,
+    /// One row per ISO week, labelled by its Monday
+    Week,
+    /// One row per calendar month, e.g. 2025-03
+    Month,
+}
 
-    eprintln!("Data repository ready.");
-    Ok(())
+/// What a deck contributes to a card's count with --count-mode
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+enum CountMode {
+    /// Copies played, weighted by time
+    #[default]
+    Copies,
+    /// 1 per deck running the card, without time weighting
+    Decks,
+    /// 1 per deck running the card, weighted by time
+    Presence,
 }
 
-/// Get path to Scryfall bulk data cache file
-fn scryfall_cache_path() -> std::path::PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join(".scryfall")
-        .join("oracle-cards.json")
+/// Order of the top-cards output with --sort
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum SortOrder {
+    /// Heaviest first
+    WeightDesc,
+    /// Lightest first
+    WeightAsc,
+    /// Alphabetical, ignoring case
+    Name,
 }
 
-/// Check if cache file exists and is fresh enough
-fn is_cache_fresh(path: &Path) -> bool {
-    if let Ok(metadata) = std::fs::metadata(path) {
-        if let Ok(modified) = metadata.modified() {
-            if let Ok(age) = SystemTime::now().duration_since(modified) {
-                return age.as_secs() < SCRYFALL_CACHE_MAX_AGE_SECS;
-            }
-        }
-    }
-    false
+/// Handling of files whose path date lies in the future (usually a typo)
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum FutureDates {
+    /// Leave the file out
+    #[default]
+    Skip,
+    /// Treat the file as dated today, so its weight is 1
+    Clamp,
+    /// Use the negative age as is, weighting the file above 1
+    Allow,
 }
 
-/// Fetch Scryfall bulk data and cache it locally
-fn fetch_scryfall_bulk_data(cache_path: &Path) -> Result<(), String> {
-    eprintln!("Fetching Scryfall bulk data index...");
-
-    // Get the download URL for oracle_cards
-    let bulk_response: ScryfallBulkDataResponse = ureq::get(SCRYFALL_BULK_API)
-        .call()
-        .map_err(|e| format!("Failed to fetch bulk data index: {}", e))?
-        .into_json()
-        .map_err(|e| format!("Failed to parse bulk data index: {}", e))?;
-
-    let oracle_entry = bulk_response
-        .data
-        .iter()
-        .find(|e| e.data_type == "oracle_cards")
-        .ok_or("No oracle_cards entry in bulk data")?;
-
-    eprintln!("Downloading oracle cards (~150MB)...");
+/// Handling of decks with cards missing from Scryfall, for --colors
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum UnknownColors {
+    /// Leave the deck out
+    #[default]
+    Skip,
+    /// Judge the deck by its known cards alone
+    Ignore,
+}
 
-    // Download the bulk data
-    let response = ureq::get(&oracle_entry.download_uri)
-        .call()
-        .map_err(|e| format!("Failed to download bulk data: {}", e))?;
+/// Deck color identity filter for --colors
+#[derive(Debug, Clone)]
+struct ColorFilter {
+    /// Allowed colors as a WUBRG bit mask (see `parse_color_mask`)
+    colors: u8,
+    /// Require exactly these colors rather than a subset
+    exact: bool,
+    unknown: UnknownColors,
+    /// Color identity mask of each card name
+    identities: Arc<HashMap<String, u8>>,
+}
 
-    // Create cache directory
-    if let Some(parent) = cache_path.parent() {
-        std::fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+impl ColorFilter {
+    /// Whether the union of the deck's card identities passes the filter
+    fn deck_ok(&self, deck: &Deck) -> bool {
+        let mut identity = 0;
+        let zones = deck.mainboard.iter().chain(&deck.sideboard).chain(&deck.commander);
+        for card in zones.flatten() {
+            match self.identities.get(&card.name) {
+                Some(mask) => identity |= mask,
+                None if self.unknown == UnknownColors::Ignore => {}
+                None => return false,
+            }
+        }
+        if self.exact {
+            identity == self.colors
+        } else {
+            identity & !self.colors == 0
+        }
     }
-
-    // Write to cache file
-    let mut file = File::create(cache_path)
-        .map_err(|e| format!("Failed to create cache file: {}", e))?;
-    std::io::copy(&mut response.into_reader(), &mut file)
-        .map_err(|e| format!("Failed to write cache file: {}", e))?;
-
-    eprintln!("Scryfall data cached at {}", cache_path.display());
-    Ok(())
 }
 
-/// Build a map of front face name -> back face name from Scryfall bulk data.
-fn load_back_faces_from_cache(cache_path: &Path) -> HashMap<String, String> {
-    let mut back_faces = HashMap::new();
-    let layouts_with_back_faces: HashSet<&str> =
-        ["transform", "modal_dfc", "reversible_card"].into_iter().collect();
-
-    let file = match File::open(cache_path) {
-        Ok(f) => f,
-        Err(_) => return back_faces,
-    };
-    let reader = BufReader::new(file);
-
-    // Parse as array of cards
-    let cards: Vec<ScryfallCard> = match serde_json::from_reader(reader) {
-        Ok(c) => c,
-        Err(_) => return back_faces,
-    };
+/// Which files and events are in scope, shared by all commands
+#[derive(Debug, Clone)]
+struct ScopeFilter {
+    format_patterns: Vec<String>,
+    /// Match formats by whole name rather than substring
+    exact_format: bool,
+    /// Take a missing format from the file's directory
+    infer_format: bool,
+    /// Today as days since the epoch
+    today: i64,
+    /// Maximum file age in days
+    max_age: i64,
+    /// Skip events with fewer Swiss rounds than this
+    min_rounds: Option<u32>,
+    /// Skip decks with fewer mainboard cards than this
+    min_deck_size: Option<u32>,
+    /// Only include decks piloted by these players (case-insensitive); empty means all
+    players: Vec<String>,
+    /// Handling of future-dated files
+    future_dates: FutureDates,
+    /// Only include decks within these colors
+    colors: Option<ColorFilter>,
+}
 
-    for card in cards {
-        let layout = match &card.layout {
-            Some(l) => l.as_str(),
-            None => continue,
-        };
+impl ScopeFilter {
+    fn from_args(args: &Args) -> ScopeFilter {
+        ScopeFilter {
+            format_patterns: parse_format_patterns(&args.formats),
+            exact_format: args.exact_format,
+            infer_format: args.infer_format,
+            today: today_days(),
+            max_age: args.max_age,
+            min_rounds: args.min_rounds,
+            min_deck_size: args.min_deck_size,
+            players: args.players.clone(),
+            future_dates: args.future_dates,
+            colors: args.colors.map(|colors| ColorFilter {
+                colors,
+                exact: args.colors_exact,
+                unknown: args.unknown_colors,
+                identities: Arc::clone(&args.color_identities),
+            }),
+        }
+    }
 
-        if layouts_with_back_faces.contains(layout) {
-            if let Some(faces) = card.card_faces {
-                if faces.len() >= 2 {
-                    back_faces.insert(faces[0].name.clone(), faces[1].name.clone());
-                }
-            }
+    /// Whether a tournament format matches one requested pattern
+    fn pattern_matches(&self, format: &str, pattern: &str) -> bool {
+        let format = format.trim().to_lowercase();
+        let pattern = pattern.trim().to_lowercase();
+        if self.exact_format {
+            format == pattern
+        } else {
+            format.contains(&pattern)
         }
     }
 
-    back_faces
-}
+    /// Whether a tournament format matches any requested pattern
+    fn format_ok(&self, format: &str) -> bool {
+        self.format_patterns.iter().any(|p| self.pattern_matches(format, p))
+    }
 
-/// Get back faces map, fetching bulk data if needed.
-fn resolve_back_faces() -> HashMap<String, String> {
-    let cache_path = scryfall_cache_path();
+    /// Whether any filter applies to individual decks rather than whole files
+    fn filters_decks(&self) -> bool {
+        !self.players.is_empty() || self.min_deck_size.is_some() || self.colors.is_some()
+    }
 
-    if !is_cache_fresh(&cache_path) {
-        if let Err(e) = fetch_scryfall_bulk_data(&cache_path) {
-            eprintln!("Warning: Failed to fetch Scryfall data: {}", e);
-            // Try to use stale cache if it exists
-            if !cache_path.exists() {
-                return HashMap::new();
+    /// Whether a deck passes the per-deck filters
+    fn deck_ok(&self, deck: &Deck) -> bool {
+        if !self.players.is_empty() {
+            let player = match &deck.player {
+                Some(p) => p.trim().to_lowercase(),
+                None => return false,
+            };
+            if !self.players.iter().any(|p| p.trim().to_lowercase() == player) {
+                return false;
+            }
+        }
+        if let Some(min) = self.min_deck_size {
+            if deck.mainboard.iter().flatten().map(|c| c.count).sum::<u32>() < min {
+                return false;
+            }
+        }
+        if let Some(colors) = &self.colors {
+            if !colors.deck_ok(deck) {
+                return false;
             }
-            eprintln!("Using stale cache...");
         }
+        true
     }
 
-    load_back_faces_from_cache(&cache_path)
+    /// Whether an event is large enough for --min-rounds. Events without a
+    /// round count are assumed to have run ceil(log2(decks)) Swiss rounds.
+    fn rounds_ok(&self, rounds: Option<u32>, num_decks: u64) -> bool {
+        match self.min_rounds {
+            Some(min) => rounds.unwrap_or_else(|| inferred_rounds(num_decks)) >= min,
+            None => true,
+        }
+    }
 }
 
-/// Parse card criterion from string like "4 Lightning Bolt" or "Lightning Bolt"
-fn parse_card_criterion(input: &str) -> CardCriterion {
-    let input = input.trim();
+/// Swiss rounds needed to find a single undefeated player among `num_decks`
+fn inferred_rounds(num_decks: u64) -> u32 {
+    if num_decks <= 1 {
+        0
+    } else {
+        64 - (num_decks - 1).leading_zeros()
+    }
+}
 
-    // Try to parse leading number
-    let mut chars = input.chars().peekable();
-    let mut num_str = String::new();
+/// Settings controlling how card counts are aggregated by `process_file`
+#[derive(Debug, Clone)]
+struct ProcessOptions {
+    /// Half-life in days for time decay
+    half_life: f64,
+    /// Apply time-based weighting
+    use_weight: bool,
+    /// Shape of the time decay
+    weight_function: WeightFunction,
+    /// Age in days where linear and step weights reach 0
+    window: f64,
+    /// Count each deck running a card once instead of summing copies
+    presence: bool,
+    /// Fetch cards for decks that only have a URL
+    url_resolver: Option<Arc<UrlResolver>>,
+    /// Count repeated decks within a file only once
+    dedupe: bool,
+    /// Drop cards whose trimmed name is shorter than this
+    min_name_length: usize,
+    /// Count mainboards only, skipping sideboards while parsing
+    skip_sideboard: bool,
+    /// Count sideboards only
+    skip_mainboard: bool,
+    /// Count only this many of the best placed decks per file
+    max_decks: Option<usize>,
+    /// Skip decks without a source URL
+    require_url: bool,
+    /// Count each deck `Deck::win_weight` times
+    win_weight: bool,
+    /// Rename cards within each deck before counting
+    renames: Option<Arc<CardRenames>>,
+    /// Also keep card counts per format
+    by_format: bool,
+    /// Count decks rather than copies in the per-format counts, whatever `presence` is
+    format_presence: bool,
+    /// Also keep unweighted card counts
+    track_raw: bool,
+    /// Also keep the first and last file date of each card
+    track_dates: bool,
+    /// Also keep the weighted number of decks running each card
+    track_presence: bool,
+    /// Scale each event by min(decks, cap) / cap, if set
+    size_cap: Option<u64>,
+}
 
-    while let Some(&c) = chars.peek() {
-        if c.is_ascii_digit() {
-            num_str.push(c);
-            chars.next();
-        } else {
-            break;
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions {
+            half_life: 45.0,
+            use_weight: true,
+            weight_function: WeightFunction::Exp,
+            window: 90.0,
+            presence: false,
+            url_resolver: None,
+            dedupe: false,
+            min_name_length: 2,
+            skip_sideboard: false,
+            skip_mainboard: false,
+            max_decks: None,
+            require_url: false,
+            win_weight: false,
+            renames: None,
+            by_format: false,
+            format_presence: false,
+            track_raw: false,
+            track_dates: false,
+            track_presence: false,
+            size_cap: None,
         }
     }
+}
 
-    if !num_str.is_empty() {
-        // Skip whitespace after number
-        while let Some(&c) = chars.peek() {
-            if c.is_whitespace() {
-                chars.next();
-            } else {
-                break;
-            }
+impl ProcessOptions {
+    /// Time decay weight of a tournament `age` days old
+    fn weight(&self, age: i64) -> f64 {
+        if !self.use_weight {
+            return 1.0;
         }
-
-        let name: String = chars.collect();
-        if !name.is_empty() {
-            return CardCriterion {
-                name,
-                count: num_str.parse().ok(),
-            };
+        match self.weight_function {
+            WeightFunction::Exp => compute_weight(age, self.half_life, true),
+            WeightFunction::Linear => (1.0 - age as f64 / self.window).max(0.0),
+            WeightFunction::Step => {
+                if (age as f64) < self.window {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
         }
     }
 
-    // No leading number, entire input is card name
-    CardCriterion {
-        name: input.to_string(),
-        count: None,
+    /// Adjust presence and time weighting for a --count-mode
+    fn with_count_mode(self, mode: CountMode) -> ProcessOptions {
+        ProcessOptions {
+            presence: self.presence || mode != CountMode::Copies,
+            use_weight: self.use_weight && mode != CountMode::Decks,
+            ..self
+        }
     }
 }
 
-/// Check if a deck matches all card criteria
-fn deck_matches_criteria(
-    deck: &Deck,
-    criteria: &[CardCriterion],
-    exact: bool,
-    include_sideboard: bool,
-) -> Option<Vec<CardMatchInfo>> {
-    let mut match_info = Vec::new();
+/// Number of decks whose mainboard size is within or outside the expected range
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+struct SizeTally {
+    within: u64,
+    outside: u64,
+}
 
-    // Build card count maps for the deck
-    let mut main_counts: HashMap<String, u32> = HashMap::new();
-    let mut side_counts: HashMap<String, u32> = HashMap::new();
+/// Weighted card counts together with the weighted number of decks they came from
+#[derive(Debug, Default)]
+struct CardCounts {
+    cards: HashMap<String, f64>,
+    decks_by_format: HashMap<String, f64>,
+    deck_sizes: HashMap<String, SizeTally>,
+    /// Card entries dropped for having a junk name
+    dropped_names: u64,
+    /// Unweighted card counts, only tracked with `ProcessOptions::track_raw`
+    raw_cards: HashMap<String, f64>,
+    /// Weighted number of decks running each card, only tracked with
+    /// `ProcessOptions::track_presence`
+    presence_cards: HashMap<String, f64>,
+    /// First and last file date (YYYY-MM-DD) of each card, only tracked with
+    /// `ProcessOptions::track_dates`
+    card_dates: HashMap<String, (String, String)>,
+    /// Weighted card counts per format, only tracked with `ProcessOptions::by_format`
+    cards_by_format: HashMap<String, HashMap<String, f64>>,
+    /// Unweighted number of decks that passed all filters
+    decks: u64,
+    /// Number of files contributing at least one deck
+    files: u64,
+}
 
-    if let Some(mainboard) = &deck.mainboard {
-        for card in mainboard {
-            *main_counts.entry(card.name.to_lowercase()).or_insert(0) += card.count;
+impl CardCounts {
+    fn merge(mut self, mut other: CardCounts) -> CardCounts {
+        // Every field adds up, so walk the smaller side's maps into the larger
+        if other.cards.len() > self.cards.len() {
+            std::mem::swap(&mut self, &mut other);
         }
-    }
-
-    if let Some(sideboard) = &deck.sideboard {
-        for card in sideboard {
-            *side_counts.entry(card.name.to_lowercase()).or_insert(0) += card.count;
+        for (card, count) in other.cards {
+            *self.cards.entry(card).or_insert(0.0) += count;
+        }
+        for (format, count) in other.decks_by_format {
+            *self.decks_by_format.entry(format).or_insert(0.0) += count;
+        }
+        for (format, tally) in other.deck_sizes {
+            let entry = self.deck_sizes.entry(format).or_default();
+            entry.within += tally.within;
+            entry.outside += tally.outside;
         }
+        self.dropped_names += other.dropped_names;
+        self.decks += other.decks;
+        self.files += other.files;
+        for (card, count) in other.raw_cards {
+            *self.raw_cards.entry(card).or_insert(0.0) += count;
+        }
+        for (card, count) in other.presence_cards {
+            *self.presence_cards.entry(card).or_insert(0.0) += count;
+        }
+        for (card, dates) in other.card_dates {
+            widen_dates(&mut self.card_dates, card, dates);
+        }
+        for (format, cards) in other.cards_by_format {
+            let entry = self.cards_by_format.entry(format).or_default();
+            for (card, count) in cards {
+                *entry.entry(card).or_insert(0.0) += count;
+            }
+        }
+        self
     }
 
-    // Check each criterion
-    for criterion in criteria {
-        let name_lower = criterion.name.to_lowercase();
-        let found_main = main_counts.get(&name_lower).copied().unwrap_or(0);
-        let found_side = side_counts.get(&name_lower).copied().unwrap_or(0);
+    fn total_decks(&self) -> f64 {
+        self.decks_by_format.values().sum()
+    }
 
-        let total = if include_sideboard {
-            found_main + found_side
-        } else {
-            found_main
-        };
+    /// Multiply all weighted counts by `factor`, leaving raw counts alone
+    fn scale(mut self, factor: f64) -> CardCounts {
+        for count in self.cards.values_mut() {
+            *count *= factor;
+        }
+        for count in self.decks_by_format.values_mut() {
+            *count *= factor;
+        }
+        for count in self.presence_cards.values_mut() {
+            *count *= factor;
+        }
+        for count in self.cards_by_format.values_mut().flat_map(|c| c.values_mut()) {
+            *count *= factor;
+        }
+        self
+    }
+}
 
-        let matches = match criterion.count {
-            Some(required) => {
-                if exact {
-                    total == required
-                } else {
-                    total >= required
-                }
+/// Widen a card's first/last seen dates to include `dates`
+fn widen_dates(card_dates: &mut HashMap<String, (String, String)>, card: String, dates: (String, String)) {
+    match card_dates.entry(card) {
+        std::collections::hash_map::Entry::Occupied(mut entry) => {
+            let (first, last) = entry.get_mut();
+            if dates.0 < *first {
+                *first = dates.0;
+            }
+            if dates.1 > *last {
+                *last = dates.1;
             }
-            None => total > 0,
-        };
-
-        if !matches {
-            return None;
         }
-
-        match_info.push(CardMatchInfo {
-            name: criterion.name.clone(),
-            requested: criterion.count,
-            found_main,
-            found_side,
-        });
+        std::collections::hash_map::Entry::Vacant(entry) => {
+            entry.insert(dates);
+        }
     }
-
-    Some(match_info)
 }
 
-/// Search a single file for matching decks
-fn search_file_for_decks(
-    path: &Path,
-    format_patterns: &[String],
-    today: i64,
-    max_age: i64,
-    criteria: &[CardCriterion],
-    exact: bool,
-    include_sideboard: bool,
-) -> Vec<DeckMatch> {
-    let mut matches = Vec::new();
-    let path_str = path.to_string_lossy();
+/// A single deck's cards (mainboard and sideboard merged) with its time weight
+struct WeightedDeck {
+    /// Time and event size weight, times the win weight with --win-weight
+    weight: f64,
+    /// Win weight of the deck, already part of `weight`
+    multiplier: u32,
+    cards: Vec<(String, u32)>,
+    format: String,
+    /// `list_fingerprint` of the deck, for --unique-decks
+    fingerprint: u64,
+    /// Whether the mainboard size is within tolerance for the format
+    within_size: bool,
+    file: Arc<Path>,
+    date: String,
+}
 
-    // Extract date from path
-    let (year, month, day) = match extract_date_from_path(&path_str) {
-        Some(d) => d,
-        None => return matches,
-    };
+/// Spread of a card's rank across bootstrap resamples, or of its deck share
+/// across formats
+#[derive(Debug)]
+struct RankStats {
+    mean: f64,
+    stddev: f64,
+}
 
-    let file_days = days_since_epoch(year, month, day);
-    let age = today - file_days;
+/// Raw (unweighted) card counts of a single file, as stored in the file cache
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+struct FileSummary {
+    format: Option<String>,
+    #[serde(default)]
+    rounds: Option<u32>,
+    /// Decks counted in this summary
+    decks: u64,
+    /// Sum of the decks' win weights, with --win-weight
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    win_weighted_decks: Option<u64>,
+    /// All decks in the event, including ones removed by deck filters
+    event_decks: u64,
+    /// Total copies of each card
+    copies: HashMap<String, u32>,
+    /// Number of decks running each card
+    presence: HashMap<String, u32>,
+    sizes: SizeTally,
+}
 
-    // Skip if too old
-    if age > max_age {
-        return matches;
-    }
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+    mtime: u64,
+    summary: FileSummary,
+}
 
-    let file_date = format!("{:04}-{:02}-{:02}", year, month, day);
+/// Version of the file cache format. Bump it whenever what a cached
+/// `FileSummary` holds changes, so caches from older builds are dropped.
+const FILE_CACHE_VERSION: u32 = 1;
 
-    // Parse JSON file
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(_) => return matches,
-    };
-    let reader = BufReader::new(file);
-    let data: DecklistFile = match serde_json::from_reader(reader) {
-        Ok(d) => d,
-        Err(_) => return matches,
-    };
+/// The file cache as stored on disk
+#[derive(Deserialize, Serialize)]
+struct CacheIndex {
+    version: u32,
+    entries: HashMap<String, CacheEntry>,
+}
 
-    // Check format
-    let format = match &data.tournament.format {
-        Some(f) => f.to_lowercase(),
-        None => return matches,
-    };
+/// On-disk cache of file summaries keyed by path and modification time.
+///
+/// Entries are read-only during processing; new summaries are collected
+/// separately and merged on `save`.
+struct FileCache {
+    path: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+    fresh: Mutex<Vec<(String, CacheEntry)>>,
+}
 
-    let format_matches = format_patterns
-        .iter()
-        .any(|p| format.contains(&p.to_lowercase()));
+impl FileCache {
+    /// Load the cache index, starting empty if it is missing, unreadable or
+    /// from another cache version
+    fn load(path: PathBuf) -> FileCache {
+        let entries = File::open(&path)
+            .ok()
+            .and_then(|f| serde_json::from_reader::<_, CacheIndex>(BufReader::new(f)).ok())
+            .filter(|index| index.version == FILE_CACHE_VERSION)
+            .map(|index| index.entries)
+            .unwrap_or_default();
+        FileCache {
+            path,
+            entries,
+            fresh: Mutex::new(Vec::new()),
+        }
+    }
 
-    if !format_matches {
-        return matches;
+    /// Summary of a file, if cached and unchanged since
+    fn get(&self, file: &Path) -> Option<&FileSummary> {
+        let entry = self.entries.get(&cache_key(file))?;
+        if Some(entry.mtime) == file_mtime(file) {
+            Some(&entry.summary)
+        } else {
+            None
+        }
     }
 
-    // Search each deck
-    if let Some(decks) = data.decks {
-        for deck in decks {
-            if let Some(matched_cards) = deck_matches_criteria(&deck, criteria, exact, include_sideboard) {
-                matches.push(DeckMatch {
-                    tournament: data.tournament.clone(),
-                    file_date: file_date.clone(),
-                    player: deck.player.clone(),
-                    result: deck.result.clone(),
-                    url: deck.url.clone(),
-                    mainboard: deck.mainboard.clone().unwrap_or_default(),
-                    sideboard: deck.sideboard.clone().unwrap_or_default(),
-                    matched_cards,
-                });
-            }
+    fn insert(&self, file: &Path, summary: FileSummary) {
+        if let Some(mtime) = file_mtime(file) {
+            self.fresh
+                .lock()
+                .unwrap()
+                .push((cache_key(file), CacheEntry { mtime, summary }));
         }
     }
 
-    matches
+    /// Write the index back to disk if anything new was cached
+    fn save(mut self) -> Result<(), String> {
+        let fresh = self.fresh.into_inner().unwrap();
+        if fresh.is_empty() {
+            return Ok(());
+        }
+        self.entries.extend(fresh);
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+        }
+        let file = File::create(&self.path)
+            .map_err(|e| format!("Failed to create cache file: {}", e))?;
+        let index = CacheIndex {
+            version: FILE_CACHE_VERSION,
+            entries: self.entries,
+        };
+        serde_json::to_writer(BufWriter::new(file), &index)
+            .map_err(|e| format!("Failed to write cache file: {}", e))
+    }
 }
 
-fn process_file(
-    path: &Path,
-    format_patterns: &[String],
-    today: i64,
+/// Settings a persisted aggregate was counted with: the weighting, the
+/// file and deck filters and the counting options. Changing any of them
+/// invalidates it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct AggregateSettings {
     half_life: f64,
-    max_age: i64,
     use_weight: bool,
-) -> HashMap<String, f64> {
-    let mut cards: HashMap<String, f64> = HashMap::new();
+    presence: bool,
+    formats: Vec<String>,
+    exact_format: bool,
+    infer_format: bool,
+    max_age: i64,
+    min_rounds: Option<u32>,
+    min_deck_size: Option<u32>,
+    players: Vec<String>,
+    future_dates: FutureDates,
+    colors: Option<u8>,
+    colors_exact: bool,
+    unknown_colors: Option<UnknownColors>,
+    include_globs: Vec<String>,
+    exclude_globs: Vec<String>,
+    resolve_urls: bool,
+    dedupe: bool,
+    min_name_length: usize,
+    skip_sideboard: bool,
+    skip_mainboard: bool,
+    max_decks: Option<usize>,
+    require_url: bool,
+    win_weight: bool,
+    size_cap: Option<u64>,
+    canonicalize: bool,
+    fold_basics: bool,
+}
 
-    let path_str = path.to_string_lossy();
+impl AggregateSettings {
+    fn new(scope: &ScopeFilter, options: &ProcessOptions, include_globs: &[String], exclude_globs: &[String]) -> Self {
+        let mut players: Vec<String> = scope.players.iter().map(|p| p.trim().to_lowercase()).collect();
+        players.sort();
+        AggregateSettings {
+            half_life: options.half_life,
+            use_weight: options.use_weight,
+            presence: options.presence,
+            formats: scope.format_patterns.clone(),
+            exact_format: scope.exact_format,
+            infer_format: scope.infer_format,
+            max_age: scope.max_age,
+            min_rounds: scope.min_rounds,
+            min_deck_size: scope.min_deck_size,
+            players,
+            future_dates: scope.future_dates,
+            colors: scope.colors.as_ref().map(|c| c.colors),
+            colors_exact: scope.colors.as_ref().is_some_and(|c| c.exact),
+            unknown_colors: scope.colors.as_ref().map(|c| c.unknown),
+            include_globs: include_globs.to_vec(),
+            exclude_globs: exclude_globs.to_vec(),
+            resolve_urls: options.url_resolver.is_some(),
+            dedupe: options.dedupe,
+            min_name_length: options.min_name_length,
+            skip_sideboard: options.skip_sideboard,
+            skip_mainboard: options.skip_mainboard,
+            max_decks: options.max_decks,
+            require_url: options.require_url,
+            win_weight: options.win_weight,
+            size_cap: options.size_cap,
+            canonicalize: options.renames.as_ref().is_some_and(|r| !r.canonical.is_empty()),
+            fold_basics: options.renames.as_ref().is_some_and(|r| r.fold_basics),
+        }
+    }
+}
 
-    // Extract date from path
-    let (year, month, day) = match extract_date_from_path(&path_str) {
-        Some(d) => d,
-        None => return cards,
-    };
+/// Running totals kept between runs by --since-file
+#[derive(Debug, Deserialize, Serialize)]
+struct Aggregate {
+    settings: AggregateSettings,
+    /// Day (since the epoch) the weights are relative to
+    as_of: i64,
+    /// Newest file date included, as days since the epoch
+    marker: i64,
+    cards: HashMap<String, f64>,
+    decks_by_format: HashMap<String, f64>,
+    decks: u64,
+    files: u64,
+}
 
-    let file_days = days_since_epoch(year, month, day);
-    let age = today - file_days;
+impl Aggregate {
+    /// Load the aggregate at `path`, or None if it is missing, unreadable or
+    /// was built with different settings
+    fn load(path: &Path, settings: &AggregateSettings) -> Option<Aggregate> {
+        let file = File::open(path).ok()?;
+        let aggregate: Aggregate = match serde_json::from_reader(BufReader::new(file)) {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("Warning: Ignoring unreadable {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if aggregate.settings != *settings {
+            eprintln!("Warning: Settings changed since {} was written, starting over", path.display());
+            return None;
+        }
+        Some(aggregate)
+    }
 
-    // Skip if too old
-    if age > max_age {
-        return cards;
+    /// The stored counts, decayed from `as_of` to `today`
+    fn decayed_counts(&self, today: i64) -> CardCounts {
+        let weight = compute_weight(today - self.as_of, self.settings.half_life, self.settings.use_weight);
+        let counts = CardCounts {
+            cards: self.cards.clone(),
+            decks_by_format: self.decks_by_format.clone(),
+            decks: self.decks,
+            files: self.files,
+            ..Default::default()
+        };
+        counts.scale(weight)
     }
 
-    // Calculate weight
-    let weight = if use_weight {
-        2.0_f64.powf(-(age as f64) / half_life)
-    } else {
-        1.0
-    };
+    fn from_counts(counts: &CardCounts, settings: AggregateSettings, today: i64, marker: i64) -> Aggregate {
+        Aggregate {
+            settings,
+            as_of: today,
+            marker,
+            cards: counts.cards.clone(),
+            decks_by_format: counts.decks_by_format.clone(),
+            decks: counts.decks,
+            files: counts.files,
+        }
+    }
 
-    // Parse JSON file
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(_) => return cards,
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        serde_json::to_writer(BufWriter::new(file), self)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// Path date of a file as days since the epoch
+fn file_day(path: &Path) -> Option<i64> {
+    let (year, month, day) = extract_date_from_path(&path.to_string_lossy())?;
+    Some(days_since_epoch(year, month, day))
+}
+
+/// Files dated after `marker`, i.e. not yet folded into the aggregate
+fn files_after(files: Vec<PathBuf>, marker: i64) -> Vec<PathBuf> {
+    files
+        .into_iter()
+        .filter(|path| file_day(path).is_some_and(|day| day > marker))
+        .collect()
+}
+
+/// A matching deck with tournament context
+#[derive(Serialize)]
+struct DeckMatch {
+    tournament: Tournament,
+    file_date: String,
+    player: Option<String>,
+    result: Option<String>,
+    url: Option<String>,
+    mainboard: Vec<Card>,
+    sideboard: Vec<Card>,
+    commander: Vec<Card>,
+    matched_cards: Vec<CardMatchInfo>,
+    /// Label from --archetypes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archetype: Option<String>,
+}
+
+/// Info about a matched card criterion
+#[derive(Serialize)]
+struct CardMatchInfo {
+    name: String,
+    requested: Option<u32>,
+    found_main: u32,
+    found_side: u32,
+}
+
+// Regex for extracting date from path
+fn date_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"/(\d{4})/(\d{2})/(\d{2})/").unwrap())
+}
+
+/// Format named by the directory holding the dated directories of a file,
+/// e.g. "modern" for data/modern/2025/01/10/event.json
+fn infer_format_from_path(path: &Path) -> Option<String> {
+    let path_str = path.to_string_lossy();
+    let start = date_regex().find(&path_str)?.start();
+    let parent = path_str[..start].rsplit('/').next()?;
+    (!parent.is_empty() && parent != ".").then(|| parent.to_string())
+}
+
+fn days_since_epoch(year: i64, month: i64, day: i64) -> i64 {
+    // Approximate days since epoch
+    (year - 1970) * 365 + (year - 1969) / 4 + (month - 1) * 30 + day
+}
+
+/// Exact days since 1970-01-01 in the proleptic Gregorian calendar
+fn civil_to_days(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Inverse of `civil_to_days`
+fn days_to_civil(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Row label for a YYYY-MM-DD date under the given coverage bucket
+fn bucket_label(date: &str, bucket: Bucket) -> String {
+    match bucket {
+        Bucket::Day => date.to_string(),
+        Bucket::Week => {
+            let part = |range: std::ops::Range<usize>| date[range].parse::<i64>().unwrap_or(0);
+            let days = civil_to_days(part(0..4), part(5..7), part(8..10));
+            // 1970-01-01 was a Thursday, three days after a Monday
+            let monday = days - (days + 3).rem_euclid(7);
+            let (y, m, d) = days_to_civil(monday);
+            format!("{:04}-{:02}-{:02}", y, m, d)
+        }
+        Bucket::Month => date[..7].to_string(),
+    }
+}
+
+fn today_days() -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    now / 86400
+}
+
+/// Weight of a data point that is `age` days old
+fn compute_weight(age: i64, half_life: f64, use_weight: bool) -> f64 {
+    if use_weight {
+        2.0_f64.powf(-(age as f64) / half_life)
+    } else {
+        1.0
+    }
+}
+
+/// Event size factor for --size-weight: events with at least `cap` decks count
+/// fully, smaller ones proportionally less
+fn event_size_weight(num_decks: u64, size_cap: Option<u64>) -> f64 {
+    match size_cap {
+        Some(cap) if cap > 0 => num_decks.min(cap) as f64 / cap as f64,
+        _ => 1.0,
+    }
+}
+
+/// Weights applied at representative ages, for --debug-weights
+fn weight_table(options: &ProcessOptions) -> Vec<(i64, f64)> {
+    DEBUG_WEIGHT_AGES.iter().map(|&age| (age, options.weight(age))).collect()
+}
+
+fn extract_date_from_path(path: &str) -> Option<(i64, i64, i64)> {
+    let caps = date_regex().captures(path)?;
+    let year: i64 = caps.get(1)?.as_str().parse().ok()?;
+    let month: i64 = caps.get(2)?.as_str().parse().ok()?;
+    let day: i64 = caps.get(3)?.as_str().parse().ok()?;
+    Some((year, month, day))
+}
+
+/// Build the arguments for a shallow `git clone` of the data repository.
+/// Sparse clones skip the checkout so only the chosen subpath is written.
+fn git_clone_args(repo_url: &str, data_dir: &str, branch: Option<&str>, sparse: bool) -> Vec<String> {
+    let mut args = vec!["clone".to_string(), "--depth=1".to_string()];
+    if sparse {
+        args.push("--no-checkout".to_string());
+    }
+    if let Some(branch) = branch {
+        args.push("--branch".to_string());
+        args.push(branch.to_string());
+    }
+    args.push(repo_url.to_string());
+    args.push(data_dir.to_string());
+    args
+}
+
+/// Build the `git sparse-checkout` invocations limiting a fresh clone to `subpath`
+fn git_sparse_checkout_args(subpath: &str) -> Vec<Vec<String>> {
+    vec![
+        vec!["sparse-checkout".to_string(), "init".to_string(), "--cone".to_string()],
+        vec!["sparse-checkout".to_string(), "set".to_string(), subpath.to_string()],
+    ]
+}
+
+/// Runs git commands, so fetching can be exercised without a real git
+trait GitRunner {
+    /// Run `git <args>` in `cwd`, failing if git can't be run or exits non-zero
+    fn run(&self, args: &[&str], cwd: &Path) -> Result<(), String>;
+}
+
+/// Runs the `git` binary on PATH
+struct SystemGit;
+
+impl GitRunner for SystemGit {
+    fn run(&self, args: &[&str], cwd: &Path) -> Result<(), String> {
+        let subcommand = args.first().copied().unwrap_or_default();
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(cwd)
+            .stdout(std::process::Stdio::null())
+            .status()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => "git not found on PATH".to_string(),
+                _ => format!("Failed to run git {}: {}", subcommand, e),
+            })?;
+
+        if !status.success() {
+            return Err(format!("git {} failed", subcommand));
+        }
+        Ok(())
+    }
+}
+
+/// Run git with owned arguments, as built by `git_clone_args` and friends
+fn run_git(git: &dyn GitRunner, args: &[String], cwd: &Path) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    git.run(&args, cwd)
+}
+
+/// Check out a `--no-checkout` clone, limited to `subpath` if git supports
+/// sparse checkout and falling back to the full tree otherwise
+fn checkout_sparse(git: &dyn GitRunner, data_dir: &Path, subpath: &str) -> Result<(), String> {
+    let sparse_ok = git_sparse_checkout_args(subpath)
+        .iter()
+        .all(|args| run_git(git, args, data_dir).is_ok());
+    if !sparse_ok {
+        eprintln!("Warning: sparse checkout unavailable, checking out the full repository");
+        let _ = git.run(&["sparse-checkout", "disable"], data_dir);
+    }
+    git.run(&["checkout"], data_dir)
+}
+
+/// Whether the git repository at `data_path` was cloned from `repo_url`,
+/// judged by its origin in `.git/config`, which clone writes before fetching
+fn is_clone_of(data_path: &Path, repo_url: &str) -> bool {
+    let Ok(config) = std::fs::read_to_string(data_path.join(".git").join("config")) else {
+        return false;
     };
-    let reader = BufReader::new(file);
-    let data: DecklistFile = match serde_json::from_reader(reader) {
-        Ok(d) => d,
-        Err(_) => return cards,
+    config
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("url"))
+        .any(|rest| rest.trim_start().strip_prefix('=').map(str::trim) == Some(repo_url))
+}
+
+/// Fetch or update the data repository, optionally checking out only `sparse`
+/// on a fresh clone
+fn fetch_data_repo(
+    git: &dyn GitRunner,
+    data_dir: &str,
+    repo_url: &str,
+    branch: Option<&str>,
+    sparse: Option<&str>,
+) -> Result<(), String> {
+    let data_path = Path::new(data_dir);
+
+    // Fail clearly up front rather than with a confusing clone/pull error
+    git.run(&["--version"], Path::new("."))?;
+
+    if data_path.join(".git").exists() && git.run(&["rev-parse", "--verify", "HEAD"], data_path).is_err() {
+        // An interrupted clone leaves a .git without a HEAD commit, which
+        // can't be pulled. Start over, but only if the clone is ours.
+        if !is_clone_of(data_path, repo_url) {
+            return Err(format!(
+                "{} has an incomplete git repository not cloned from {}; remove it manually",
+                data_dir, repo_url
+            ));
+        }
+        eprintln!("Warning: {} holds an incomplete clone, cloning again", data_dir);
+        std::fs::remove_dir_all(data_path).map_err(|e| format!("Failed to remove {}: {}", data_dir, e))?;
+    }
+
+    if data_path.join(".git").exists() {
+        // Repository exists, update it
+        progress!("Updating data repository in {}...", data_dir);
+        if let Some(branch) = branch {
+            // The clone is shallow and single-branch, so other branches have
+            // to be fetched, and added to the fetched branches so later pulls
+            // can track them, before they can be checked out
+            let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch);
+            git.run(&["fetch", "--depth=1", "origin", &refspec], data_path)
+                .map_err(|e| format!("Failed to fetch branch '{}' of the data repository: {}", branch, e))?;
+            git.run(&["remote", "set-branches", "--add", "origin", branch], data_path)?;
+            git.run(&["checkout", "-B", branch, "--track", &format!("origin/{}", branch)], data_path)?;
+        } else {
+            git.run(&["pull", "--ff-only"], data_path)?;
+        }
+    } else {
+        // Shallow clone (only recent history)
+        progress!("Cloning data repository to {}...", data_dir);
+
+        // Create parent directory if needed
+        if let Some(parent) = data_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory: {}", e))?;
+        }
+
+        let clone_args = git_clone_args(repo_url, data_dir, branch, sparse.is_some());
+        run_git(git, &clone_args, Path::new(".")).map_err(|e| match branch {
+            Some(branch) => format!("{} (does branch '{}' exist?)", e, branch),
+            None => e,
+        })?;
+
+        if let Some(subpath) = sparse {
+            checkout_sparse(git, data_path, subpath)?;
+        }
+    }
+
+    progress!("Data repository ready.");
+    Ok(())
+}
+
+/// Check a fetched data repository: it must have a HEAD commit, pass
+/// `git fsck` and contain decklist files
+fn verify_data_repo(data_dir: &str) -> Result<String, String> {
+    let head = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(data_dir)
+        .output()
+        .map_err(|e| format!("Failed to run git rev-parse: {}", e))?;
+    let head = if head.status.success() {
+        String::from_utf8_lossy(&head.stdout).trim().to_string()
+    } else {
+        String::new()
     };
 
-    // Check format
-    let format = match &data.tournament.format {
-        Some(f) => f.to_lowercase(),
-        None => return cards,
+    let fsck = Command::new("git")
+        .args(["fsck", "--no-progress"])
+        .current_dir(data_dir)
+        .output()
+        .map_err(|e| format!("Failed to run git fsck: {}", e))?;
+    let fsck = if fsck.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&fsck.stderr).trim().to_string())
     };
 
-    let format_matches = format_patterns
-        .iter()
-        .any(|p| format.contains(&p.to_lowercase()));
+    check_data_repo(&head, fsck, collect_json_files(data_dir, None, None).len())
+}
 
-    if !format_matches {
-        return cards;
+/// Judge the results of a data repository check, returning a summary line
+fn check_data_repo(head: &str, fsck: Result<(), String>, json_files: usize) -> Result<String, String> {
+    if head.is_empty() {
+        return Err("data repository has no HEAD commit".to_string());
+    }
+    fsck.map_err(|e| format!("git fsck failed: {}", e))?;
+    if json_files == 0 {
+        return Err("data repository contains no JSON files".to_string());
     }
+    Ok(format!("{} JSON files at {}", json_files, head))
+}
 
-    // Process decks
-    if let Some(decks) = data.decks {
-        for deck in decks {
-            if let Some(mainboard) = deck.mainboard {
-                for card in mainboard {
-                    *cards.entry(card.name).or_insert(0.0) += card.count as f64 * weight;
-                }
-            }
-            if let Some(sideboard) = deck.sideboard {
-                for card in sideboard {
-                    *cards.entry(card.name).or_insert(0.0) += card.count as f64 * weight;
+/// Get path to the default config file
+fn config_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".config")
+        .join("mtg_top_cards")
+        .join("config.toml")
+}
+
+/// Load the config file. A missing default config is not an error, but a
+/// missing explicitly requested one is.
+fn load_config(explicit: Option<&str>) -> Result<Config, String> {
+    let path = match explicit {
+        Some(p) => PathBuf::from(p),
+        None => config_path(),
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if explicit.is_none() && e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Config::default())
+        }
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    toml::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+/// Build `Args` from parsed matches, filling every flag not given on the
+/// command line from the config
+fn args_with_config(matches: &ArgMatches, config: &Config) -> Result<Args, String> {
+    let mut args = Args::from_arg_matches(matches).map_err(|e| e.to_string())?;
+    let unset = |m: &ArgMatches, id: &str| m.value_source(id) != Some(ValueSource::CommandLine);
+
+    // Top-level top-cards flags only apply to a run without a subcommand
+    if let Some((name, _)) = matches.subcommand() {
+        if let Some(arg) = top_cards_flags().iter().find(|a| !unset(matches, a.get_id().as_str())) {
+            let flag = arg.get_long().map_or_else(|| arg.get_id().to_string(), |long| format!("--{}", long));
+            return Err(format!("{} is a top-cards option; pass it after the {} subcommand", flag, name));
+        }
+    }
+
+    macro_rules! fill {
+        ($m:expr, $target:expr, $id:literal, $value:expr) => {
+            if let Some(v) = &$value {
+                if unset($m, $id) {
+                    $target = v.clone();
                 }
             }
+        };
+    }
+
+    args.formats_set = !unset(matches, "formats") || config.formats.is_some();
+    fill!(matches, args.formats, "formats", config.formats);
+    fill!(matches, args.dir, "dir", config.dir.as_ref().map(|d| Some(d.clone())));
+    fill!(matches, args.max_age, "max_age", config.max_age);
+    fill!(matches, args.min_rounds, "min_rounds", config.min_rounds.map(Some));
+    fill!(matches, args.min_deck_size, "min_deck_size", config.min_deck_size.map(Some));
+    fill!(matches, args.players, "players", config.players);
+    fill!(matches, args.data_dir, "data_dir", config.data_dir);
+    fill!(matches, args.data_repo, "data_repo", config.data_repo);
+    fill!(matches, args.data_branch, "data_branch", config.data_branch.as_ref().map(|b| Some(b.clone())));
+
+    // Without a subcommand, the top-cards flags were parsed at the top level
+    let sub = matches.subcommand().map(|(_, m)| m);
+    if args.command.is_none() {
+        args.command = Some(Commands::TopCards(std::mem::replace(&mut args.top_cards, default_top_cards_args())));
+    }
+    match (&mut args.command, sub.or(Some(matches))) {
+        (Some(Commands::TopCards(a)), Some(m)) => {
+            let c = &config.top_cards;
+            a.num_set = !unset(m, "num") || c.num.is_some();
+            fill!(m, a.num, "num", c.num);
+            fill!(m, a.half_life, "half_life", c.half_life);
+            fill!(m, a.no_weight, "no_weight", c.no_weight);
         }
+        (Some(Commands::SearchDecks(a)), Some(m)) => {
+            fill!(m, a.num, "num", config.search_decks.num);
+        }
+        (Some(Commands::CoOccur(a)), Some(m)) => {
+            let c = &config.co_occur;
+            fill!(m, a.num, "num", c.num);
+            fill!(m, a.half_life, "half_life", c.half_life);
+            fill!(m, a.no_weight, "no_weight", c.no_weight);
+        }
+        (Some(Commands::ComparePlayers(a)), Some(m)) => {
+            let c = &config.compare_players;
+            fill!(m, a.num, "num", c.num);
+            fill!(m, a.half_life, "half_life", c.half_life);
+            fill!(m, a.no_weight, "no_weight", c.no_weight);
+        }
+        _ => {}
     }
 
-    cards
+    Ok(args)
 }
 
-/// Collect JSON files from a directory
-fn collect_json_files(search_dir: &str) -> Vec<std::path::PathBuf> {
-    WalkDir::new(search_dir)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.file_type().is_file()
-                && e.path().extension().map_or(false, |ext| ext == "json")
-        })
-        .map(|e| e.into_path())
-        .collect()
+/// The top-cards flags, which are also accepted before any subcommand
+fn top_cards_flags() -> Vec<clap::Arg> {
+    let command = <TopCardsArgs as clap::Args>::augment_args(clap::Command::new("top-cards"));
+    command.get_arguments().cloned().collect()
 }
 
-/// Run the top-cards command
-fn run_top_cards(args: &Args, top_args: &TopCardsArgs) {
-    let search_dir = args.dir.clone().unwrap_or_else(|| {
-        if args.fetch {
-            args.data_dir.clone()
-        } else {
-            ".".to_string()
+/// The command line parser, with the top-level copies of the top-cards flags
+/// left out of --help; `top-cards --help` lists them
+fn cli() -> clap::Command {
+    top_cards_flags()
+        .iter()
+        .fold(Args::command(), |command, arg| command.mut_arg(arg.get_id(), |a| a.hide(true)))
+}
+
+/// Arguments for top-cards with every flag at its default, as clap derives them
+fn default_top_cards_args() -> TopCardsArgs {
+    let command = <TopCardsArgs as clap::Args>::augment_args(clap::Command::new("top-cards"));
+    TopCardsArgs::from_arg_matches(&command.get_matches_from(["top-cards"])).expect("top-cards defaults parse")
+}
+
+/// Parse the command line and merge in the config file
+fn parse_args() -> Result<Args, String> {
+    let matches = cli().get_matches();
+    let explicit = matches.get_one::<String>("config").cloned();
+    let config = load_config(explicit.as_deref())?;
+    args_with_config(&matches, &config)
+}
+
+/// Get path to the cache file of a Scryfall bulk type, e.g. oracle-cards.json
+/// for oracle_cards
+fn scryfall_cache_path(bulk_type: ScryfallBulk) -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".scryfall")
+        .join(format!("{}.json", bulk_type.data_type().replace('_', "-")))
+}
+
+/// Get path to the processed-file cache index
+fn file_cache_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".mtg_top_cards")
+        .join("cache")
+        .join("index.json")
+}
+
+/// Cache key for a file, stable across working directories
+fn cache_key(path: &Path) -> String {
+    std::fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Modification time of a file in nanoseconds since the epoch
+fn file_mtime(path: &Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
+/// Check if cache file exists and is fresh enough
+fn is_cache_fresh(path: &Path) -> bool {
+    if let Ok(metadata) = std::fs::metadata(path) {
+        if let Ok(modified) = metadata.modified() {
+            if let Ok(age) = SystemTime::now().duration_since(modified) {
+                return age.as_secs() < SCRYFALL_CACHE_MAX_AGE_SECS;
+            }
         }
-    });
+    }
+    false
+}
 
-    let format_patterns: Vec<String> = args
-        .formats
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect();
-    let today = today_days();
-    let use_weight = !top_args.no_weight;
+/// HTTP agent for Scryfall requests, with timeouts so a stalled connection
+/// errors out instead of hanging forever
+fn scryfall_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(SCRYFALL_CONNECT_TIMEOUT)
+        .timeout_read(SCRYFALL_READ_TIMEOUT)
+        .build()
+}
+
+/// Whether a failed request is worth retrying: transport errors (timeouts,
+/// refused connections), rate limiting and server-side errors
+fn is_retryable(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(code, _) => *code == 429 || (500..600).contains(code),
+        ureq::Error::Transport(_) => true,
+    }
+}
 
-    let files = collect_json_files(&search_dir);
-    eprintln!("Processing {} files...", files.len());
+/// Delay requested by a Retry-After header in seconds, capped so a bogus
+/// value can't stall the run
+fn retry_after(error: &ureq::Error) -> Option<Duration> {
+    match error {
+        ureq::Error::Status(_, response) => response
+            .header("Retry-After")
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|secs| Duration::from_secs(secs).min(SCRYFALL_MAX_RETRY_AFTER)),
+        ureq::Error::Transport(_) => None,
+    }
+}
 
-    // Process files in parallel and merge results
-    let card_counts: HashMap<String, f64> = files
-        .par_iter()
-        .map(|path| {
-            process_file(
-                path,
-                &format_patterns,
-                today,
-                top_args.half_life,
-                args.max_age,
-                use_weight,
-            )
-        })
-        .reduce(HashMap::new, |mut acc, map| {
-            for (card, count) in map {
-                *acc.entry(card).or_insert(0.0) += count;
+/// Exponential backoff before retry `attempt` (from 0), capped at
+/// `SCRYFALL_MAX_BACKOFF`
+fn backoff_delay(base_delay: Duration, attempt: u32) -> Duration {
+    base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(SCRYFALL_MAX_BACKOFF)
+}
+
+/// GET `url`, retrying up to `retries` times on retryable failures. The delay
+/// starts at `base_delay` and doubles each attempt, up to a cap, unless the
+/// server sends Retry-After.
+fn get_with_retries(
+    agent: &ureq::Agent,
+    url: &str,
+    retries: u32,
+    base_delay: Duration,
+) -> Result<ureq::Response, Box<ureq::Error>> {
+    let mut attempt = 0;
+    loop {
+        match agent.get(url).call() {
+            Ok(response) => return Ok(response),
+            Err(e) if attempt < retries && is_retryable(&e) => {
+                let delay = retry_after(&e).unwrap_or_else(|| backoff_delay(base_delay, attempt));
+                eprintln!(
+                    "Warning: Request to {} failed ({}), retrying in {:.1}s ({}/{})",
+                    url,
+                    e,
+                    delay.as_secs_f64(),
+                    attempt + 1,
+                    retries
+                );
+                std::thread::sleep(delay);
+                attempt += 1;
             }
-            acc
-        });
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+}
 
-    // Sort by count descending
-    let mut sorted: Vec<_> = card_counts.into_iter().collect();
-    sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+/// GET a Scryfall URL with the --scryfall-retries policy
+fn scryfall_get(agent: &ureq::Agent, url: &str) -> Result<ureq::Response, Box<ureq::Error>> {
+    get_with_retries(agent, url, SCRYFALL_RETRIES.load(Ordering::Relaxed), SCRYFALL_RETRY_BASE_DELAY)
+}
+
+/// Reader wrapper that reports the cumulative number of bytes read
+struct ProgressReader<R, F> {
+    inner: R,
+    bytes_read: u64,
+    on_progress: F,
+}
+
+impl<R: Read, F: FnMut(u64)> ProgressReader<R, F> {
+    fn new(inner: R, on_progress: F) -> Self {
+        ProgressReader {
+            inner,
+            bytes_read: 0,
+            on_progress,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.bytes_read += n as u64;
+            (self.on_progress)(self.bytes_read);
+        }
+        Ok(n)
+    }
+}
+
+/// Progress callback printing download progress to stderr, only when the
+/// displayed percentage (or megabyte count, if the size is unknown) changes
+fn download_progress_printer(total_bytes: Option<u64>) -> impl FnMut(u64) {
+    const MB: f64 = 1024.0 * 1024.0;
+    let mut last_step = u64::MAX;
+    move |bytes_read| {
+        if quiet() {
+            return;
+        }
+        let step = match total_bytes {
+            Some(total) if total > 0 => bytes_read * 100 / total,
+            _ => bytes_read / (1024 * 1024),
+        };
+        if step == last_step {
+            return;
+        }
+        last_step = step;
+        match total_bytes {
+            Some(total) if total > 0 => eprint!(
+                "\r  {:>3}% ({:.1}/{:.1} MB)",
+                step,
+                bytes_read as f64 / MB,
+                total as f64 / MB
+            ),
+            _ => eprint!("\r  {:.1} MB", bytes_read as f64 / MB),
+        }
+    }
+}
+
+/// Find the entry for `bulk_type` in the bulk data index
+fn select_bulk_entry<'a>(
+    index: &'a ScryfallBulkDataResponse,
+    bulk_type: &str,
+) -> Result<&'a ScryfallBulkDataEntry, String> {
+    index.data.iter().find(|e| e.data_type == bulk_type).ok_or_else(|| {
+        let available: Vec<&str> = index.data.iter().map(|e| e.data_type.as_str()).collect();
+        format!("No {} entry in bulk data (available: {})", bulk_type, available.join(", "))
+    })
+}
+
+/// Why Scryfall bulk data couldn't be fetched
+#[derive(Debug)]
+enum BulkFetchError {
+    /// The bulk data index has no entry for the requested type
+    MissingBulk(String),
+    /// The index or the data couldn't be downloaded or stored
+    Failed(String),
+}
+
+impl From<String> for BulkFetchError {
+    fn from(e: String) -> Self {
+        BulkFetchError::Failed(e)
+    }
+}
+
+/// Fetch Scryfall bulk data and cache it locally
+fn fetch_scryfall_bulk_data(bulk_type: ScryfallBulk, cache_path: &Path) -> Result<(), BulkFetchError> {
+    progress!("Fetching Scryfall bulk data index...");
+
+    // Get the download URL for the requested bulk type
+    let agent = scryfall_agent();
+    let bulk_response: ScryfallBulkDataResponse = scryfall_get(&agent, SCRYFALL_BULK_API)
+        .map_err(|e| format!("Failed to fetch bulk data index: {}", e))?
+        .into_json()
+        .map_err(|e| format!("Failed to parse bulk data index: {}", e))?;
+
+    let entry = select_bulk_entry(&bulk_response, bulk_type.data_type()).map_err(BulkFetchError::MissingBulk)?;
+
+    progress!("Downloading {} (oracle_cards is ~150MB, the others larger)...", bulk_type.data_type());
+
+    // Download the bulk data
+    let response = scryfall_get(&agent, &entry.download_uri)
+        .map_err(|e| format!("Failed to download bulk data: {}", e))?;
+    let total_bytes: Option<u64> = response
+        .header("Content-Length")
+        .and_then(|v| v.parse().ok());
+
+    // Create cache directory
+    if let Some(parent) = cache_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+    }
+
+    // Write to a temporary file first so an interrupted download never
+    // leaves a truncated cache behind
+    let partial_path = cache_path.with_extension("json.part");
+    let mut file = File::create(&partial_path)
+        .map_err(|e| format!("Failed to create cache file: {}", e))?;
+    let mut reader = ProgressReader::new(response.into_reader(), download_progress_printer(total_bytes));
+    let copied = std::io::copy(&mut reader, &mut file);
+    progress!();
+    copied.map_err(|e| format!("Failed to write cache file: {}", e))?;
+    std::fs::rename(&partial_path, cache_path)
+        .map_err(|e| format!("Failed to move cache file into place: {}", e))?;
+
+    progress!("Scryfall data cached at {}", cache_path.display());
+    Ok(())
+}
+
+/// Parse the cached Scryfall bulk data, returning no cards if it is missing or invalid
+fn load_scryfall_cards(cache_path: &Path) -> Vec<ScryfallCard> {
+    let file = match File::open(cache_path) {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+    let reader = BufReader::new(file);
+
+    // Parse as array of cards
+    serde_json::from_reader(reader).unwrap_or_default()
+}
+
+/// Build a map of front face name -> back face name from Scryfall bulk data.
+fn load_back_faces_from_cache(cache_path: &Path) -> HashMap<String, String> {
+    let mut back_faces = HashMap::new();
+    let layouts_with_back_faces: HashSet<&str> =
+        ["transform", "modal_dfc", "reversible_card"].into_iter().collect();
+
+    for card in load_scryfall_cards(cache_path) {
+        let layout = match &card.layout {
+            Some(l) => l.as_str(),
+            None => continue,
+        };
+
+        if layouts_with_back_faces.contains(layout) {
+            if let Some(faces) = card.card_faces {
+                if faces.len() >= 2 {
+                    back_faces.insert(faces[0].name.clone(), faces[1].name.clone());
+                }
+            }
+        }
+    }
+
+    back_faces
+}
+
+/// Collect every card name in Scryfall bulk data, including each face name
+fn load_oracle_names_from_cache(cache_path: &Path) -> HashSet<String> {
+    let mut names = HashSet::new();
+
+    for card in load_scryfall_cards(cache_path) {
+        for face in card.card_faces.into_iter().flatten() {
+            names.insert(face.name);
+        }
+        if let Some(name) = card.name {
+            names.insert(name);
+        }
+    }
+
+    names
+}
+
+/// Build a map of alternate card name -> canonical name from Scryfall bulk
+/// data. Double-faced cards are canonically named by their front face, as in
+/// decklists, so their full and back face names map to it; printed (e.g.
+/// localized) names map to the canonical name of their card. Names that are
+/// canonical for some card are never remapped.
+fn load_canonical_names_from_cache(cache_path: &Path) -> HashMap<String, String> {
+    let layouts_with_back_faces: HashSet<&str> =
+        ["transform", "modal_dfc", "reversible_card"].into_iter().collect();
+    let mut aliases: Vec<(String, String)> = Vec::new();
+    let mut canonical_names = HashSet::new();
+
+    for card in load_scryfall_cards(cache_path) {
+        let Some(name) = card.name else { continue };
+        let faces = card.card_faces.unwrap_or_default();
+        let double_faced = card.layout.as_deref().is_some_and(|l| layouts_with_back_faces.contains(l));
+        let canonical = match faces.first() {
+            Some(front) if double_faced => front.name.clone(),
+            _ => name.clone(),
+        };
+
+        if canonical != name {
+            aliases.push((name, canonical.clone()));
+        }
+        if let Some(printed) = card.printed_name {
+            aliases.push((printed, canonical.clone()));
+        }
+        for face in faces {
+            if double_faced {
+                aliases.push((face.name, canonical.clone()));
+            }
+            if let Some(printed) = face.printed_name {
+                aliases.push((printed, canonical.clone()));
+            }
+        }
+        canonical_names.insert(canonical);
+    }
+
+    aliases
+        .into_iter()
+        .filter(|(alias, canonical)| alias != canonical && !canonical_names.contains(alias))
+        .collect()
+}
+
+/// Build a map of card name -> colors (as WUBRG letters) from Scryfall bulk data.
+/// Both the full name and each face name are mapped, so front faces of
+/// double-faced cards resolve too.
+fn load_colors_from_cache(cache_path: &Path) -> HashMap<String, Vec<char>> {
+    let mut colors = HashMap::new();
+    let to_chars = |c: &Vec<String>| c.iter().filter_map(|s| s.chars().next()).collect::<Vec<char>>();
+
+    for card in load_scryfall_cards(cache_path) {
+        let card_colors = card.colors.as_ref().map(to_chars);
+
+        if let Some(faces) = &card.card_faces {
+            for face in faces {
+                // Double-faced cards only carry colors per face
+                if let Some(c) = face.colors.as_ref().map(to_chars).or_else(|| card_colors.clone()) {
+                    colors.insert(face.name.clone(), c);
+                }
+            }
+        }
+
+        if let Some(name) = card.name {
+            let c = card_colors.or_else(|| {
+                card.card_faces
+                    .as_ref()
+                    .and_then(|faces| faces.first())
+                    .and_then(|face| face.colors.as_ref().map(to_chars))
+            });
+            if let Some(c) = c {
+                colors.insert(name, c);
+            }
+        }
+    }
+
+    colors
+}
+
+/// Build a map of card name -> color identity mask from Scryfall bulk data.
+/// Face names of multi-faced cards map to the whole card's identity.
+fn load_color_identities_from_cache(cache_path: &Path) -> HashMap<String, u8> {
+    let mut identities = HashMap::new();
+
+    for card in load_scryfall_cards(cache_path) {
+        let Some(identity) = &card.color_identity else {
+            continue;
+        };
+        let mask = parse_color_mask(&identity.concat()).unwrap_or(0);
+        for face in card.card_faces.iter().flatten() {
+            identities.insert(face.name.clone(), mask);
+        }
+        if let Some(name) = card.name {
+            identities.insert(name, mask);
+        }
+    }
+
+    identities
+}
+
+/// Build a map of card name -> rarity from Scryfall bulk data.
+/// Face names of multi-faced cards are mapped too.
+fn load_rarities_from_cache(cache_path: &Path) -> HashMap<String, String> {
+    let mut rarities = HashMap::new();
+
+    for card in load_scryfall_cards(cache_path) {
+        let rarity = match card.rarity {
+            Some(r) => r,
+            None => continue,
+        };
+        for face in card.card_faces.iter().flatten() {
+            rarities.insert(face.name.clone(), rarity.clone());
+        }
+        if let Some(name) = card.name {
+            rarities.insert(name, rarity);
+        }
+    }
+
+    rarities
+}
+
+/// Build a map of card name -> type line from Scryfall bulk data.
+/// Face names of multi-faced cards map to their own face's type line when present.
+fn load_type_lines_from_cache(cache_path: &Path) -> HashMap<String, String> {
+    let mut type_lines = HashMap::new();
+
+    for card in load_scryfall_cards(cache_path) {
+        for face in card.card_faces.iter().flatten() {
+            if let Some(t) = face.type_line.as_ref().or(card.type_line.as_ref()) {
+                type_lines.insert(face.name.clone(), t.clone());
+            }
+        }
+        if let (Some(name), Some(t)) = (card.name, card.type_line) {
+            type_lines.insert(name, t);
+        }
+    }
+
+    type_lines
+}
+
+/// Build a map of card name -> mana value from Scryfall bulk data.
+/// Lands are left out so they land in the N/A bucket of --mana-curve.
+fn load_mana_values_from_cache(cache_path: &Path) -> HashMap<String, f64> {
+    let mut mana_values = HashMap::new();
+    let is_land = |t: Option<&String>| t.is_some_and(|t| t.contains("Land"));
+
+    for card in load_scryfall_cards(cache_path) {
+        let cmc = match card.cmc {
+            Some(c) => c,
+            None => continue,
+        };
+        let faces = card.card_faces.as_deref().unwrap_or_default();
+        for face in faces {
+            if !is_land(face.type_line.as_ref().or(card.type_line.as_ref())) {
+                mana_values.insert(face.name.clone(), cmc);
+            }
+        }
+        // Multi-faced cards count as whatever their front face is
+        let front_type = faces.first().and_then(|f| f.type_line.as_ref()).or(card.type_line.as_ref());
+        if let Some(name) = card.name {
+            if !is_land(front_type) {
+                mana_values.insert(name, cmc);
+            }
+        }
+    }
+
+    mana_values
+}
+
+/// Load USD prices from the cache, keyed by card and face name. Oracle cards
+/// carry the price of one representative printing, which may be missing.
+fn load_prices_from_cache(cache_path: &Path) -> HashMap<String, f64> {
+    let mut prices = HashMap::new();
+
+    for card in load_scryfall_cards(cache_path) {
+        let usd = match card.prices.and_then(|p| p.usd).and_then(|p| p.parse::<f64>().ok()) {
+            Some(p) => p,
+            None => continue,
+        };
+        for face in card.card_faces.iter().flatten() {
+            prices.insert(face.name.clone(), usd);
+        }
+        if let Some(name) = card.name {
+            prices.insert(name, usd);
+        }
+    }
+
+    prices
+}
+
+/// Return the Scryfall data path: the --scryfall-file if given, otherwise the cache,
+/// fetching bulk data if it is missing or stale.
+fn ensure_scryfall_cache(bulk_type: ScryfallBulk) -> Option<PathBuf> {
+    scryfall_data_path(bulk_type, SCRYFALL_FILE.get().map(PathBuf::as_path))
+}
+
+/// Use `local` as is if given, skipping the freshness check and download.
+/// Returns `None` if no cache is available at all.
+fn scryfall_data_path(bulk_type: ScryfallBulk, local: Option<&Path>) -> Option<PathBuf> {
+    if let Some(local) = local {
+        return Some(local.to_path_buf());
+    }
+
+    let cache_path = scryfall_cache_path(bulk_type);
+
+    if !is_cache_fresh(&cache_path) {
+        match fetch_scryfall_bulk_data(bulk_type, &cache_path) {
+            Ok(()) => {}
+            // Unlike a network error, a bulk type missing from the index won't go
+            // away on the next run, so don't carry on with stale or no card data
+            Err(BulkFetchError::MissingBulk(e)) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            Err(BulkFetchError::Failed(e)) => {
+                eprintln!("Warning: Failed to fetch Scryfall data: {}", e);
+                // Try to use stale cache if it exists
+                if !cache_path.exists() {
+                    return None;
+                }
+                progress!("Using stale cache...");
+            }
+        }
+    }
+
+    Some(cache_path)
+}
+
+/// Get back faces map, fetching bulk data if needed.
+fn resolve_back_faces(bulk_type: ScryfallBulk) -> HashMap<String, String> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_back_faces_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Get the alternate -> canonical card name map, fetching bulk data if needed.
+fn resolve_canonical_names(bulk_type: ScryfallBulk) -> HashMap<String, String> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_canonical_names_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Get the set of known card names, fetching bulk data if needed.
+fn resolve_oracle_names(bulk_type: ScryfallBulk) -> HashSet<String> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_oracle_names_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Names of the given cards that Scryfall doesn't know, in input order
+fn unknown_card_names<'a>(cards: &'a [(String, f64)], known: &HashSet<String>) -> Vec<&'a str> {
+    cards
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| !known.contains(*name))
+        .collect()
+}
+
+/// Get card colors map, fetching bulk data if needed.
+fn resolve_colors(bulk_type: ScryfallBulk) -> HashMap<String, Vec<char>> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_colors_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Get card color identity map, fetching bulk data if needed.
+/// Without any data, `--unknown-colors skip` would leave out every deck, so
+/// that is an error rather than a warning.
+fn resolve_color_identities(bulk_type: ScryfallBulk, unknown: UnknownColors) -> Result<HashMap<String, u8>, String> {
+    let identities = ensure_scryfall_cache(bulk_type)
+        .map(|path| load_color_identities_from_cache(&path))
+        .unwrap_or_default();
+    if identities.is_empty() {
+        if unknown == UnknownColors::Skip {
+            return Err(
+                "No Scryfall color identity data available for --colors, so every deck would be skipped \
+                 (use --unknown-colors ignore to filter on known cards only)"
+                    .to_string(),
+            );
+        }
+        eprintln!("Warning: No Scryfall color identity data available for --colors");
+    }
+    Ok(identities)
+}
+
+/// Get card rarity map, fetching bulk data if needed.
+fn resolve_rarities(bulk_type: ScryfallBulk) -> HashMap<String, String> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_rarities_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Get card price map, fetching bulk data if needed.
+fn resolve_prices(bulk_type: ScryfallBulk) -> HashMap<String, f64> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_prices_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Get card type line map, fetching bulk data if needed.
+fn resolve_type_lines(bulk_type: ScryfallBulk) -> HashMap<String, String> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_type_lines_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Get card mana value map, fetching bulk data if needed.
+fn resolve_mana_values(bulk_type: ScryfallBulk) -> HashMap<String, f64> {
+    ensure_scryfall_cache(bulk_type)
+        .map(|path| load_mana_values_from_cache(&path))
+        .unwrap_or_default()
+}
+
+/// Color bucket of a card for --by-color: a single color, Multicolor, Colorless,
+/// or Unknown if the card is missing from Scryfall
+fn color_bucket(colors: Option<&Vec<char>>) -> &'static str {
+    match colors.map(|c| c.as_slice()) {
+        None => "Unknown",
+        Some([]) => "Colorless",
+        Some(['W']) => "White",
+        Some(['U']) => "Blue",
+        Some(['B']) => "Black",
+        Some(['R']) => "Red",
+        Some(['G']) => "Green",
+        Some([_]) => "Unknown",
+        Some(_) => "Multicolor",
+    }
+}
+
+/// Sum weighted counts by color bucket, in WUBRG order followed by
+/// Multicolor, Colorless and Unknown
+fn group_by_color(
+    cards: &[(String, f64)],
+    colors: &HashMap<String, Vec<char>>,
+) -> Vec<(&'static str, f64)> {
+    group_into_buckets(cards, &COLOR_BUCKETS, |name| color_bucket(colors.get(name)))
+}
+
+/// Rarity bucket of a card for --by-rarity
+fn rarity_bucket(rarity: Option<&String>) -> &'static str {
+    match rarity.map(|r| r.as_str()) {
+        Some("common") => "Common",
+        Some("uncommon") => "Uncommon",
+        Some("rare") => "Rare",
+        Some("mythic") => "Mythic",
+        Some(_) => "Special",
+        None => "Unknown",
+    }
+}
+
+/// Sum weighted counts by rarity, from common to mythic followed by Special and Unknown
+fn group_by_rarity(
+    cards: &[(String, f64)],
+    rarities: &HashMap<String, String>,
+) -> Vec<(&'static str, f64)> {
+    group_into_buckets(cards, &RARITY_BUCKETS, |name| rarity_bucket(rarities.get(name)))
+}
+
+/// Primary type bucket of a card for --by-type, from the front face of its
+/// type line; Unknown if the card is missing from Scryfall
+fn type_bucket(type_line: Option<&String>) -> &'static str {
+    let front = match type_line {
+        Some(t) => t.split(" // ").next().unwrap_or_default(),
+        None => return "Unknown",
+    };
+    // Supertypes and subtypes are ignored; only the card types before the dash count
+    let types: Vec<&str> = front.split('—').next().unwrap_or_default().split_whitespace().collect();
+    TYPE_BUCKETS
+        .iter()
+        .find(|bucket| types.contains(bucket))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Weighted totals of lands and nonland cards for --land-ratio
+#[derive(Debug, Default, PartialEq)]
+struct LandRatio {
+    lands: f64,
+    spells: f64,
+    /// Cards missing from Scryfall, left out of both totals
+    missing: usize,
+}
+
+impl LandRatio {
+    /// Share of lands among the classified cards (0-1)
+    fn land_share(&self) -> f64 {
+        let total = self.lands + self.spells;
+        if total > 0.0 {
+            self.lands / total
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Split weighted counts into lands and spells by the front face of each
+/// card's type line, so modal lands like "Sorcery // Land" count as spells
+fn land_ratio(cards: &HashMap<String, f64>, type_lines: &HashMap<String, String>) -> LandRatio {
+    let mut ratio = LandRatio::default();
+    for (name, &count) in cards {
+        let Some(type_line) = type_lines.get(name) else {
+            ratio.missing += 1;
+            continue;
+        };
+        let front = type_line.split(" // ").next().unwrap_or_default();
+        let types = front.split('—').next().unwrap_or_default();
+        if types.split_whitespace().any(|t| t == "Land") {
+            ratio.lands += count;
+        } else {
+            ratio.spells += count;
+        }
+    }
+    ratio
+}
+
+/// Sum weighted counts by primary card type, in `TYPE_BUCKETS` order
+fn group_by_type(
+    cards: &[(String, f64)],
+    type_lines: &HashMap<String, String>,
+) -> Vec<(&'static str, f64)> {
+    group_into_buckets(cards, &TYPE_BUCKETS, |name| type_bucket(type_lines.get(name)))
+}
+
+/// Mana value bucket of a card for --mana-curve; lands and unknown cards are N/A
+fn mana_value_bucket(cmc: Option<f64>) -> &'static str {
+    match cmc {
+        Some(c) if c >= 7.0 => "7+",
+        Some(c) => MANA_VALUE_BUCKETS[c.max(0.0) as usize],
+        None => "N/A",
+    }
+}
+
+/// Sum weighted counts by integer mana value, from 0 to 7+ followed by N/A
+fn group_by_mana_value(
+    cards: &[(String, f64)],
+    mana_values: &HashMap<String, f64>,
+) -> Vec<(&'static str, f64)> {
+    group_into_buckets(cards, &MANA_VALUE_BUCKETS, |name| {
+        mana_value_bucket(mana_values.get(name).copied())
+    })
+}
+
+/// Sum weighted counts into the given buckets, keeping their order
+fn group_into_buckets<F>(
+    cards: &[(String, f64)],
+    order: &[&'static str],
+    bucket_of: F,
+) -> Vec<(&'static str, f64)>
+where
+    F: Fn(&str) -> &'static str,
+{
+    let mut buckets: Vec<(&'static str, f64)> = order.iter().map(|&b| (b, 0.0)).collect();
+    for (name, count) in cards {
+        let bucket = bucket_of(name);
+        if let Some(entry) = buckets.iter_mut().find(|(b, _)| *b == bucket) {
+            entry.1 += count;
+        }
+    }
+    buckets
+}
+
+/// Fetches decklists for decks that only carry a source URL.
+///
+/// Requests are serialized and spaced at least `DECKLIST_FETCH_INTERVAL` apart
+/// so we never hammer the source sites.
+#[derive(Debug)]
+struct UrlResolver {
+    agent: ureq::Agent,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl UrlResolver {
+    fn new() -> UrlResolver {
+        UrlResolver {
+            agent: ureq::AgentBuilder::new()
+                .timeout(DECKLIST_FETCH_TIMEOUT)
+                .build(),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Fill in the cards of a deck with a URL but an empty mainboard.
+    /// Failures are reported and leave the deck untouched.
+    fn fill_deck(&self, deck: &mut Deck) {
+        if deck.mainboard.as_ref().is_some_and(|m| !m.is_empty()) {
+            return;
+        }
+        let export_url = match deck.url.as_deref().and_then(decklist_export_url) {
+            Some(u) => u,
+            None => return,
+        };
+        match self.fetch(&export_url) {
+            Ok(text) => fill_deck_from_text(deck, &text),
+            Err(e) => eprintln!("Warning: Failed to resolve {}: {}", export_url, e),
+        }
+    }
+
+    fn fetch(&self, url: &str) -> Result<String, String> {
+        {
+            let mut last = self.last_request.lock().unwrap();
+            if let Some(prev) = *last {
+                let elapsed = prev.elapsed();
+                if elapsed < DECKLIST_FETCH_INTERVAL {
+                    std::thread::sleep(DECKLIST_FETCH_INTERVAL - elapsed);
+                }
+            }
+            *last = Some(Instant::now());
+        }
+        self.agent
+            .get(url)
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_string()
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Plain-text export URL for a deck page on a known source site
+fn decklist_export_url(url: &str) -> Option<String> {
+    // MTGGoldfish: /deck/<id> -> /deck/download/<id>
+    if let Some(rest) = url.split("mtggoldfish.com/deck/").nth(1) {
+        let id: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if !id.is_empty() {
+            return Some(format!("https://www.mtggoldfish.com/deck/download/{}", id));
+        }
+    }
+    // MTGTop8: ...?d=<id> -> MTGO export
+    if url.contains("mtgtop8.com/") {
+        if let Some(rest) = url.split("d=").nth(1) {
+            let id: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            if !id.is_empty() {
+                return Some(format!("https://mtgtop8.com/mtgo?d={}", id));
+            }
+        }
+    }
+    // Already a plain-text list
+    if url.ends_with(".txt") {
+        return Some(url.to_string());
+    }
+    None
+}
+
+/// Parse a plain-text decklist ("4 Lightning Bolt" per line, optionally "4x").
+/// A blank line or a "Sideboard" header starts the sideboard.
+fn parse_text_decklist(text: &str) -> (Vec<Card>, Vec<Card>) {
+    let mut mainboard = Vec::new();
+    let mut sideboard = Vec::new();
+    let mut in_sideboard = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            if !mainboard.is_empty() {
+                in_sideboard = true;
+            }
+            continue;
+        }
+        let lower = line.to_lowercase();
+        if lower.starts_with("sideboard") {
+            in_sideboard = true;
+            continue;
+        }
+        if line.starts_with("//") || lower == "deck" || lower == "mainboard" {
+            continue;
+        }
+
+        let (count, name) = match line.split_once(char::is_whitespace) {
+            Some((count, name)) => (count.trim_end_matches(['x', 'X']), name.trim()),
+            None => continue,
+        };
+        let count: u32 = match count.parse() {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        let card = Card {
+            count,
+            name: name.to_string(),
+        };
+        if in_sideboard {
+            sideboard.push(card);
+        } else {
+            mainboard.push(card);
+        }
+    }
+
+    (mainboard, sideboard)
+}
+
+/// Fill a deck's boards from a fetched plain-text decklist, keeping any
+/// sideboard the deck already had if the list has none
+fn fill_deck_from_text(deck: &mut Deck, text: &str) {
+    let (mainboard, sideboard) = parse_text_decklist(text);
+    if mainboard.is_empty() {
+        return;
+    }
+    deck.mainboard = Some(mainboard);
+    if !sideboard.is_empty() {
+        deck.sideboard = Some(sideboard);
+    }
+}
+
+/// Parse card criterion from string like "4 Lightning Bolt" or "Lightning Bolt".
+/// A leading "!" marks the criterion as an exclusion, e.g. "!Ragavan, Nimble Pilferer".
+fn parse_card_criterion(input: &str) -> CardCriterion {
+    let input = input.trim();
+    if let Some(rest) = input.strip_prefix('!') {
+        return CardCriterion {
+            excluded: true,
+            ..parse_card_criterion(rest)
+        };
+    }
+
+    // Try to parse leading number
+    let mut chars = input.chars().peekable();
+    let mut num_str = String::new();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() {
+            num_str.push(c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+
+    // The count must be a whole token, so "97th Regiment" stays a name
+    let count_is_token = chars.peek().is_some_and(|c| c.is_whitespace());
+    if !num_str.is_empty() && count_is_token {
+        // Skip whitespace after number
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        let name: String = chars.collect();
+        if !name.is_empty() {
+            return CardCriterion {
+                name,
+                count: num_str.parse().ok(),
+                excluded: false,
+                pattern: None,
+            };
+        }
+    }
+
+    // No leading number, entire input is card name
+    CardCriterion {
+        name: input.to_string(),
+        count: None,
+        excluded: false,
+        pattern: None,
+    }
+}
+
+/// Compile each criterion's name as a case-insensitive regex that must match
+/// the whole card name, for --regex
+fn compile_name_patterns(criteria: &mut [CardCriterion]) -> Result<(), String> {
+    for criterion in criteria {
+        let pattern = RegexBuilder::new(&format!("^(?:{})$", criterion.name))
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("invalid regex {:?}: {}", criterion.name, e))?;
+        criterion.pattern = Some(pattern);
+    }
+    Ok(())
+}
+
+/// Archetype definitions file for --archetypes:
+///
+/// ```toml
+/// [[archetypes]]
+/// name = "Burn"
+/// cards = ["4 Lightning Bolt", "Goblin Guide"]
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ArchetypesFile {
+    #[serde(default)]
+    archetypes: Vec<ArchetypeDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArchetypeDefinition {
+    name: String,
+    cards: Vec<String>,
+}
+
+/// An archetype and the signature cards a deck must run to carry its label
+struct Archetype {
+    name: String,
+    signature: Vec<CardCriterion>,
+}
+
+/// Whether a format is played with a command zone, by its name
+fn is_commander_format(format: &str) -> bool {
+    let format = format.to_lowercase();
+    format.contains("commander") || format.contains("edh")
+}
+
+/// Archetype key of a Commander deck: the names in its command zone, sorted and
+/// joined, so partner pairs group whichever order a list gives them in
+fn commander_key(commander: &[Card]) -> Option<String> {
+    let mut names: Vec<&str> = commander.iter().map(|c| c.name.trim()).filter(|n| !n.is_empty()).collect();
+    if names.is_empty() {
+        return None;
+    }
+    names.sort_unstable();
+    names.dedup();
+    Some(names.join(" + "))
+}
+
+/// Parse archetype definitions, keeping their order
+fn parse_archetypes(text: &str) -> Result<Vec<Archetype>, String> {
+    let file: ArchetypesFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    Ok(file
+        .archetypes
+        .into_iter()
+        .map(|a| Archetype {
+            name: a.name,
+            signature: a.cards.iter().map(|c| parse_card_criterion(c)).collect(),
+        })
+        .collect())
+}
+
+/// Load archetype definitions from a TOML file
+fn load_archetypes(path: &str) -> Result<Vec<Archetype>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    parse_archetypes(&text).map_err(|e| format!("Failed to parse {}: {}", path, e))
+}
+
+/// Name of the first archetype whose signature cards are all in the deck's
+/// mainboard or command zone
+fn archetype_label<'a>(archetypes: &'a [Archetype], deck: &Deck) -> Option<&'a str> {
+    archetypes
+        .iter()
+        .find(|a| !a.signature.is_empty() && deck_matches_criteria(deck, &a.signature, &MatchOptions::default()).is_some())
+        .map(|a| a.name.as_str())
+}
+
+/// Lowercase a card name, strip its accents and collapse punctuation and
+/// whitespace runs into single spaces, for --loose matching
+fn loose_name(name: &str) -> String {
+    let stripped: String = name
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Check if a deck matches all card criteria, or any of them with `options.match_any`.
+///
+/// In any-of mode a criterion with a count also matches partially, i.e. when the
+/// deck runs fewer copies than requested. Only the criteria that matched are
+/// reported. A deck satisfying any excluded criterion never matches.
+/// With `options.commander_only` only the command zone is searched.
+fn deck_matches_criteria(
+    deck: &Deck,
+    criteria: &[CardCriterion],
+    options: &MatchOptions,
+) -> Option<Vec<CardMatchInfo>> {
+    let mut match_info = Vec::new();
+    let key = |name: &str| {
+        if options.loose {
+            loose_name(name)
+        } else {
+            name.to_lowercase()
+        }
+    };
+
+    // Build card count maps for the deck
+    let mut main_counts: HashMap<String, u32> = HashMap::new();
+    let mut side_counts: HashMap<String, u32> = HashMap::new();
+
+    // The command zone counts as part of the mainboard
+    let main_zones = if options.commander_only {
+        [None, deck.commander.as_ref()]
+    } else {
+        [deck.mainboard.as_ref(), deck.commander.as_ref()]
+    };
+    for card in main_zones.into_iter().flatten().flatten() {
+        *main_counts.entry(key(&card.name)).or_insert(0) += card.count;
+    }
+
+    if let Some(sideboard) = deck.sideboard.as_ref().filter(|_| !options.commander_only) {
+        for card in sideboard {
+            *side_counts.entry(key(&card.name)).or_insert(0) += card.count;
+        }
+    }
+
+    // Check each criterion
+    for criterion in criteria {
+        let name_lower = key(&criterion.name);
+        // With fuzzy or regex matching, every card whose name matches counts
+        let count_in = |counts: &HashMap<String, u32>| -> u32 {
+            if let Some(pattern) = &criterion.pattern {
+                counts
+                    .iter()
+                    .filter(|(name, _)| pattern.is_match(name))
+                    .map(|(_, count)| count)
+                    .sum()
+            } else if options.fuzzy {
+                counts
+                    .iter()
+                    .filter(|(name, _)| name.contains(&name_lower))
+                    .map(|(_, count)| count)
+                    .sum()
+            } else {
+                counts.get(&name_lower).copied().unwrap_or(0)
+            }
+        };
+        let found_main = count_in(&main_counts);
+        let found_side = count_in(&side_counts);
+
+        let total = if options.include_sideboard {
+            found_main + found_side
+        } else {
+            found_main
+        };
+
+        let matches = match criterion.count {
+            Some(_) if options.match_any && !options.at_most && !criterion.excluded => total > 0,
+            Some(required) => {
+                if options.exact {
+                    total == required
+                } else if options.at_most {
+                    total > 0 && total <= required
+                } else {
+                    total >= required
+                }
+            }
+            None => total > 0,
+        };
+
+        if criterion.excluded {
+            if matches {
+                return None;
+            }
+            continue;
+        }
+
+        if !matches {
+            if options.match_any {
+                continue;
+            }
+            return None;
+        }
+
+        match_info.push(CardMatchInfo {
+            name: criterion.name.clone(),
+            requested: criterion.count,
+            found_main,
+            found_side,
+        });
+    }
+
+    if options.match_any && match_info.is_empty() {
+        return None;
+    }
+    Some(match_info)
+}
+
+/// Average copies of one searched card across the decks it matched in
+#[derive(Debug, PartialEq)]
+struct CriterionSummary {
+    name: String,
+    decks: usize,
+    avg_main: f64,
+    avg_side: f64,
+}
+
+/// Average the copies found for each criterion over the matches containing
+/// it, in the order the criteria first appear
+fn summarize_matches(matches: &[DeckMatch]) -> Vec<CriterionSummary> {
+    let mut summaries: Vec<CriterionSummary> = Vec::new();
+    for info in matches.iter().flat_map(|m| &m.matched_cards) {
+        let index = match summaries.iter().position(|s| s.name == info.name) {
+            Some(i) => i,
+            None => {
+                summaries.push(CriterionSummary {
+                    name: info.name.clone(),
+                    decks: 0,
+                    avg_main: 0.0,
+                    avg_side: 0.0,
+                });
+                summaries.len() - 1
+            }
+        };
+        let summary = &mut summaries[index];
+        summary.decks += 1;
+        summary.avg_main += info.found_main as f64;
+        summary.avg_side += info.found_side as f64;
+    }
+    for summary in &mut summaries {
+        summary.avg_main /= summary.decks as f64;
+        summary.avg_side /= summary.decks as f64;
+    }
+    summaries
+}
+
+/// A decklist file that passed the date and format filters
+struct ScopedFile {
+    /// Age of the file in days
+    age: i64,
+    /// File date as YYYY-MM-DD
+    date: String,
+    data: DecklistFile,
+}
+
+/// Age in days and YYYY-MM-DD date of a file, if its path date is within the
+/// scope's `max_age` days. Future dates are handled per `scope.future_dates`.
+fn file_age(path: &Path, scope: &ScopeFilter) -> Option<(i64, String)> {
+    let path_str = path.to_string_lossy();
+
+    // Extract date from path
+    let (year, month, day) = extract_date_from_path(&path_str)?;
+
+    let file_days = days_since_epoch(year, month, day);
+    let mut age = scope.today - file_days;
+
+    // Skip if too old
+    if age > scope.max_age {
+        return None;
+    }
+
+    if age < 0 {
+        match scope.future_dates {
+            FutureDates::Skip => return None,
+            FutureDates::Clamp => age = 0,
+            FutureDates::Allow => {}
+        }
+    }
+
+    Some((age, format!("{:04}-{:02}-{:02}", year, month, day)))
+}
+
+/// Whether a card name is a basic land, ignoring case and surrounding whitespace
+fn is_basic_land(name: &str) -> bool {
+    BASIC_LANDS.contains(&name.trim().to_lowercase().as_str())
+}
+
+/// The plain basic land name of a basic land variant (e.g. "Snow-Covered
+/// Mountain" or "mountain"), for --fold-basics; `None` for other cards and
+/// names already in plain form
+fn fold_basic_land(name: &str) -> Option<String> {
+    if !is_basic_land(name) {
+        return None;
+    }
+    let lower = name.trim().to_lowercase();
+    let base = lower.strip_prefix("snow-covered ").unwrap_or(&lower);
+    let mut chars = base.chars();
+    let first = chars.next()?;
+    let folded = first.to_uppercase().chain(chars).collect::<String>();
+    (folded != name).then_some(folded)
+}
+
+/// Card renames applied within each deck before it is counted, so a deck
+/// running two names of one card still counts once towards its presence
+#[derive(Debug, Default)]
+struct CardRenames {
+    /// Alternate name -> canonical name, for --canonicalize
+    canonical: HashMap<String, String>,
+    /// Fold basic land variants into the plain basics, for --fold-basics
+    fold_basics: bool,
+}
+
+impl CardRenames {
+    fn rename(&self, name: String) -> String {
+        let name = self.canonical.get(&name).cloned().unwrap_or(name);
+        match self.fold_basics.then(|| fold_basic_land(&name)).flatten() {
+            Some(folded) => folded,
+            None => name,
+        }
+    }
+}
+
+/// Drop basic lands from aggregated card counts
+fn remove_basic_lands(cards: &mut HashMap<String, f64>) {
+    cards.retain(|name, _| !is_basic_land(name));
+}
+
+/// Weights for --rank-decay: the Nth most recent tournament (by age in days)
+/// gets 1/N. Tournaments on the same day share a rank.
+fn rank_decay_weights(ages: &[i64]) -> Vec<f64> {
+    let mut sorted = ages.to_vec();
+    sorted.sort_unstable();
+    ages.iter()
+        .map(|age| {
+            let rank = sorted.partition_point(|other| other < age);
+            1.0 / (rank + 1) as f64
+        })
+        .collect()
+}
+
+/// Merge unweighted per-tournament counts, weighting each by its recency rank
+fn merge_rank_decayed(tournaments: Vec<(i64, CardCounts)>) -> CardCounts {
+    let ages: Vec<i64> = tournaments.iter().map(|(age, _)| *age).collect();
+    tournaments
+        .into_iter()
+        .zip(rank_decay_weights(&ages))
+        .map(|((_, counts), weight)| counts.scale(weight))
+        .fold(CardCounts::default(), CardCounts::merge)
+}
+
+/// Count `files` in parallel and merge the results. Each thread folds its
+/// files into one accumulator, so the large maps are only merged a handful
+/// of times at the end rather than pairwise across every file.
+fn par_merge_counts<F>(files: &[PathBuf], count_file: F) -> CardCounts
+where
+    F: Fn(&Path) -> CardCounts + Sync + Send,
+{
+    files
+        .par_iter()
+        .fold(CardCounts::default, |acc, path| acc.merge(count_file(path)))
+        .reduce(CardCounts::default, CardCounts::merge)
+}
+
+/// Requested format patterns that no counted tournament format matched,
+/// usually a typo or a date window with no events
+fn unmatched_format_patterns<'a>(scope: &'a ScopeFilter, counts: &CardCounts) -> Vec<&'a str> {
+    scope
+        .format_patterns
+        .iter()
+        .filter(|pattern| !counts.decks_by_format.keys().any(|format| scope.pattern_matches(format, pattern)))
+        .map(|pattern| pattern.as_str())
+        .collect()
+}
+
+/// Parse a file if it passes the scope's date, format and event size filters
+fn load_scoped_file(path: &Path, scope: &ScopeFilter) -> Option<ScopedFile> {
+    let (age, date) = file_age(path, scope)?;
+
+    // Parse JSON file
+    let parsed = File::open(path)
+        .ok()
+        .and_then(|file| serde_json::from_reader::<_, DecklistFile>(BufReader::new(file)).ok());
+    let Some(mut data) = parsed else {
+        note_unparseable(path);
+        return None;
+    };
+    if data.tournament.format.is_none() && scope.infer_format {
+        data.tournament.format = infer_format_from_path(path);
+    }
+
+    // Check format
+    if !scope.format_ok(data.tournament.format.as_ref()?) {
+        return None;
+    }
+
+    let num_decks = data.decks.as_ref().map_or(0, |d| d.len() as u64);
+    if !scope.rounds_ok(data.tournament.rounds, num_decks) {
+        return None;
+    }
+
+    if let Some(decks) = &mut data.decks {
+        decks.retain(|deck| scope.deck_ok(deck));
+    }
+
+    Some(ScopedFile { age, date, data })
+}
+
+/// Read only the tournament of a decklist file. Bare arrays of decks have
+/// an empty tournament.
+fn peek_tournament(path: &Path) -> Option<Tournament> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let first = reader.fill_buf().ok()?.iter().find(|b| !b.is_ascii_whitespace()).copied();
+    if first == Some(b'[') {
+        return Some(Tournament::default());
+    }
+    let header: TournamentHeader = serde_json::from_reader(reader).ok()?;
+    if let Some(tournament) = header.tournament {
+        return Some(tournament);
+    }
+    let fields = TOP_LEVEL_TOURNAMENT_KEYS
+        .into_iter()
+        .zip([header.format, header.name, header.date, header.rounds])
+        .filter_map(|(key, value)| Some((key.to_string(), value?)))
+        .collect();
+    Some(top_level_tournament(&fields))
+}
+
+/// Finishing position from a deck result like "1st", "5th Place" or "Top 8".
+/// Match records such as "5-0" carry no placement.
+fn parse_placement(result: &str) -> Option<u32> {
+    let result = result.trim().to_lowercase();
+    if let Some(rest) = result.strip_prefix("top") {
+        return rest.trim().parse().ok();
+    }
+    let digits: String = result.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let suffix = result[digits.len()..].trim_start();
+    if ["st", "nd", "rd", "th"].iter().any(|s| suffix.starts_with(s)) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Search a single file for matching decks
+fn search_file_for_decks(
+    path: &Path,
+    scope: &ScopeFilter,
+    criteria: &[CardCriterion],
+    options: &MatchOptions,
+) -> Vec<DeckMatch> {
+    search_file_collecting_names(path, scope, criteria, options, None)
+}
+
+/// `search_file_for_decks`, also adding the card names of every in-scope deck,
+/// from all zones, to `names` if given
+fn search_file_collecting_names(
+    path: &Path,
+    scope: &ScopeFilter,
+    criteria: &[CardCriterion],
+    options: &MatchOptions,
+    mut names: Option<&mut HashSet<String>>,
+) -> Vec<DeckMatch> {
+    let mut matches = Vec::new();
+    let ScopedFile { date: file_date, data, .. } =
+        match load_scoped_file(path, scope) {
+            Some(f) => f,
+            None => return matches,
+        };
+
+    // Search each deck
+    if let Some(decks) = data.decks {
+        for deck in decks {
+            if let Some(names) = &mut names {
+                let zones = deck.mainboard.iter().chain(&deck.sideboard).chain(&deck.commander);
+                names.extend(zones.flatten().map(|card| card.name.clone()));
+            }
+            if options.require_url && deck.url.is_none() {
+                continue;
+            }
+            if let Some(max) = options.max_placement {
+                if deck.result.as_deref().and_then(parse_placement).is_none_or(|p| p > max) {
+                    continue;
+                }
+            }
+            if let Some(matched_cards) = deck_matches_criteria(&deck, criteria, options) {
+                matches.push(DeckMatch {
+                    tournament: data.tournament.clone(),
+                    file_date: file_date.clone(),
+                    player: deck.player.clone(),
+                    result: deck.result.clone(),
+                    url: deck.url.clone(),
+                    mainboard: deck.mainboard.clone().unwrap_or_default(),
+                    sideboard: deck.sideboard.clone().unwrap_or_default(),
+                    commander: deck.commander.clone().unwrap_or_default(),
+                    matched_cards,
+                    archetype: None,
+                });
+            }
+        }
+    }
+
+    matches
+}
+
+fn process_file(
+    path: &Path,
+    scope: &ScopeFilter,
+    options: &ProcessOptions,
+    cache: Option<&FileCache>,
+) -> CardCounts {
+    let (age, date) = match file_age(path, scope) {
+        Some(a) => a,
+        None => return CardCounts::default(),
+    };
+
+    let weight = options.weight(age);
+
+    // Summaries of deck-filtered, deduplicated or mainboard-only files differ
+    // from a full parse, and resolved URLs may change between runs, so none
+    // use the cache
+    let deck_filter = scope.filters_decks().then_some(scope);
+    let bypass_cache =
+        deck_filter.is_some()
+            || options.url_resolver.is_some()
+            || options.dedupe
+            || options.skip_sideboard
+            || options.skip_mainboard
+            || options.max_decks.is_some()
+            || options.require_url
+            || options.win_weight
+            || options.renames.is_some();
+    let cache = if bypass_cache {
+        None
+    } else {
+        cache
+    };
+
+    let mut counts = if let Some(summary) = cache.and_then(|c| c.get(path)) {
+        counts_from_summary(&with_inferred_format(summary, path, scope), scope, weight, options)
+    } else {
+        let summary = match summarize_file(path, deck_filter, options) {
+            Some(s) => s,
+            None => {
+                note_unparseable(path);
+                return CardCounts::default();
+            }
+        };
+        let counts = counts_from_summary(&with_inferred_format(&summary, path, scope), scope, weight, options);
+        if let Some(cache) = cache {
+            cache.insert(path, summary);
+        }
+        counts
+    };
+
+    if options.track_dates {
+        counts.card_dates = counts.cards.keys().map(|name| (name.clone(), (date.clone(), date.clone()))).collect();
+    }
+    counts
+}
+
+/// A summary without a format, given the one its path names with --infer-format.
+/// The cached summary itself is left without one.
+fn with_inferred_format<'a>(summary: &'a FileSummary, path: &Path, scope: &ScopeFilter) -> Cow<'a, FileSummary> {
+    if summary.format.is_some() || !scope.infer_format {
+        return Cow::Borrowed(summary);
+    }
+    Cow::Owned(FileSummary {
+        format: infer_format_from_path(path),
+        ..summary.clone()
+    })
+}
+
+/// Parse a file and tally its raw (unweighted) card counts, counting only the
+/// decks accepted by `deck_filter` if given. The URL resolver, dedupe and
+/// skip-sideboard settings of `options` apply to each deck.
+///
+/// Decks are streamed one at a time rather than materialized as a whole, which
+/// keeps memory flat on files with thousands of decks.
+fn summarize_file(
+    path: &Path,
+    deck_filter: Option<&ScopeFilter>,
+    options: &ProcessOptions,
+) -> Option<FileSummary> {
+    read_summary(path, deck_filter, options, false).map(SummaryBuilder::finish)
+}
+
+/// Stream a file into a `SummaryBuilder`, keeping each counted deck if
+/// `keep_decks` is set
+fn read_summary<'a>(
+    path: &Path,
+    deck_filter: Option<&'a ScopeFilter>,
+    options: &'a ProcessOptions,
+    keep_decks: bool,
+) -> Option<SummaryBuilder<'a>> {
+    let file = File::open(path).ok()?;
+    let reader = BufReader::new(file);
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let visitor = StreamingSummaryVisitor { deck_filter, options, keep_decks };
+    let builder = deserializer.deserialize_any(visitor).ok()?;
+    deserializer.end().ok()?;
+    if builder.duplicates > 0 {
+        eprintln!(
+            "Warning: Skipped {} duplicate deck(s) in {}",
+            builder.duplicates,
+            path.display()
+        );
+    }
+    Some(builder)
+}
+
+/// The `max` best placed decks, for --max-decks-per-event. Decks without a
+/// parseable placement rank after placed ones, keeping file order.
+fn best_placed(mut decks: Vec<Deck>, max: usize) -> Vec<Deck> {
+    decks.sort_by_key(|deck| deck.result.as_deref().and_then(parse_placement).unwrap_or(u32::MAX));
+    decks.truncate(max);
+    decks
+}
+
+/// Hash of a deck's player and mainboard, independent of card order
+fn deck_fingerprint(deck: &Deck) -> u64 {
+    let mut cards: Vec<(&str, u32)> = deck
+        .mainboard
+        .iter()
+        .flatten()
+        .map(|c| (c.name.as_str(), c.count))
+        .collect();
+    cards.sort_unstable();
+
+    let mut hasher = DefaultHasher::new();
+    deck.player.hash(&mut hasher);
+    cards.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Accumulates a `FileSummary` deck by deck
+#[derive(Default)]
+struct SummaryBuilder<'a> {
+    deck_filter: Option<&'a ScopeFilter>,
+    resolver: Option<&'a UrlResolver>,
+    /// Fingerprints of decks seen so far, when deduplicating
+    seen: Option<HashSet<u64>>,
+    duplicates: usize,
+    /// Deserialize decks without their sideboards
+    skip_sideboard: bool,
+    /// Count only sideboards
+    skip_mainboard: bool,
+    /// Count only the best placed decks of the file, if set
+    max_decks: Option<usize>,
+    /// Skip decks without a source URL
+    require_url: bool,
+    /// Count each deck `Deck::win_weight` times
+    win_weight: bool,
+    renames: Option<&'a CardRenames>,
+    /// Decks held back until the whole file is read, with `max_decks`
+    held: Vec<Deck>,
+    format: Option<String>,
+    rounds: Option<u32>,
+    summary: FileSummary,
+    /// Mainboard sizes, checked against the format once it is known
+    main_sizes: Vec<u32>,
+    /// Each counted deck, for callers that need decks individually
+    kept: Option<Vec<CountedDeck>>,
+}
+
+/// A deck as `SummaryBuilder` counted it, after renames and board skipping
+struct CountedDeck {
+    cards: HashMap<String, u32>,
+    multiplier: u32,
+    main_size: u32,
+    fingerprint: u64,
+}
+
+impl SummaryBuilder<'_> {
+    fn add_deck(&mut self, mut deck: Deck) {
+        self.summary.event_decks += 1;
+        if self.deck_filter.is_some_and(|f| !f.deck_ok(&deck)) {
+            return;
+        }
+        if self.require_url && deck.url.is_none() {
+            return;
+        }
+        if let Some(resolver) = self.resolver {
+            resolver.fill_deck(&mut deck);
+        }
+        if let Some(seen) = &mut self.seen {
+            if !seen.insert(deck_fingerprint(&deck)) {
+                self.duplicates += 1;
+                return;
+            }
+        }
+        if self.max_decks.is_some() {
+            self.held.push(deck);
+        } else {
+            self.count_deck(deck);
+        }
+    }
+
+    fn count_deck(&mut self, mut deck: Deck) {
+        self.summary.decks += 1;
+        let multiplier = if self.win_weight { deck.win_weight() } else { 1 };
+        if self.win_weight {
+            *self.summary.win_weighted_decks.get_or_insert(0) += multiplier as u64;
+        }
+        let main_size = deck.mainboard.iter().chain(&deck.commander).flatten().map(|c| c.count).sum();
+        self.main_sizes.push(main_size);
+
+        let mut deck_cards: HashMap<String, u32> = HashMap::new();
+        if self.skip_mainboard {
+            deck.mainboard = None;
+            deck.commander = None;
+        }
+        let fingerprint = self.kept.is_some().then(|| list_fingerprint(&deck));
+        for card in deck.mainboard.into_iter().chain(deck.commander).chain(deck.sideboard).flatten() {
+            let name = match self.renames {
+                Some(renames) => renames.rename(card.name),
+                None => card.name,
+            };
+            *deck_cards.entry(name).or_insert(0) += card.count;
+        }
+        if let (Some(kept), Some(fingerprint)) = (&mut self.kept, fingerprint) {
+            kept.push(CountedDeck {
+                cards: deck_cards.clone(),
+                multiplier,
+                main_size,
+                fingerprint,
+            });
+        }
+        for (name, count) in deck_cards {
+            *self.summary.presence.entry(name.clone()).or_insert(0) += multiplier;
+            *self.summary.copies.entry(name).or_insert(0) += count * multiplier;
+        }
+    }
+
+    fn finish(self) -> FileSummary {
+        self.finish_with_decks().0
+    }
+
+    fn finish_with_decks(mut self) -> (FileSummary, Vec<CountedDeck>) {
+        if let Some(max) = self.max_decks {
+            for deck in best_placed(std::mem::take(&mut self.held), max) {
+                self.count_deck(deck);
+            }
+        }
+        let mut summary = self.summary;
+        let format_name = self.format.clone().unwrap_or_default();
+        for size in self.main_sizes {
+            if deck_size_within_tolerance(&format_name, size) {
+                summary.sizes.within += 1;
+            } else {
+                summary.sizes.outside += 1;
+            }
+        }
+        summary.format = self.format;
+        summary.rounds = self.rounds;
+        (summary, self.kept.unwrap_or_default())
+    }
+}
+
+/// Visits a decklist file object, folding each deck into a `SummaryBuilder`
+struct StreamingSummaryVisitor<'a> {
+    deck_filter: Option<&'a ScopeFilter>,
+    options: &'a ProcessOptions,
+    keep_decks: bool,
+}
+
+impl<'a> StreamingSummaryVisitor<'a> {
+    fn builder(&self) -> SummaryBuilder<'a> {
+        SummaryBuilder {
+            deck_filter: self.deck_filter,
+            resolver: self.options.url_resolver.as_deref(),
+            seen: self.options.dedupe.then(HashSet::new),
+            skip_sideboard: self.options.skip_sideboard,
+            skip_mainboard: self.options.skip_mainboard,
+            max_decks: self.options.max_decks,
+            require_url: self.options.require_url,
+            win_weight: self.options.win_weight,
+            renames: self.options.renames.as_deref(),
+            kept: self.keep_decks.then(Vec::new),
+            ..Default::default()
+        }
+    }
+}
+
+impl<'de, 'a> serde::de::Visitor<'de> for StreamingSummaryVisitor<'a> {
+    type Value = SummaryBuilder<'a>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a decklist file object or an array of decks")
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<SummaryBuilder<'a>, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        // A bare array of decks has no tournament, so no format or rounds
+        let mut builder = self.builder();
+        DeckStream(&mut builder).visit_seq(seq)?;
+        Ok(builder)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<SummaryBuilder<'a>, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut builder = self.builder();
+        let mut tournament: Option<Tournament> = None;
+        // Fallback for files with the tournament fields at the top level
+        let mut top_level = serde_json::Map::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "tournament" | "event" => tournament = Some(map.next_value()?),
+                key if TOP_LEVEL_TOURNAMENT_KEYS.contains(&key) => {
+                    top_level.insert(key.to_string(), map.next_value()?);
+                }
+                "decks" => map.next_value_seed(DeckStream(&mut builder))?,
+                _ => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
+        }
+
+        let tournament = match tournament {
+            Some(t) => t,
+            None => match top_level_tournament(&top_level) {
+                t if t.format.is_some() => t,
+                _ => return Err(serde::de::Error::missing_field("tournament")),
+            },
+        };
+        builder.format = tournament.format;
+        builder.rounds = tournament.rounds;
+        Ok(builder)
+    }
+}
+
+/// Streams an optional array of decks into a `SummaryBuilder`
+struct DeckStream<'a, 'b>(&'a mut SummaryBuilder<'b>);
+
+impl<'de> serde::de::DeserializeSeed<'de> for DeckStream<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for DeckStream<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array of decks")
+    }
+
+    fn visit_none<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        if self.0.skip_sideboard {
+            while let Some(deck) = seq.next_element::<MainboardDeck>()? {
+                self.0.add_deck(deck.into());
+            }
+        } else {
+            while let Some(deck) = seq.next_element::<Deck>()? {
+                self.0.add_deck(deck);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply the format and event size filters and weight to a file summary
+fn counts_from_summary(
+    summary: &FileSummary,
+    scope: &ScopeFilter,
+    weight: f64,
+    options: &ProcessOptions,
+) -> CardCounts {
+    let mut counts = CardCounts::default();
+
+    // Check format
+    let format_name = match &summary.format {
+        Some(f) if scope.format_ok(f) => f,
+        _ => return counts,
+    };
+    if summary.decks == 0 || !scope.rounds_ok(summary.rounds, summary.event_decks) {
+        return counts;
+    }
+    let weight = weight * event_size_weight(summary.event_decks, options.size_cap);
+
+    let weighted_decks = summary.win_weighted_decks.unwrap_or(summary.decks);
+    counts.decks_by_format.insert(format_name.clone(), weighted_decks as f64 * weight);
+    counts.decks = summary.decks;
+    counts.files = 1;
+    counts.deck_sizes.insert(format_name.clone(), summary.sizes);
+
+    let source = if options.presence { &summary.presence } else { &summary.copies };
+    let mut by_format = HashMap::new();
+    for (name, &count) in source {
+        if !name_long_enough(name, options.min_name_length) {
+            counts.dropped_names += 1;
+            continue;
+        }
+        counts.cards.insert(name.clone(), count as f64 * weight);
+        if options.by_format {
+            let count = if options.format_presence { summary.presence[name] } else { count };
+            by_format.insert(name.clone(), count as f64 * weight);
+        }
+        if options.track_raw {
+            counts.raw_cards.insert(name.clone(), count as f64);
+        }
+        if options.track_presence {
+            let decks = summary.presence.get(name).copied().unwrap_or(0);
+            counts.presence_cards.insert(name.clone(), decks as f64 * weight);
+        }
+    }
+    if options.by_format {
+        counts.cards_by_format.insert(format_name.clone(), by_format);
+    }
+
+    counts
+}
+
+/// Whether a card name is long enough to be a real card rather than a
+/// parsing leftover. Empty names never are.
+fn name_long_enough(name: &str, min_length: usize) -> bool {
+    name.trim().chars().count() >= min_length.max(1)
+}
+
+/// Collect the in-scope decks of a file individually, for resampling. Decks
+/// pass the same filters as in `process_file`. None if the file can't be parsed.
+fn collect_weighted_decks(path: &Path, scope: &ScopeFilter, options: &ProcessOptions) -> Option<Vec<WeightedDeck>> {
+    let (age, date) = match file_age(path, scope) {
+        Some(a) => a,
+        None => return Some(Vec::new()),
+    };
+    let deck_filter = scope.filters_decks().then_some(scope);
+    let (summary, decks) = read_summary(path, deck_filter, options, true)?.finish_with_decks();
+
+    let format = match &with_inferred_format(&summary, path, scope).format {
+        Some(f) if scope.format_ok(f) => f.clone(),
+        _ => return Some(Vec::new()),
+    };
+    if !scope.rounds_ok(summary.rounds, summary.event_decks) {
+        return Some(Vec::new());
+    }
+    let weight = options.weight(age) * event_size_weight(summary.event_decks, options.size_cap);
+    let file: Arc<Path> = Arc::from(path);
+
+    let decks = decks
+        .into_iter()
+        .map(|deck| WeightedDeck {
+            weight: weight * deck.multiplier as f64,
+            multiplier: deck.multiplier,
+            cards: deck
+                .cards
+                .into_iter()
+                .map(|(name, count)| (name, if options.presence { 1 } else { count }))
+                .collect(),
+            within_size: deck_size_within_tolerance(&format, deck.main_size),
+            format: format.clone(),
+            fingerprint: deck.fingerprint,
+            file: file.clone(),
+            date: date.clone(),
+        })
+        .collect();
+    Some(decks)
+}
+
+/// Hash of a deck's normalized contents, independent of card order, name case
+/// and who played it. The command zone counts with the mainboard.
+fn list_fingerprint(deck: &Deck) -> u64 {
+    let normalize = |cards: &mut dyn Iterator<Item = &Card>| {
+        let mut board: BTreeMap<String, u32> = BTreeMap::new();
+        for card in cards {
+            *board.entry(card.name.trim().to_lowercase()).or_insert(0) += card.count;
+        }
+        board
+    };
+    let main = normalize(&mut deck.mainboard.iter().chain(&deck.commander).flatten());
+    let side = normalize(&mut deck.sideboard.iter().flatten());
+
+    let mut hasher = DefaultHasher::new();
+    main.hash(&mut hasher);
+    side.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Keep one copy of each distinct list across all files, preferring the
+/// highest-weighted (most recent) occurrence
+fn unique_decks(mut decks: Vec<WeightedDeck>) -> Vec<WeightedDeck> {
+    decks.sort_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap());
+    let mut seen = HashSet::new();
+    decks.retain(|deck| seen.insert(deck.fingerprint));
+    decks
+}
+
+/// Aggregate individually collected decks like `process_file` aggregates files
+fn counts_from_decks(decks: &[WeightedDeck], options: &ProcessOptions) -> CardCounts {
+    let mut counts = CardCounts::default();
+    let mut files = HashSet::new();
+    // Junk names count once per file, as they do per file summary
+    let mut dropped = HashSet::new();
+    for deck in decks {
+        files.insert(&deck.file);
+        *counts.decks_by_format.entry(deck.format.clone()).or_insert(0.0) += deck.weight;
+        counts.decks += 1;
+        let sizes = counts.deck_sizes.entry(deck.format.clone()).or_default();
+        if deck.within_size {
+            sizes.within += 1;
+        } else {
+            sizes.outside += 1;
+        }
+        for (name, count) in &deck.cards {
+            if !name_long_enough(name, options.min_name_length) {
+                dropped.insert((&deck.file, name));
+                continue;
+            }
+            let weighted = *count as f64 * deck.weight;
+            *counts.cards.entry(name.clone()).or_insert(0.0) += weighted;
+            if options.by_format {
+                *counts
+                    .cards_by_format
+                    .entry(deck.format.clone())
+                    .or_default()
+                    .entry(name.clone())
+                    .or_insert(0.0) += if options.format_presence { deck.weight } else { weighted };
+            }
+            if options.track_raw {
+                *counts.raw_cards.entry(name.clone()).or_insert(0.0) += (*count * deck.multiplier) as f64;
+            }
+            if options.track_presence {
+                *counts.presence_cards.entry(name.clone()).or_insert(0.0) += deck.weight;
+            }
+            if options.track_dates {
+                widen_dates(&mut counts.card_dates, name.clone(), (deck.date.clone(), deck.date.clone()));
+            }
+        }
+    }
+    counts.files = files.len() as u64;
+    counts.dropped_names = dropped.len() as u64;
+    counts
+}
+
+/// Resample decks with replacement and report the rank spread of each card in `top`.
+/// Cards missing from a resample are ranked after every card that appeared.
+fn bootstrap_ranks<R: Rng>(
+    decks: &[WeightedDeck],
+    top: &[String],
+    iterations: usize,
+    rng: &mut R,
+) -> Vec<RankStats> {
+    let mut ranks: Vec<Vec<f64>> = vec![Vec::with_capacity(iterations); top.len()];
+
+    for _ in 0..iterations {
+        let mut counts: HashMap<&str, f64> = HashMap::new();
+        for _ in 0..decks.len() {
+            let deck = &decks[rng.gen_range(0..decks.len())];
+            for (name, count) in &deck.cards {
+                *counts.entry(name.as_str()).or_insert(0.0) += *count as f64 * deck.weight;
+            }
+        }
+
+        let mut sorted: Vec<_> = counts.into_iter().collect();
+        sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(b.0)));
+        let rank_of: HashMap<&str, usize> = sorted
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (*name, i + 1))
+            .collect();
+
+        for (card, card_ranks) in top.iter().zip(ranks.iter_mut()) {
+            let rank = rank_of.get(card.as_str()).copied().unwrap_or(sorted.len() + 1);
+            card_ranks.push(rank as f64);
+        }
+    }
+
+    ranks.iter().map(|r| mean_and_stddev(r)).collect()
+}
+
+/// Population mean and standard deviation
+fn mean_and_stddev(values: &[f64]) -> RankStats {
+    let n = values.len().max(1) as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    RankStats {
+        mean,
+        stddev: variance.sqrt(),
+    }
+}
+
+/// Mean and standard deviation of each card's deck share (0-1) across all
+/// analyzed formats; a format where the card isn't played counts as 0
+fn cross_format_share_stats(counts: &CardCounts, names: &[String]) -> Vec<RankStats> {
+    let mut formats: Vec<(&String, f64)> = counts
+        .decks_by_format
+        .iter()
+        .filter(|(_, &decks)| decks > 0.0)
+        .map(|(format, &decks)| (format, decks))
+        .collect();
+    formats.sort_by(|a, b| a.0.cmp(b.0));
+
+    names
+        .iter()
+        .map(|name| {
+            let shares: Vec<f64> = formats
+                .iter()
+                .map(|(format, decks)| {
+                    let played = counts
+                        .cards_by_format
+                        .get(*format)
+                        .and_then(|cards| cards.get(name))
+                        .copied()
+                        .unwrap_or(0.0);
+                    played / decks
+                })
+                .collect();
+            mean_and_stddev(&shares)
+        })
+        .collect()
+}
+
+/// Ranked cards for one requested format under --split-formats
+#[derive(Debug)]
+struct FormatSection {
+    format: String,
+    decks: f64,
+    cards: Vec<(String, f64)>,
+}
+
+/// Split per-format counts into one ranked section per requested format
+/// pattern, in the order given. Each tournament format goes to the first
+/// pattern it matches, so overlapping patterns don't count a deck twice
+fn split_by_format(counts: &CardCounts, scope: &ScopeFilter, exclude_basics: bool) -> Vec<FormatSection> {
+    let patterns = &scope.format_patterns;
+    let mut sections: Vec<FormatSection> = patterns
+        .iter()
+        .map(|pattern| FormatSection {
+            format: pattern.clone(),
+            decks: 0.0,
+            cards: Vec::new(),
+        })
+        .collect();
+    let mut cards: Vec<HashMap<String, f64>> = vec![HashMap::new(); patterns.len()];
+
+    for (format, &decks) in &counts.decks_by_format {
+        let Some(i) = patterns.iter().position(|p| scope.pattern_matches(format, p)) else {
+            continue;
+        };
+        sections[i].decks += decks;
+        for (name, count) in counts.cards_by_format.get(format).into_iter().flatten() {
+            *cards[i].entry(name.clone()).or_insert(0.0) += count;
+        }
+    }
+
+    for (section, mut cards) in sections.iter_mut().zip(cards) {
+        if exclude_basics {
+            remove_basic_lands(&mut cards);
+        }
+        let mut sorted: Vec<_> = cards.into_iter().collect();
+        sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        section.cards = sorted;
+    }
+    sections
+}
+
+/// Expected mainboard size and tolerance for a format
+fn expected_deck_size(format: &str) -> (u32, u32) {
+    let format = format.to_lowercase();
+    EXPECTED_DECK_SIZES
+        .iter()
+        .find(|(pattern, _, _)| format.contains(pattern))
+        .map(|&(_, size, tolerance)| (size, tolerance))
+        .unwrap_or(DEFAULT_DECK_SIZE)
+}
+
+/// Whether a mainboard of `size` cards is plausible for the format
+fn deck_size_within_tolerance(format: &str, size: u32) -> bool {
+    let (expected, tolerance) = expected_deck_size(format);
+    size.abs_diff(expected) <= tolerance
+}
+
+/// Percentage of (weighted) decks accounted for by a (weighted) deck count
+fn deck_share_percent(decks_with_card: f64, total_decks: f64) -> f64 {
+    if total_decks > 0.0 {
+        decks_with_card / total_decks * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Count cards played alongside the seed cards in a single file.
+/// Seed cards themselves are not counted.
+fn co_occur_file(
+    path: &Path,
+    scope: &ScopeFilter,
+    seeds: &[CardCriterion],
+    match_options: &MatchOptions,
+    options: &ProcessOptions,
+) -> HashMap<String, f64> {
+    let mut cards: HashMap<String, f64> = HashMap::new();
+    let ScopedFile { age, data, .. } = match load_scoped_file(path, scope) {
+        Some(f) => f,
+        None => return cards,
+    };
+
+    let weight = options.weight(age);
+    let seed_names: HashSet<String> = seeds.iter().map(|c| c.name.to_lowercase()).collect();
+
+    if let Some(decks) = data.decks {
+        for deck in decks {
+            if deck_matches_criteria(&deck, seeds, match_options).is_none() {
+                continue;
+            }
+            let boards = deck.mainboard.iter().chain(&deck.sideboard).chain(&deck.commander).flatten();
+            for card in boards {
+                if seed_names.contains(&card.name.to_lowercase()) {
+                    continue;
+                }
+                *cards.entry(card.name.clone()).or_insert(0.0) += card.count as f64 * weight;
+            }
+        }
+    }
+
+    cards
+}
+
+/// Weighted count of seed-matching decks in a single file playing each pair
+/// of the indexed cards, as a flattened square matrix. The diagonal holds
+/// decks playing the card at all.
+fn co_play_matrix_file(
+    path: &Path,
+    scope: &ScopeFilter,
+    seeds: &[CardCriterion],
+    match_options: &MatchOptions,
+    options: &ProcessOptions,
+    index: &HashMap<String, usize>,
+) -> Vec<f64> {
+    let size = index.len();
+    let mut matrix = vec![0.0; size * size];
+    let Some(ScopedFile { age, data, .. }) = load_scoped_file(path, scope) else {
+        return matrix;
+    };
+
+    let weight = options.weight(age);
+    for deck in data.decks.into_iter().flatten() {
+        if deck_matches_criteria(&deck, seeds, match_options).is_none() {
+            continue;
+        }
+        let present: BTreeSet<usize> = deck
+            .mainboard
+            .iter()
+            .chain(&deck.sideboard)
+            .chain(&deck.commander)
+            .flatten()
+            .filter_map(|card| index.get(&card.name).copied())
+            .collect();
+        for &row in &present {
+            for &col in &present {
+                matrix[row * size + col] += weight;
+            }
+        }
+    }
+
+    matrix
+}
+
+/// Quote a CSV field if it contains a comma, quote or newline
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Write a square matrix with card names as the header row and first column
+fn write_matrix_csv(out: &mut dyn Write, names: &[String], matrix: &[f64]) -> std::io::Result<()> {
+    let header: Vec<String> = names.iter().map(|n| csv_field(n)).collect();
+    writeln!(out, ",{}", header.join(","))?;
+    for (row, name) in names.iter().enumerate() {
+        let cells: Vec<String> = matrix[row * names.len()..(row + 1) * names.len()]
+            .iter()
+            .map(|v| format!("{:.2}", v))
+            .collect();
+        writeln!(out, "{},{}", csv_field(name), cells.join(","))?;
+    }
+    Ok(())
+}
+
+/// One player's decks across the in-scope events
+#[derive(Debug, Default)]
+struct PlayerTally {
+    /// Player name as first seen
+    name: String,
+    decks: u64,
+    best_placement: Option<u32>,
+    /// Copies of each non-basic card over the mainboards and command zones
+    cards: HashMap<String, u32>,
+}
+
+impl PlayerTally {
+    fn merge(mut self, other: PlayerTally) -> PlayerTally {
+        if self.name.is_empty() {
+            self.name = other.name;
+        }
+        self.decks += other.decks;
+        self.best_placement = match (self.best_placement, other.best_placement) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        for (card, count) in other.cards {
+            *self.cards.entry(card).or_insert(0) += count;
+        }
+        self
+    }
+}
+
+/// A row of the player-stats output
+#[derive(Debug, PartialEq)]
+struct PlayerStats {
+    name: String,
+    decks: u64,
+    best_placement: Option<u32>,
+    /// Non-basic card with the most copies over all the player's decks
+    top_card: Option<String>,
+}
+
+/// Tally the decks of a single file by player, keyed by lowercased name
+fn player_tallies_file(path: &Path, scope: &ScopeFilter) -> HashMap<String, PlayerTally> {
+    let mut tallies: HashMap<String, PlayerTally> = HashMap::new();
+    let Some(ScopedFile { data, .. }) = load_scoped_file(path, scope) else {
+        return tallies;
+    };
+
+    for deck in data.decks.into_iter().flatten() {
+        let Some(player) = deck.player.as_deref().map(str::trim).filter(|p| !p.is_empty()) else {
+            continue;
+        };
+        let tally = tallies.entry(player.to_lowercase()).or_default();
+        if tally.name.is_empty() {
+            tally.name = player.to_string();
+        }
+        tally.decks += 1;
+        let placement = deck.result.as_deref().and_then(parse_placement);
+        tally.best_placement = match (tally.best_placement, placement) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        for card in deck.mainboard.iter().chain(&deck.commander).flatten() {
+            if !is_basic_land(&card.name) {
+                *tally.cards.entry(card.name.clone()).or_insert(0) += card.count;
+            }
+        }
+    }
+
+    tallies
+}
+
+/// Per-player deck counts, best finishes and most played cards, most decks first
+fn player_stats(files: &[PathBuf], scope: &ScopeFilter) -> Vec<PlayerStats> {
+    let tallies = files
+        .par_iter()
+        .map(|path| player_tallies_file(path, scope))
+        .reduce(HashMap::new, |mut acc, tallies| {
+            for (key, tally) in tallies {
+                let entry = acc.remove(&key).unwrap_or_default();
+                acc.insert(key, entry.merge(tally));
+            }
+            acc
+        });
+
+    let mut stats: Vec<PlayerStats> = tallies
+        .into_values()
+        .map(|tally| PlayerStats {
+            top_card: tally
+                .cards
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+                .map(|(card, _)| card.clone()),
+            name: tally.name,
+            decks: tally.decks,
+            best_placement: tally.best_placement,
+        })
+        .collect();
+    stats.sort_by(|a, b| b.decks.cmp(&a.decks).then(a.name.cmp(&b.name)));
+    stats
+}
+
+/// Raw deck counts behind a conditional share
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ConditionalCounts {
+    /// Decks running card A
+    with_a: u64,
+    /// Decks running both card A and card B
+    with_both: u64,
+}
+
+impl ConditionalCounts {
+    fn merge(self, other: ConditionalCounts) -> ConditionalCounts {
+        ConditionalCounts {
+            with_a: self.with_a + other.with_a,
+            with_both: self.with_both + other.with_both,
+        }
+    }
+
+    /// P(B | A), or `None` if no deck runs A
+    fn share(&self) -> Option<f64> {
+        (self.with_a > 0).then(|| self.with_both as f64 / self.with_a as f64)
+    }
+}
+
+/// Count the in-scope decks of a file running card A, and those also running card B
+fn conditional_file(
+    path: &Path,
+    scope: &ScopeFilter,
+    card_a: &CardCriterion,
+    card_b: &CardCriterion,
+    match_options: &MatchOptions,
+) -> ConditionalCounts {
+    let mut counts = ConditionalCounts::default();
+    let data = match load_scoped_file(path, scope) {
+        Some(f) => f.data,
+        None => return counts,
+    };
+
+    for deck in data.decks.iter().flatten() {
+        if deck_matches_criteria(deck, std::slice::from_ref(card_a), match_options).is_none() {
+            continue;
+        }
+        counts.with_a += 1;
+        if deck_matches_criteria(deck, std::slice::from_ref(card_b), match_options).is_some() {
+            counts.with_both += 1;
+        }
+    }
+
+    counts
+}
+
+/// Load a single deck for deck-diff. `spec` is a tournament file with an
+/// optional `#N` (1-based deck number) or `@player` suffix, or a plain-text
+/// decklist.
+fn load_deck_source(spec: &str) -> Result<Deck, String> {
+    if !spec.ends_with(".json") && !spec.contains(".json#") && !spec.contains(".json@") {
+        let text = std::fs::read_to_string(spec).map_err(|e| format!("Failed to read {}: {}", spec, e))?;
+        let (mainboard, sideboard) = parse_text_decklist(&text);
+        return Ok(Deck {
+            player: None,
+            result: None,
+            url: None,
+            mainboard: Some(mainboard),
+            sideboard: Some(sideboard),
+            commander: None,
+            wins: None,
+            match_record: None,
+        });
+    }
+
+    let (path, selector) = match spec.rfind(".json") {
+        Some(i) => spec.split_at(i + ".json".len()),
+        None => (spec, ""),
+    };
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let data: DecklistFile = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+    let decks = data.decks.unwrap_or_default();
+
+    let deck = if let Some(player) = selector.strip_prefix('@') {
+        decks
+            .into_iter()
+            .find(|d| d.player.as_deref().is_some_and(|p| p.eq_ignore_ascii_case(player)))
+            .ok_or_else(|| format!("No deck by {} in {}", player, path))?
+    } else {
+        let number = match selector.strip_prefix('#') {
+            Some(n) => n.parse::<usize>().map_err(|_| format!("Invalid deck number '{}'", n))?,
+            None => 1,
+        };
+        decks
+            .into_iter()
+            .nth(number.wrapping_sub(1))
+            .ok_or_else(|| format!("No deck {} in {}", number, path))?
+    };
+    Ok(deck)
+}
+
+/// A card whose count differs between two boards
+#[derive(Debug, PartialEq)]
+struct CardDiff {
+    name: String,
+    before: u32,
+    after: u32,
+}
+
+/// Cards whose counts differ between two boards, sorted by name
+fn diff_boards(before: &[Card], after: &[Card]) -> Vec<CardDiff> {
+    let tally = |cards: &[Card]| {
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for card in cards {
+            *counts.entry(card.name.clone()).or_insert(0) += card.count;
+        }
+        counts
+    };
+    let before = tally(before);
+    let after = tally(after);
+
+    let names: HashSet<&String> = before.keys().chain(after.keys()).collect();
+    let mut diffs: Vec<CardDiff> = names
+        .into_iter()
+        .map(|name| CardDiff {
+            name: name.clone(),
+            before: before.get(name).copied().unwrap_or(0),
+            after: after.get(name).copied().unwrap_or(0),
+        })
+        .filter(|d| d.before != d.after)
+        .collect();
+    diffs.sort_by(|a, b| a.name.cmp(&b.name));
+    diffs
+}
+
+/// Collect dated JSON files (under a /YYYY/MM/DD/ path) from a directory,
+/// keeping only paths (relative to `search_dir`) that match `include` if
+/// given and don't match `exclude`. Undated files such as schemas or indexes
+/// are never decklists, so they are left out without being opened.
+fn collect_json_files(search_dir: &str, include: Option<&GlobSet>, exclude: Option<&GlobSet>) -> Vec<PathBuf> {
+    WalkDir::new(search_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_type().is_file()
+                && e.path().extension().is_some_and(|ext| ext == "json")
+                && extract_date_from_path(&e.path().to_string_lossy()).is_some()
+        })
+        .filter(|e| {
+            let relative = e.path().strip_prefix(search_dir).unwrap_or(e.path());
+            include.is_none_or(|globs| globs.is_match(relative)) && !exclude.is_some_and(|globs| globs.is_match(relative))
+        })
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Compile the patterns of a glob flag such as --include-glob into one set;
+/// `None` if there are none
+fn build_globs(flag: &str, patterns: &[String]) -> Result<Option<GlobSet>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("invalid {} '{}': {}", flag, pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+/// Collect JSON files from several directories. Files reachable through more
+/// than one directory (e.g. overlapping mounts) are only listed once.
+fn collect_json_files_from(
+    search_dirs: &[String],
+    include: Option<&GlobSet>,
+    exclude: Option<&GlobSet>,
+) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    search_dirs
+        .iter()
+        .flat_map(|dir| collect_json_files(dir, include, exclude))
+        .filter(|path| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())))
+        .collect()
+}
+
+/// Read a list of JSON file paths, one per line, keeping the ones a directory
+/// walk would: dated .json paths passing the globs. Blank lines and repeats
+/// are dropped.
+fn read_file_list(reader: impl BufRead, include: Option<&GlobSet>, exclude: Option<&GlobSet>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "json")
+                && extract_date_from_path(&path.to_string_lossy()).is_some()
+                && include.is_none_or(|globs| globs.is_match(path))
+                && !exclude.is_some_and(|globs| globs.is_match(path))
+        })
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
+/// Parse --colors into a bit mask, one bit per color in WUBRG order. "C"
+/// (colorless) adds no colors, so "C" alone allows only colorless decks.
+fn parse_color_mask(value: &str) -> Result<u8, String> {
+    if value.trim().is_empty() {
+        return Err("no colors given; use letters from WUBRG, or C for colorless".to_string());
+    }
+    value.trim().chars().try_fold(0, |mask, c| match c.to_ascii_uppercase() {
+        'C' => Ok(mask),
+        c => match "WUBRG".find(c) {
+            Some(bit) => Ok(mask | 1 << bit),
+            None => Err(format!("'{}' is not a color; use letters from WUBRG, or C for colorless", c)),
+        },
+    })
+}
+
+/// Parse a --sample fraction, which must lie in (0, 1]
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let fraction: f64 = value.parse().map_err(|_| format!("'{}' is not a number", value))?;
+    if fraction > 0.0 && fraction <= 1.0 {
+        Ok(fraction)
+    } else {
+        Err(format!("{} is not between 0 (exclusive) and 1", value))
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, its output is fixed across Rust
+/// versions and platforms, so a --seed reproduces the same sample anywhere.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Keep each file with probability `fraction`. The draw for a file depends
+/// only on the seed and its path, so a seed picks the same subset regardless
+/// of walk order or other files coming and going.
+fn sample_files(files: Vec<PathBuf>, fraction: f64, seed: u64) -> Vec<PathBuf> {
+    files
+        .into_iter()
+        .filter(|path| {
+            let mut bytes = seed.to_le_bytes().to_vec();
+            bytes.extend_from_slice(path.as_os_str().as_encoded_bytes());
+            // The top 53 bits, where the multiply has mixed in every byte
+            let draw = (fnv1a(&bytes) >> 11) as f64 / (1u64 << 53) as f64;
+            draw < fraction
+        })
+        .collect()
+}
+
+/// The JSON files a command should process, honoring --dir, --fetch,
+/// --files-from, --include-glob, --exclude-glob and --sample
+fn input_files(args: &Args) -> Vec<PathBuf> {
+    let files = listed_files(args);
+    if let Some(message) = no_files_error(args, &files) {
+        eprintln!("Error: {}", message);
+        std::process::exit(2);
+    }
+    match args.sample {
+        Some(fraction) => {
+            let seed = args.seed.unwrap_or_else(|| rand::thread_rng().gen());
+            let total = files.len();
+            let files = sample_files(files, fraction, seed);
+            progress!("Sampled {} of {} files (--seed {})", files.len(), total, seed);
+            files
+        }
+        None => files,
+    }
+}
+
+/// Error for a run that found no files at all, usually a wrong --dir;
+/// `None` if files were found or --allow-empty is set
+fn no_files_error(args: &Args, files: &[PathBuf]) -> Option<String> {
+    if !files.is_empty() || args.allow_empty {
+        return None;
+    }
+    let source = match &args.files_from {
+        Some(path) => format!("--files-from {}", path),
+        None => resolve_search_dirs(args).join(", "),
+    };
+    Some(format!(
+        "No dated JSON files found in {} (pass --allow-empty if that is expected)",
+        source
+    ))
+}
+
+/// Every JSON file in scope of --dir, --fetch, --files-from and the globs
+fn listed_files(args: &Args) -> Vec<PathBuf> {
+    let globs = |flag: &str, patterns: &[String]| {
+        build_globs(flag, patterns).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let include = globs("--include-glob", &args.include_globs);
+    let exclude = globs("--exclude-glob", &args.exclude_globs);
+    match args.files_from.as_deref() {
+        Some("-") => read_file_list(std::io::stdin().lock(), include.as_ref(), exclude.as_ref()),
+        Some(path) => match File::open(path) {
+            Ok(file) => read_file_list(BufReader::new(file), include.as_ref(), exclude.as_ref()),
+            Err(e) => {
+                eprintln!("Error: Failed to read --files-from {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => collect_json_files_from(&resolve_search_dirs(args), include.as_ref(), exclude.as_ref()),
+    }
+}
+
+/// Split a comma-separated list of directories
+fn split_dirs(dirs: &str) -> Vec<String> {
+    dirs.split(',')
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+/// The --data-dir that --fetch clones into: the first of a comma-separated
+/// list, since there is only one --data-repo. The others, e.g. a private
+/// mirror, are merged as they are.
+fn fetch_dir(data_dir: &str) -> Option<String> {
+    split_dirs(data_dir).into_iter().next()
+}
+
+/// Directories to walk for JSON files, honoring --dir and --fetch
+fn resolve_search_dirs(args: &Args) -> Vec<String> {
+    match &args.dir {
+        Some(dir) => split_dirs(dir),
+        None if args.fetch => split_dirs(&args.data_dir),
+        None => vec![".".to_string()],
+    }
+}
+
+/// Split the comma-separated --formats value into lowercase patterns,
+/// expanding known aliases. Unknown tokens are kept as literal patterns.
+fn parse_format_patterns(formats: &str) -> Vec<String> {
+    formats
+        .split(',')
+        .map(|s| {
+            let token = s.trim().to_lowercase();
+            FORMAT_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == token)
+                .map(|(_, format)| format.to_string())
+                .unwrap_or(token)
+        })
+        .collect()
+}
+
+/// A single line of top-cards output
+struct OutputLine {
+    rank: usize,
+    name: String,
+    count: f64,
+    /// Unweighted count, with --show-raw
+    raw: Option<f64>,
+    /// USD price with --with-price; the inner None means Scryfall has no price
+    price: Option<Option<f64>>,
+    /// First and last date the card was seen, with --dates
+    dates: Option<(String, String)>,
+    /// Weighted number of decks running the card, with --detailed
+    presence: Option<f64>,
+}
+
+/// Output lines for bucketed totals, ranked in bucket order
+fn bucket_lines(buckets: Vec<(&'static str, f64)>) -> Vec<OutputLine> {
+    buckets
+        .into_iter()
+        .enumerate()
+        .map(|(i, (bucket, count))| OutputLine {
+            rank: i + 1,
+            name: bucket.to_string(),
+            count,
+            raw: None,
+            price: None,
+            dates: None,
+            presence: None,
+        })
+        .collect()
+}
+
+/// Reorder output lines for --sort. Lines sharing a rank (a card and its back
+/// face) move together, ordered by the first of them; ties keep rank order.
+fn sort_output_lines(lines: Vec<OutputLine>, order: SortOrder) -> Vec<OutputLine> {
+    let mut groups: Vec<Vec<OutputLine>> = Vec::new();
+    for line in lines {
+        match groups.last_mut() {
+            Some(group) if group[0].rank == line.rank => group.push(line),
+            _ => groups.push(vec![line]),
+        }
+    }
+    match order {
+        SortOrder::WeightDesc => groups.sort_by(|a, b| b[0].count.partial_cmp(&a[0].count).unwrap()),
+        SortOrder::WeightAsc => groups.sort_by(|a, b| a[0].count.partial_cmp(&b[0].count).unwrap()),
+        SortOrder::Name => groups.sort_by_cached_key(|group| group[0].name.to_lowercase()),
+    }
+    groups.into_iter().flatten().collect()
+}
+
+/// Sort cards by name, ignoring case, for --distinct-only
+fn alphabetical(mut cards: Vec<(String, f64)>) -> Vec<(String, f64)> {
+    cards.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()).then_with(|| a.0.cmp(&b.0)));
+    cards
+}
+
+/// Render an output line as a single-line JSON object for --jsonl
+fn jsonl_line(line: &OutputLine) -> String {
+    let mut object = serde_json::json!({"name": line.name, "weight": line.count});
+    if let Some(raw) = line.raw {
+        object["raw"] = raw.into();
+    }
+    if let Some(price) = line.price {
+        object["price_usd"] = price.into();
+    }
+    if let Some((first, last)) = &line.dates {
+        object["first_seen"] = first.as_str().into();
+        object["last_seen"] = last.as_str().into();
+    }
+    object.to_string()
+}
+
+/// Columns added by --detailed: the share of decks running a card and its
+/// average copies in those decks, e.g. " 50.0% 3.00"
+fn detailed_columns(copies: f64, presence: f64, total_decks: f64) -> String {
+    let average = if presence > 0.0 { copies / presence } else { 0.0 };
+    format!(" {:.1}% {:.2}", deck_share_percent(presence, total_decks), average)
+}
+
+/// Price column appended to a plain-text line with --with-price: "$1.23", or
+/// "-" for cards without a price
+fn price_suffix(price: Option<Option<f64>>) -> String {
+    match price {
+        Some(Some(usd)) => format!(" ${:.2}", usd),
+        Some(None) => " -".to_string(),
+        None => String::new(),
+    }
+}
+
+/// A piece of a parsed --template
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Rank,
+    Name,
+    /// Weighted count, with optional decimal precision (default 2)
+    Count(Option<usize>),
+    /// Deck share percentage, with optional decimal precision (default 1)
+    Share(Option<usize>),
+    /// Scryfall type line
+    Type,
+}
+
+/// Parse a --template string such as "{rank}. {name} ({count:.0})".
+/// Use "{{" and "}}" for literal braces.
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(format!("unclosed placeholder '{{{}'", placeholder)),
+                    }
+                }
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(parse_placeholder(&placeholder)?);
+            }
+            '}' => return Err("unmatched '}'".to_string()),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+
+    Ok(parts)
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<TemplatePart, String> {
+    let (name, spec) = match placeholder.split_once(':') {
+        Some((name, spec)) => (name, Some(spec)),
+        None => (placeholder, None),
+    };
+    let precision = match spec {
+        Some(spec) => Some(
+            spec.strip_prefix('.')
+                .and_then(|p| p.parse::<usize>().ok())
+                .ok_or_else(|| format!("invalid format spec '{}' in '{{{}}}'", spec, placeholder))?,
+        ),
+        None => None,
+    };
+
+    match (name, precision) {
+        ("count", _) => Ok(TemplatePart::Count(precision)),
+        ("share", _) => Ok(TemplatePart::Share(precision)),
+        ("rank", None) => Ok(TemplatePart::Rank),
+        ("name", None) => Ok(TemplatePart::Name),
+        ("type", None) => Ok(TemplatePart::Type),
+        ("rank" | "name" | "type", Some(_)) => {
+            Err(format!("'{{{}}}' does not take a precision", name))
+        }
+        _ => Err(format!("unknown placeholder '{{{}}}'", placeholder)),
+    }
+}
+
+/// Render one output line using a parsed template
+fn render_template(
+    template: &[TemplatePart],
+    line: &OutputLine,
+    share: f64,
+    type_line: Option<&str>,
+) -> String {
+    let mut out = String::new();
+    for part in template {
+        match part {
+            TemplatePart::Literal(s) => out.push_str(s),
+            TemplatePart::Rank => out.push_str(&line.rank.to_string()),
+            TemplatePart::Name => out.push_str(&line.name),
+            TemplatePart::Count(p) => out.push_str(&format!("{:.*}", p.unwrap_or(2), line.count)),
+            TemplatePart::Share(p) => out.push_str(&format!("{:.*}", p.unwrap_or(1), share)),
+            TemplatePart::Type => out.push_str(type_line.unwrap_or("-")),
+        }
+    }
+    out
+}
+
+/// Run the top-cards command
+fn run_top_cards(args: &Args, top_args: &TopCardsArgs) {
+    let scope = ScopeFilter::from_args(args);
+    let template = match top_args.template.as_deref().map(parse_template).transpose() {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Error: invalid --template: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if top_args.window <= 0.0 {
+        eprintln!("Error: --window must be positive");
+        std::process::exit(1);
+    }
+    if top_args.since_file.is_some() && top_args.weight_function != WeightFunction::Exp {
+        // Stored totals are aged by halving, which only holds for exponential decay
+        eprintln!("Error: --since-file only supports --weight-function exp");
+        std::process::exit(1);
+    }
+    let options = ProcessOptions {
+        half_life: top_args.half_life,
+        use_weight: !top_args.no_weight,
+        weight_function: top_args.weight_function,
+        window: top_args.window,
+        presence: top_args.percent,
+        url_resolver: top_args.resolve_urls.then(|| Arc::new(UrlResolver::new())),
+        dedupe: top_args.dedupe,
+        min_name_length: top_args.min_name_length,
+        skip_sideboard: top_args.skip_sideboard || top_args.board == Board::Main,
+        skip_mainboard: top_args.board == Board::Side,
+        max_decks: top_args.max_decks_per_event,
+        require_url: top_args.require_url,
+        win_weight: top_args.win_weight,
+        renames: (top_args.canonicalize || top_args.fold_basics).then(|| {
+            let canonical = if top_args.canonicalize {
+                progress!("Loading canonical card names...");
+                let canonical = resolve_canonical_names(args.scryfall_bulk);
+                progress!("Loaded {} alternate card names", canonical.len());
+                canonical
+            } else {
+                HashMap::new()
+            };
+            Arc::new(CardRenames {
+                canonical,
+                fold_basics: top_args.fold_basics,
+            })
+        }),
+        by_format: top_args.cross_format_stability || top_args.split_formats,
+        format_presence: top_args.cross_format_stability,
+        track_raw: top_args.show_raw,
+        track_dates: top_args.dates,
+        track_presence: top_args.detailed,
+        size_cap: top_args.size_weight.then_some(top_args.size_cap),
+    }
+    .with_count_mode(top_args.count_mode);
+
+    if top_args.debug_weights {
+        eprintln!("Age (days)  Weight");
+        for (age, weight) in weight_table(&options) {
+            eprintln!("{:>10}  {:.4}", age, weight);
+        }
+    }
+
+    let cache = if top_args.no_cache {
+        None
+    } else {
+        Some(FileCache::load(file_cache_path()))
+    };
+
+    let aggregate_settings = AggregateSettings::new(&scope, &options, &args.include_globs, &args.exclude_globs);
+    let aggregate = top_args
+        .since_file
+        .as_ref()
+        .and_then(|path| Aggregate::load(Path::new(path), &aggregate_settings));
+
+    let mut files = input_files(args);
+    if let Some(aggregate) = &aggregate {
+        files = files_after(files, aggregate.marker);
+        progress!("Resuming totals from {} ({} new files)", top_args.since_file.as_deref().unwrap_or_default(), files.len());
+    }
+    progress!("Processing {} files...", files.len());
+
+    // Process files in parallel and merge results
+    let mut counts: CardCounts = if top_args.rank_decay {
+        // Recency ranks are only known once every matching tournament is
+        // counted, so tally unweighted and apply the weights afterwards
+        let unweighted = ProcessOptions {
+            use_weight: false,
+            ..options.clone()
+        };
+        let tournaments: Vec<(i64, CardCounts)> = files
+            .par_iter()
+            .filter_map(|path| {
+                let (age, _) = file_age(path, &scope)?;
+                let counts = process_file(path, &scope, &unweighted, cache.as_ref());
+                (counts.total_decks() > 0.0).then_some((age, counts))
+            })
+            .collect();
+        merge_rank_decayed(tournaments)
+    } else if top_args.unique_decks {
+        // Duplicates can only be told apart once every file is read, so collect
+        // decks individually instead of folding per file
+        let all_decks: Vec<WeightedDeck> = files
+            .par_iter()
+            .filter_map(|path| {
+                let decks = collect_weighted_decks(path, &scope, &options);
+                if decks.is_none() {
+                    note_unparseable(path);
+                }
+                decks
+            })
+            .flatten()
+            .collect();
+        let total = all_decks.len();
+        let decks = unique_decks(all_decks);
+        progress!("Kept {} unique decks out of {}", decks.len(), total);
+        counts_from_decks(&decks, &options)
+    } else {
+        par_merge_counts(&files, |path| process_file(path, &scope, &options, cache.as_ref()))
+    };
+
+    if let Some(cache) = cache {
+        if let Err(e) = cache.save() {
+            eprintln!("Warning: Failed to update file cache: {}", e);
+        }
+    }
+
+    if let Some(path) = &top_args.since_file {
+        let previous_marker = aggregate.as_ref().map_or(i64::MIN, |a| a.marker);
+        if let Some(aggregate) = &aggregate {
+            counts = counts.merge(aggregate.decayed_counts(scope.today));
+        }
+        let marker = files
+            .iter()
+            .filter(|path| file_age(path, &scope).is_some())
+            .filter_map(|path| file_day(path))
+            .fold(previous_marker, i64::max);
+        let updated = Aggregate::from_counts(&counts, aggregate_settings, scope.today, marker);
+        if let Err(e) = updated.save(Path::new(path)) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    let total_decks = counts.total_decks();
+
+    // The default list names formats many runs don't care about
+    let unmatched = unmatched_format_patterns(&scope, &counts);
+    if args.formats_set && !unmatched.is_empty() && !files.is_empty() {
+        eprintln!(
+            "Warning: No decks matched format(s): {} (check the spelling or widen --max-age)",
+            unmatched.join(", ")
+        );
+    }
+
+    if counts.dropped_names > 0 {
+        progress!(
+            "Dropped {} card entries with names shorter than {} characters",
+            counts.dropped_names,
+            top_args.min_name_length.max(1)
+        );
+    }
+
+    if top_args.size_sanity {
+        let mut formats: Vec<_> = counts.deck_sizes.iter().collect();
+        formats.sort_by(|a, b| a.0.cmp(b.0));
+        eprintln!("Deck size sanity:");
+        for (format, tally) in formats {
+            let (expected, tolerance) = expected_deck_size(format);
+            eprintln!(
+                "  {}: {} within, {} outside (expected {}±{})",
+                format, tally.within, tally.outside, expected, tolerance
+            );
+        }
+    }
+
+    if top_args.land_ratio {
+        progress!("Loading card type data...");
+        let ratio = land_ratio(&counts.cards, &resolve_type_lines(args.scryfall_bulk));
+        let share = ratio.land_share() * 100.0;
+        eprintln!("Lands vs spells: {:.1}% lands, {:.1}% spells", share, 100.0 - share);
+        if ratio.missing > 0 {
+            eprintln!("  ({} cards missing from Scryfall excluded)", ratio.missing);
+        }
+    }
+
+    if top_args.split_formats {
+        let mut writer = output_writer(top_args.output.as_deref());
+        let sections = split_by_format(&counts, &scope, top_args.exclude_basics);
+        for (i, section) in sections.iter().enumerate() {
+            if i > 0 {
+                writeln!(writer).unwrap();
+            }
+            writeln!(writer, "=== {} ({:.0} decks) ===", section.format, section.decks).unwrap();
+            for (name, count) in section.cards.iter().take(top_args.num) {
+                if top_args.percent {
+                    writeln!(writer, "{:.1}% {}", deck_share_percent(*count, section.decks), name).unwrap();
+                } else {
+                    writeln!(writer, "{:.2} {}", count, name).unwrap();
+                }
+            }
+        }
+        finish_top_cards(args, top_args, &scope, &counts);
+        return;
+    }
+
+    let mut cards = std::mem::take(&mut counts.cards);
+    if top_args.exclude_basics {
+        remove_basic_lands(&mut cards);
+    }
+
+    // Sort by count descending
+    let mut sorted: Vec<_> = cards.into_iter().collect();
+    sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    // Take top N cards
+    let limit = if top_args.distinct_only && !top_args.num_set { usize::MAX } else { top_args.num };
+    let mut top_cards: Vec<_> = sorted.into_iter().take(limit).collect();
+    if top_args.distinct_only {
+        top_cards = alphabetical(top_cards);
+    }
+
+    if top_args.cross_format_stability {
+        let names: Vec<String> = top_cards.iter().map(|(name, _)| name.clone()).collect();
+        let stats = cross_format_share_stats(&counts, &names);
+        let mut ranked: Vec<((String, f64), RankStats)> = top_cards.into_iter().zip(stats).collect();
+        ranked.sort_by(|a, b| {
+            let spread = a.1.stddev.partial_cmp(&b.1.stddev).unwrap();
+            let spread = if top_args.specialist { spread.reverse() } else { spread };
+            spread.then_with(|| b.1.mean.partial_cmp(&a.1.mean).unwrap())
+        });
+
+        eprintln!("Deck share across {} formats (mean ± stddev):", counts.decks_by_format.len());
+        for (i, ((name, _), stat)) in ranked.iter().enumerate() {
+            eprintln!("  {:>5} {:>6.1}% ± {:<6.1} {}", i + 1, stat.mean * 100.0, stat.stddev * 100.0, name);
+        }
+        top_cards = ranked.into_iter().map(|(card, _)| card).collect();
+    }
+
+    if let Some(iterations) = top_args.bootstrap {
+        progress!("Bootstrapping ranks over {} resamples...", iterations);
+        let decks: Vec<WeightedDeck> = files
+            .par_iter()
+            .flat_map_iter(|path| collect_weighted_decks(path, &scope, &options).into_iter().flatten())
+            .collect();
+        let names: Vec<String> = top_cards.iter().map(|(name, _)| name.clone()).collect();
+        let stats = bootstrap_ranks(&decks, &names, iterations, &mut StdRng::from_entropy());
+
+        eprintln!("Rank stability (mean ± stddev):");
+        for (i, (name, stat)) in names.iter().zip(stats).enumerate() {
+            eprintln!("  {:>5} {:>8.1} ± {:<6.1} {}", i + 1, stat.mean, stat.stddev, name);
+        }
+    }
+
+    if let Some(report_path) = &top_args.report_unknown {
+        progress!("Loading Scryfall card names...");
+        let known = resolve_oracle_names(args.scryfall_bulk);
+        if known.is_empty() {
+            eprintln!("Warning: No Scryfall data available, skipping unknown card report");
+        } else {
+            let unknown = unknown_card_names(&top_cards, &known);
+            let mut report = String::new();
+            for name in &unknown {
+                report.push_str(name);
+                report.push('\n');
+            }
+            match std::fs::write(report_path, report) {
+                Ok(()) => progress!("Wrote {} unknown card names to {}", unknown.len(), report_path),
+                Err(e) => eprintln!("Warning: Failed to write {}: {}", report_path, e),
+            }
+        }
+    }
+
+    let final_cards: Vec<OutputLine> = if top_args.by_color {
+        progress!("Loading card color data...");
+        let colors = resolve_colors(args.scryfall_bulk);
+        progress!("Loaded colors for {} cards", colors.len());
+        bucket_lines(group_by_color(&top_cards, &colors))
+    } else if top_args.by_rarity {
+        progress!("Loading card rarity data...");
+        let rarities = resolve_rarities(args.scryfall_bulk);
+        progress!("Loaded rarities for {} cards", rarities.len());
+        bucket_lines(group_by_rarity(&top_cards, &rarities))
+    } else if top_args.mana_curve {
+        progress!("Loading card mana value data...");
+        let mana_values = resolve_mana_values(args.scryfall_bulk);
+        progress!("Loaded mana values for {} cards", mana_values.len());
+        bucket_lines(group_by_mana_value(&top_cards, &mana_values))
+    } else if top_args.by_type {
+        progress!("Loading card type data...");
+        let type_lines = resolve_type_lines(args.scryfall_bulk);
+        progress!("Loaded types for {} cards", type_lines.len());
+        bucket_lines(group_by_type(&top_cards, &type_lines))
+    } else {
+        // Resolve back faces if requested
+        // An inventory lists only names actually seen
+        let back_faces = if top_args.resolve_faces && !top_args.distinct_only {
+            progress!("Loading double-faced card data...");
+            let faces = resolve_back_faces(args.scryfall_bulk);
+            progress!("Loaded {} double-faced cards", faces.len());
+            faces
+        } else {
+            HashMap::new()
+        };
+
+        let prices = if top_args.with_price {
+            progress!("Loading card price data...");
+            let prices = resolve_prices(args.scryfall_bulk);
+            progress!("Loaded prices for {} cards", prices.len());
+            prices
+        } else {
+            HashMap::new()
+        };
+        let price_of = |name: &str| top_args.with_price.then(|| prices.get(name).copied());
+
+        // Build final output: each card, plus back face if it has one (sharing its rank)
+        let mut final_cards: Vec<OutputLine> = Vec::new();
+        for (i, (name, count)) in top_cards.into_iter().enumerate() {
+            let back_face = back_faces.get(&name).cloned();
+            let raw = top_args.show_raw.then(|| counts.raw_cards.get(&name).copied().unwrap_or(0.0));
+            let price = price_of(&name);
+            let dates = top_args.dates.then(|| counts.card_dates.get(&name).cloned()).flatten();
+            let presence = top_args.detailed.then(|| counts.presence_cards.get(&name).copied().unwrap_or(0.0));
+            final_cards.push(OutputLine {
+                rank: i + 1,
+                name,
+                count,
+                raw,
+                price,
+                dates: dates.clone(),
+                presence,
+            });
+            if let Some(back_face) = back_face {
+                final_cards.push(OutputLine {
+                    rank: i + 1,
+                    price: price_of(&back_face),
+                    name: back_face,
+                    count,
+                    raw,
+                    dates,
+                    presence,
+                });
+            }
+        }
+        final_cards
+    };
+    let final_cards = match top_args.sort {
+        Some(order) => sort_output_lines(final_cards, order),
+        None => final_cards,
+    };
+
+    let type_lines = if template.as_ref().is_some_and(|t| t.contains(&TemplatePart::Type)) {
+        progress!("Loading card type data...");
+        resolve_type_lines(args.scryfall_bulk)
+    } else {
+        HashMap::new()
+    };
+
+    // Output results
+    let mut writer = output_writer(top_args.output.as_deref());
+
+    let mut ranked_rows = Vec::new();
+    for line in final_cards {
+        let share = deck_share_percent(line.count, total_decks);
+        if top_args.names_only {
+            writeln!(writer, "{}", line.name).unwrap();
+        } else if top_args.jsonl {
+            writeln!(writer, "{}", jsonl_line(&line)).unwrap();
+        } else if let Some(template) = &template {
+            let type_line = type_lines.get(&line.name).map(|t| t.as_str());
+            writeln!(writer, "{}", render_template(template, &line, share, type_line)).unwrap();
+        } else {
+            let value = if top_args.percent {
+                format!("{:.1}%", share)
+            } else {
+                format!("{:.2}", line.count)
+            };
+            let raw = line.raw.map(|r| format!(" {:.0}", r)).unwrap_or_default();
+            let dates = line.dates.as_ref().map(|(first, last)| format!(" {} {}", first, last)).unwrap_or_default();
+            let detailed = line
+                .presence
+                .map(|presence| detailed_columns(line.count, presence, total_decks))
+                .unwrap_or_default();
+            let price = price_suffix(line.price);
+            if top_args.ranked {
+                ranked_rows.push((line.rank, value, format!("{}{}{}  {}{}", raw, detailed, dates, line.name, price)));
+            } else {
+                writeln!(writer, "{}{}{}{} {}{}", value, raw, detailed, dates, line.name, price).unwrap();
+            }
+        }
+    }
+    for line in ranked_text_lines(&ranked_rows) {
+        writeln!(writer, "{}", line).unwrap();
+    }
+
+    finish_top_cards(args, top_args, &scope, &counts);
+}
+
+/// Lay out (rank, value, rest) rows for --ranked: the rank and the value are
+/// right-aligned in columns as wide as their widest entry
+fn ranked_text_lines(rows: &[(usize, String, String)]) -> Vec<String> {
+    let rank_width = rows.iter().map(|(rank, _, _)| rank.to_string().len()).max().unwrap_or(0).max(3);
+    let value_width = rows.iter().map(|(_, value, _)| value.len()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(rank, value, rest)| format!("{:>rank_width$}.  {:>value_width$}{}", rank, value, rest))
+        .collect()
+}
+
+/// Open the top-cards output file, or stdout when none is given
+fn output_writer(path: Option<&str>) -> BufWriter<Box<dyn Write>> {
+    let output: Box<dyn Write> = match path {
+        Some(path) => {
+            let file = File::create(path).expect("Failed to create output file");
+            Box::new(BufWriter::new(file))
+        }
+        None => Box::new(std::io::stdout()),
+    };
+    BufWriter::new(output)
+}
+
+/// Report where the output went and what was processed
+fn finish_top_cards(args: &Args, top_args: &TopCardsArgs, scope: &ScopeFilter, counts: &CardCounts) {
+    if let Some(path) = &top_args.output {
+        progress!("Output written to {}", path);
+    }
+
+    if let Some(path) = &top_args.manifest {
+        let manifest = run_manifest(args, top_args, scope, counts, SystemTime::now());
+        let text = serde_json::to_string_pretty(&manifest).unwrap();
+        if let Err(e) = std::fs::write(path, text + "\n") {
+            eprintln!("Error: Failed to write manifest {}: {}", path, e);
+            std::process::exit(1);
+        }
+        progress!("Manifest written to {}", path);
+    }
+
+    let mut formats: Vec<&str> = counts.decks_by_format.keys().map(|f| f.as_str()).collect();
+    formats.sort_unstable();
+    let formats = if formats.is_empty() { "none".to_string() } else { formats.join(", ") };
+    progress!("Processed {} decks across {} files (format: {})", counts.decks, counts.files, formats);
+}
+
+/// Record of how a top-cards run was produced, for --manifest
+fn run_manifest(
+    args: &Args,
+    top_args: &TopCardsArgs,
+    scope: &ScopeFilter,
+    counts: &CardCounts,
+    now: SystemTime,
+) -> serde_json::Value {
+    let scryfall_path = SCRYFALL_FILE
+        .get()
+        .cloned()
+        .unwrap_or_else(|| scryfall_cache_path(args.scryfall_bulk));
+    let scryfall_modified = std::fs::metadata(&scryfall_path).and_then(|m| m.modified()).ok();
+    let value_name = |value: Option<clap::builder::PossibleValue>| value.map(|v| v.get_name().to_string());
+
+    serde_json::json!({
+        "created": utc_timestamp(now),
+        "formats": scope.format_patterns,
+        "max_age": args.max_age,
+        "half_life": top_args.half_life,
+        "weighted": !top_args.no_weight,
+        "weight_function": value_name(clap::ValueEnum::to_possible_value(&top_args.weight_function)),
+        "window": top_args.window,
+        "count_mode": value_name(clap::ValueEnum::to_possible_value(&top_args.count_mode)),
+        "files": counts.files,
+        "decks": counts.decks,
+        "weighted_decks": counts.total_decks(),
+        "scryfall": {
+            "bulk": args.scryfall_bulk.data_type(),
+            "path": scryfall_path.display().to_string(),
+            "modified": scryfall_modified.map(utc_timestamp),
+        },
+    })
+}
+
+/// A time as an RFC 3339 UTC timestamp, e.g. 2025-01-10T08:30:00Z
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (year, month, day) = days_to_civil(secs.div_euclid(86400));
+    let secs_of_day = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Run the search-decks command
+fn run_search_decks(args: &Args, search_args: &SearchDecksArgs) {
+    let scope = ScopeFilter::from_args(args);
+
+    // Parse card criteria
+    let mut criteria: Vec<CardCriterion> = search_args
+        .cards
+        .iter()
+        .map(|s| parse_card_criterion(s))
+        .chain(search_args.exclude.iter().map(|s| CardCriterion {
+            excluded: true,
+            ..parse_card_criterion(s)
+        }))
+        .collect();
+    if search_args.regex {
+        if let Err(e) = compile_name_patterns(&mut criteria) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if search_args.match_any {
+        progress!("Searching for decks containing any of:");
+    } else {
+        progress!("Searching for decks containing:");
+    }
+    for c in &criteria {
+        if c.excluded {
+            progress!("  - not {}", c.name);
+            continue;
+        }
+        match c.count {
+            Some(_) if search_args.match_any && !search_args.at_most => {
+                progress!("  - {} (any count)", c.name)
+            }
+            Some(n) => {
+                let mode = if search_args.exact {
+                    "exact"
+                } else if search_args.at_most {
+                    "at most"
+                } else {
+                    "at least"
+                };
+                progress!("  - {} {} ({})", n, c.name, mode)
+            }
+            None => progress!("  - {} (any count)", c.name),
+        }
+    }
+
+    let match_options = MatchOptions {
+        exact: search_args.exact,
+        at_most: search_args.at_most,
+        include_sideboard: search_args.sideboard,
+        match_any: search_args.match_any,
+        require_url: search_args.require_url,
+        fuzzy: search_args.fuzzy,
+        loose: search_args.loose,
+        max_placement: None,
+        commander_only: search_args.commander_only,
+    };
+
+    let archetypes = match search_args.archetypes.as_deref().map(load_archetypes).transpose() {
+        Ok(a) => a.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let files = input_files(args);
+    progress!("Searching {} files...", files.len());
+
+    // Search files in parallel, gathering card names on the way for typo
+    // suggestions should nothing match. Fuzzy and regex queries need none.
+    let suggest = !search_args.fuzzy && !search_args.regex;
+    let (mut all_matches, names): (Vec<DeckMatch>, HashSet<String>) = files
+        .par_iter()
+        .map(|path| {
+            let mut names = HashSet::new();
+            let matches =
+                search_file_collecting_names(path, &scope, &criteria, &match_options, suggest.then_some(&mut names));
+            (matches, names)
+        })
+        .reduce(
+            || (Vec::new(), HashSet::new()),
+            |mut acc, (matches, names)| {
+                acc.0.extend(matches);
+                acc.1.extend(names);
+                acc
+            },
+        );
+    for deck_match in &mut all_matches {
+        let deck = Deck {
+            player: None,
+            result: None,
+            url: None,
+            mainboard: Some(deck_match.mainboard.clone()),
+            sideboard: None,
+            commander: Some(deck_match.commander.clone()),
+            wins: None,
+            match_record: None,
+        };
+        let commander_format = deck_match.tournament.format.as_deref().is_some_and(is_commander_format);
+        deck_match.archetype = archetype_label(&archetypes, &deck).map(str::to_string).or_else(|| {
+            (search_args.merge_partner_commanders && commander_format)
+                .then(|| commander_key(&deck_match.commander))
+                .flatten()
+        });
+    }
+
+    // Sort by date (most recent first)
+    all_matches.sort_by(|a, b| b.file_date.cmp(&a.file_date));
+
+    let total_matches = all_matches.len();
+    let summaries = if search_args.summary {
+        summarize_matches(&all_matches)
+    } else {
+        Vec::new()
+    };
+
+    // Limit results
+    all_matches.truncate(search_args.num);
+
+    progress!("Found {} matching decks", all_matches.len());
+
+    if all_matches.is_empty() {
+        // Exact names that no deck runs are usually typos
+        if suggest {
+            for criterion in criteria.iter().filter(|c| !c.excluded) {
+                let suggestions = suggest_card_names(&criterion.name, &names);
+                if !suggestions.is_empty() {
+                    eprintln!("No deck runs \"{}\". Did you mean: {}?", criterion.name, suggestions.join(", "));
+                }
+            }
+        }
+        return;
+    }
+
+    // Output results
+    println!();
+    for (i, deck_match) in all_matches.iter().enumerate() {
+        println!("=== Deck {} ===", i + 1);
+        println!("Date: {}", deck_match.file_date);
+        if let Some(name) = &deck_match.tournament.name {
+            println!("Tournament: {}", name);
+        }
+        if let Some(format) = &deck_match.tournament.format {
+            println!("Format: {}", format);
+        }
+        if let Some(player) = &deck_match.player {
+            println!("Player: {}", player);
+        }
+        if let Some(result) = &deck_match.result {
+            println!("Result: {}", result);
+        }
+        if let Some(url) = &deck_match.url {
+            println!("URL: {}", url);
+        }
+        if let Some(archetype) = &deck_match.archetype {
+            println!("Archetype: {}", archetype);
+        }
+
+        println!("\nMatched cards:");
+        for m in &deck_match.matched_cards {
+            let req = match m.requested {
+                Some(n) => format!(" (requested: {})", n),
+                None => String::new(),
+            };
+            println!("  {} (main: {}, side: {}){}", m.name, m.found_main, m.found_side, req);
+        }
+
+        if !deck_match.commander.is_empty() {
+            println!("\nCommander:");
+            for card in &deck_match.commander {
+                println!("  {} {}", card.count, card.name);
+            }
+        }
+
+        println!("\nMainboard ({} cards):", deck_match.mainboard.iter().map(|c| c.count).sum::<u32>());
+        for card in &deck_match.mainboard {
+            println!("  {} {}", card.count, card.name);
+        }
+
+        if !deck_match.sideboard.is_empty() {
+            println!("\nSideboard ({} cards):", deck_match.sideboard.iter().map(|c| c.count).sum::<u32>());
+            for card in &deck_match.sideboard {
+                println!("  {} {}", card.count, card.name);
+            }
+        }
+        println!();
+    }
+
+    if search_args.summary {
+        println!("=== Summary ===");
+        println!("Matching decks: {}", total_matches);
+        for summary in &summaries {
+            println!(
+                "  {}: {:.2} main, {:.2} side on average ({} decks)",
+                summary.name, summary.avg_main, summary.avg_side, summary.decks
+            );
+        }
+    }
+}
+
+/// Run the co-occur command
+fn run_co_occur(args: &Args, co_args: &CoOccurArgs) {
+    let scope = ScopeFilter::from_args(args);
+
+    let seeds: Vec<CardCriterion> = co_args.seeds.iter().map(|s| parse_card_criterion(s)).collect();
+    let match_options = MatchOptions {
+        include_sideboard: co_args.sideboard,
+        ..Default::default()
+    };
+    let options = ProcessOptions {
+        half_life: co_args.half_life,
+        use_weight: !co_args.no_weight,
+        ..Default::default()
+    };
+
+    let files = input_files(args);
+    progress!("Processing {} files...", files.len());
+
+    let card_counts: HashMap<String, f64> = files
+        .par_iter()
+        .fold(HashMap::new, |mut acc, path| {
+            for (card, count) in co_occur_file(path, &scope, &seeds, &match_options, &options) {
+                *acc.entry(card).or_insert(0.0) += count;
+            }
+            acc
+        })
+        .reduce(HashMap::new, |mut acc, map| {
+            for (card, count) in map {
+                *acc.entry(card).or_insert(0.0) += count;
+            }
+            acc
+        });
+
+    let mut sorted: Vec<_> = card_counts.into_iter().collect();
+    sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    for (card, count) in sorted.iter().take(co_args.num) {
+        println!("{:.2} {}", count, card);
+    }
+
+    if let Some(path) = &co_args.matrix {
+        // Only pairs among the top cards are tracked, keeping memory bounded
+        let names: Vec<String> = sorted.into_iter().take(co_args.matrix_size).map(|(card, _)| card).collect();
+        let index: HashMap<String, usize> = names.iter().enumerate().map(|(i, n)| (n.clone(), i)).collect();
+        let matrix = files
+            .par_iter()
+            .map(|path| co_play_matrix_file(path, &scope, &seeds, &match_options, &options, &index))
+            .reduce(
+                || vec![0.0; names.len() * names.len()],
+                |mut acc, m| {
+                    acc.iter_mut().zip(m).for_each(|(a, v)| *a += v);
+                    acc
+                },
+            );
+        let mut out = output_writer(Some(path));
+        if let Err(e) = write_matrix_csv(&mut out, &names, &matrix).and_then(|_| out.flush()) {
+            eprintln!("Error: Failed to write {}: {}", path, e);
+            std::process::exit(1);
+        }
+        progress!("Matrix of {} cards written to {}", names.len(), path);
+    }
+}
+
+/// Play rate of each card across one player's decks: the weighted share of
+/// their decks running it
+fn player_play_rates(
+    files: &[PathBuf],
+    scope: &ScopeFilter,
+    player: &str,
+    options: &ProcessOptions,
+) -> HashMap<String, f64> {
+    let scope = ScopeFilter {
+        players: vec![player.to_string()],
+        ..scope.clone()
+    };
+    let options = ProcessOptions {
+        presence: true,
+        ..options.clone()
+    };
+
+    let counts = par_merge_counts(files, |path| process_file(path, &scope, &options, None));
+
+    let total_decks = counts.total_decks();
+    if total_decks == 0.0 {
+        return HashMap::new();
+    }
+    counts
+        .cards
+        .into_iter()
+        .map(|(name, count)| (name, count / total_decks))
+        .collect()
+}
+
+/// Cards played by only one of two players, and cards both play
+#[derive(Debug, Default)]
+struct PlayerComparison {
+    only_a: Vec<(String, f64)>,
+    only_b: Vec<(String, f64)>,
+    /// Shared cards with the play rate for A and for B
+    shared: Vec<(String, f64, f64)>,
+}
+
+/// Split two players' play rates into unique and shared cards, each sorted by
+/// play rate descending
+fn compare_play_rates(rates_a: &HashMap<String, f64>, rates_b: &HashMap<String, f64>) -> PlayerComparison {
+    let mut comparison = PlayerComparison::default();
+    for (name, &rate_a) in rates_a {
+        match rates_b.get(name) {
+            Some(&rate_b) => comparison.shared.push((name.clone(), rate_a, rate_b)),
+            None => comparison.only_a.push((name.clone(), rate_a)),
+        }
+    }
+    for (name, &rate_b) in rates_b {
+        if !rates_a.contains_key(name) {
+            comparison.only_b.push((name.clone(), rate_b));
+        }
+    }
+
+    let by_rate = |a: &(String, f64), b: &(String, f64)| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0));
+    comparison.only_a.sort_by(by_rate);
+    comparison.only_b.sort_by(by_rate);
+    comparison
+        .shared
+        .sort_by(|a, b| (b.1 + b.2).partial_cmp(&(a.1 + a.2)).unwrap().then_with(|| a.0.cmp(&b.0)));
+    comparison
+}
+
+fn run_compare_players(args: &Args, cmp_args: &ComparePlayersArgs) {
+    let scope = ScopeFilter::from_args(args);
+    let options = ProcessOptions {
+        half_life: cmp_args.half_life,
+        use_weight: !cmp_args.no_weight,
+        ..Default::default()
+    };
+
+    let files = input_files(args);
+    progress!("Processing {} files...", files.len());
+
+    let rates_a = player_play_rates(&files, &scope, &cmp_args.player_a, &options);
+    let rates_b = player_play_rates(&files, &scope, &cmp_args.player_b, &options);
+    for (player, rates) in [(&cmp_args.player_a, &rates_a), (&cmp_args.player_b, &rates_b)] {
+        if rates.is_empty() {
+            eprintln!("Warning: No decks found for {}", player);
+        }
+    }
+    let comparison = compare_play_rates(&rates_a, &rates_b);
+
+    println!("Only {}:", cmp_args.player_a);
+    for (name, rate) in comparison.only_a.iter().take(cmp_args.num) {
+        println!("  {:>6.1}% {}", rate * 100.0, name);
+    }
+    println!();
+    println!("Only {}:", cmp_args.player_b);
+    for (name, rate) in comparison.only_b.iter().take(cmp_args.num) {
+        println!("  {:>6.1}% {}", rate * 100.0, name);
+    }
+    println!();
+    println!("Shared ({} / {}):", cmp_args.player_a, cmp_args.player_b);
+    for (name, rate_a, rate_b) in comparison.shared.iter().take(cmp_args.num) {
+        println!("  {:>6.1}% {:>6.1}% {}", rate_a * 100.0, rate_b * 100.0, name);
+    }
+}
+
+fn run_player_stats(args: &Args, stats_args: &PlayerStatsArgs) {
+    let scope = ScopeFilter::from_args(args);
+    let files = input_files(args);
+    progress!("Processing {} files...", files.len());
+
+    for stats in player_stats(&files, &scope).iter().take(stats_args.num) {
+        let best = stats.best_placement.map_or("-".to_string(), |p| format!("#{}", p));
+        println!(
+            "{:>5} {:>5}  {}  ({})",
+            stats.decks,
+            best,
+            stats.name,
+            stats.top_card.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+fn run_conditional(args: &Args, cond_args: &ConditionalArgs) {
+    let scope = ScopeFilter::from_args(args);
+
+    let card_a = parse_card_criterion(&cond_args.card_a);
+    let card_b = parse_card_criterion(&cond_args.card_b);
+    let match_options = MatchOptions {
+        include_sideboard: cond_args.sideboard,
+        ..Default::default()
+    };
+
+    let files = input_files(args);
+    progress!("Processing {} files...", files.len());
+
+    let counts = files
+        .par_iter()
+        .map(|path| conditional_file(path, &scope, &card_a, &card_b, &match_options))
+        .reduce(ConditionalCounts::default, ConditionalCounts::merge);
+
+    match counts.share() {
+        Some(share) => println!(
+            "P({} | {}) = {:.1}% ({} of {} decks)",
+            card_b.name,
+            card_a.name,
+            share * 100.0,
+            counts.with_both,
+            counts.with_a
+        ),
+        None => println!("No decks found running {}", card_a.name),
+    }
+}
+
+/// Whether the name part of a plain-text line carries another output column:
+/// a leading count, share or date (--show-raw, --detailed, --dates, or the
+/// value after a --ranked rank) or a trailing price (--with-price)
+fn has_extra_column(name: &str) -> bool {
+    let leading = name.starts_with(|c: char| c.is_ascii_digit() || c == '%' || c == '$');
+    let trailing = name.rsplit_once(' ').is_some_and(|(_, last)| last.starts_with('$') || last == "-");
+    leading || trailing
+}
+
+/// Parse a saved top-cards output into (name, weight) pairs in file order.
+/// Accepts plain "{weight} {name}" lines (a trailing "%" from --percent is
+/// dropped) and --jsonl objects; blank lines are skipped. Lines with extra
+/// columns, e.g. from --show-raw or --ranked, are rejected.
+fn parse_snapshot(text: &str) -> Result<Vec<(String, f64)>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let entry = if line.starts_with('{') {
+            serde_json::from_str::<serde_json::Value>(line).ok().and_then(|v| {
+                Some((v.get("name")?.as_str()?.to_string(), v.get("weight")?.as_f64()?))
+            })
+        } else {
+            line.split_once(' ').and_then(|(weight, name)| {
+                let weight = weight.trim_end_matches('%').parse().ok()?;
+                Some((name.trim().to_string(), weight))
+            })
+        };
+        let entry =
+            entry.ok_or_else(|| format!("line {}: expected \"<weight> <name>\" or JSON, got {:?}", i + 1, line))?;
+        if !line.starts_with('{') && has_extra_column(&entry.0) {
+            return Err(format!(
+                "line {}: only plain \"<weight> <name>\" or --jsonl snapshots are supported, got {:?}",
+                i + 1,
+                line
+            ));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// A card's rank and weight in two snapshots; None where it is absent
+#[derive(Debug, PartialEq)]
+struct CardShift {
+    name: String,
+    before: Option<(usize, f64)>,
+    after: Option<(usize, f64)>,
+}
+
+impl CardShift {
+    fn delta(&self) -> f64 {
+        self.after.map_or(0.0, |a| a.1) - self.before.map_or(0.0, |b| b.1)
+    }
+
+    fn label(&self) -> &'static str {
+        match (self.before, self.after) {
+            (None, _) => "new",
+            (_, None) => "dropped",
+            _ if self.delta() == 0.0 => "unchanged",
+            _ if self.delta() > 0.0 => "gained",
+            _ => "lost",
+        }
+    }
+}
+
+/// Rank and weight changes between two snapshots, largest absolute weight
+/// change first. Ranks are 1-based line positions.
+fn diff_snapshots(before: &[(String, f64)], after: &[(String, f64)]) -> Vec<CardShift> {
+    let ranked = |entries: &[(String, f64)]| -> HashMap<String, (usize, f64)> {
+        let mut ranked = HashMap::new();
+        for (i, (name, weight)) in entries.iter().enumerate() {
+            ranked.entry(name.clone()).or_insert((i + 1, *weight));
+        }
+        ranked
+    };
+    let before = ranked(before);
+    let after = ranked(after);
+
+    let names: HashSet<&String> = before.keys().chain(after.keys()).collect();
+    let mut shifts: Vec<CardShift> = names
+        .into_iter()
+        .map(|name| CardShift {
+            name: name.clone(),
+            before: before.get(name).copied(),
+            after: after.get(name).copied(),
+        })
+        .collect();
+    shifts.sort_by(|a, b| {
+        b.delta()
+            .abs()
+            .partial_cmp(&a.delta().abs())
+            .unwrap()
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    shifts
+}
+
+fn run_diff(diff_args: &DiffArgs) {
+    let load = |path: &str| {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))
+            .and_then(|text| parse_snapshot(&text).map_err(|e| format!("{}: {}", path, e)))
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            })
+    };
+    let before = load(&diff_args.before);
+    let after = load(&diff_args.after);
+
+    let rank = |r: Option<(usize, f64)>| r.map_or("-".to_string(), |(rank, _)| rank.to_string());
+    for shift in diff_snapshots(&before, &after).iter().take(diff_args.num) {
+        println!(
+            "{:>+10.2} {:<7} {} (rank {} -> {})",
+            shift.delta(),
+            shift.label(),
+            shift.name,
+            rank(shift.before),
+            rank(shift.after)
+        );
+    }
+}
+
+fn run_deck_diff(diff_args: &DeckDiffArgs) {
+    let load = |spec: &str| {
+        load_deck_source(spec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let deck_a = load(&diff_args.deck_a);
+    let deck_b = load(&diff_args.deck_b);
+
+    let boards = [
+        ("Commander", &deck_a.commander, &deck_b.commander),
+        ("Mainboard", &deck_a.mainboard, &deck_b.mainboard),
+        ("Sideboard", &deck_a.sideboard, &deck_b.sideboard),
+    ];
+    let mut any = false;
+    for (label, before, after) in boards {
+        let diffs = diff_boards(before.as_deref().unwrap_or_default(), after.as_deref().unwrap_or_default());
+        if diffs.is_empty() {
+            continue;
+        }
+        any = true;
+        println!("{}:", label);
+        for d in diffs {
+            let delta = d.after as i64 - d.before as i64;
+            println!("  {:+} {} ({} -> {})", delta, d.name, d.before, d.after);
+        }
+    }
+    if !any {
+        println!("Decks are identical");
+    }
+}
+
+/// In-scope decks and files per bucket, keyed by bucket label. Files are
+/// only filtered, never aggregated into card counts.
+fn coverage_counts(files: &[PathBuf], scope: &ScopeFilter, bucket: Bucket) -> BTreeMap<String, (u64, u64)> {
+    files
+        .par_iter()
+        .fold(BTreeMap::new, |mut rows: BTreeMap<String, (u64, u64)>, path| {
+            if let Some(scoped) = load_scoped_file(path, scope) {
+                let row = rows.entry(bucket_label(&scoped.date, bucket)).or_default();
+                row.0 += scoped.data.decks.as_ref().map_or(0, |d| d.len() as u64);
+                row.1 += 1;
+            }
+            rows
+        })
+        .reduce(BTreeMap::new, |mut acc, rows| {
+            for (label, (decks, files)) in rows {
+                let row = acc.entry(label).or_default();
+                row.0 += decks;
+                row.1 += files;
+            }
+            acc
+        })
+}
+
+fn run_coverage(args: &Args, coverage_args: &CoverageArgs) {
+    let scope = ScopeFilter::from_args(args);
+    let files = input_files(args);
+    progress!("Scanning {} files...", files.len());
+
+    println!("date,decks,files");
+    for (label, (decks, files)) in coverage_counts(&files, &scope, coverage_args.bucket) {
+        println!("{},{},{}", label, decks, files);
+    }
+}
+
+/// Files in scope by date and format, per tournament format, for --dry-run.
+/// Only the tournament headers are read, so event size and deck filters
+/// are not applied.
+fn dry_run_formats(files: &[PathBuf], scope: &ScopeFilter) -> BTreeMap<String, usize> {
+    let formats: Vec<String> = files
+        .par_iter()
+        .filter_map(|path| {
+            file_age(path, scope)?;
+            let Some(tournament) = peek_tournament(path) else {
+                note_unparseable(path);
+                return None;
+            };
+            let format = match tournament.format {
+                Some(format) => format,
+                None if scope.infer_format => infer_format_from_path(path)?,
+                None => return None,
+            };
+            scope.format_ok(&format).then_some(format)
+        })
+        .collect();
+    let mut counts = BTreeMap::new();
+    for format in formats {
+        *counts.entry(format).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn run_dry_run(args: &Args) {
+    let scope = ScopeFilter::from_args(args);
+    let files = input_files(args);
+    progress!("Checking {} files...", files.len());
+
+    let formats = dry_run_formats(&files, &scope);
+    println!("{} files in scope", formats.values().sum::<usize>());
+    for (format, count) in formats {
+        println!("  {}: {}", format, count);
+    }
+}
+
+/// Up to three names closest to a card name no deck runs, by case-insensitive
+/// edit distance. Names off by more than a third of the query are left out,
+/// and nothing is suggested if the name itself is known.
+fn suggest_card_names(query: &str, names: &HashSet<String>) -> Vec<String> {
+    let query = query.trim().to_lowercase();
+    if names.iter().any(|name| name.to_lowercase() == query) {
+        return Vec::new();
+    }
+    let max_distance = (query.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, &String)> = names
+        .iter()
+        .map(|name| (strsim::levenshtein(&query, &name.to_lowercase()), name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    close.sort();
+    close.into_iter().take(3).map(|(_, name)| name.clone()).collect()
+}
+
+/// Top finishes of a card across all files, oldest first
+fn card_timeline(files: &[PathBuf], scope: &ScopeFilter, card: &CardCriterion, options: &MatchOptions) -> Vec<DeckMatch> {
+    let mut entries: Vec<DeckMatch> = files
+        .par_iter()
+        .flat_map(|path| search_file_for_decks(path, scope, std::slice::from_ref(card), options))
+        .collect();
+    entries.sort_by(|a, b| a.file_date.cmp(&b.file_date).then_with(|| a.tournament.name.cmp(&b.tournament.name)));
+    entries
+}
+
+fn run_timeline(args: &Args, timeline_args: &TimelineArgs) {
+    let scope = ScopeFilter::from_args(args);
+    let card = parse_card_criterion(&timeline_args.card);
+    let match_options = MatchOptions {
+        include_sideboard: timeline_args.sideboard,
+        max_placement: Some(timeline_args.placement),
+        ..Default::default()
+    };
+
+    let files = input_files(args);
+    progress!("Searching {} files...", files.len());
+
+    let entries = card_timeline(&files, &scope, &card, &match_options);
+    let (first, last) = match (entries.first(), entries.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            println!("No top {} finishes found for {}", timeline_args.placement, card.name);
+            return;
+        }
+    };
+
+    let describe = |m: &DeckMatch| {
+        format!(
+            "{} {} ({}, {})",
+            m.file_date,
+            m.result.as_deref().unwrap_or("?"),
+            m.tournament.name.as_deref().unwrap_or("Unknown event"),
+            m.player.as_deref().unwrap_or("Unknown player")
+        )
+    };
+    println!("{}: {} top {} finishes", card.name, entries.len(), timeline_args.placement);
+    println!("First: {}", describe(first));
+    println!("Last:  {}", describe(last));
+    println!();
+    for m in &entries {
+        println!(
+            "{}  {:<10} {:<40} {}",
+            m.file_date,
+            m.result.as_deref().unwrap_or("?"),
+            m.tournament.name.as_deref().unwrap_or("Unknown event"),
+            m.player.as_deref().unwrap_or("Unknown player")
+        );
+    }
+}
+
+fn main() {
+    let mut args = match parse_args() {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    QUIET.store(args.quiet, Ordering::Relaxed);
+    SCRYFALL_RETRIES.store(args.scryfall_retries, Ordering::Relaxed);
+    if let Some(path) = &args.scryfall_file {
+        if !Path::new(path).is_file() {
+            eprintln!("Error: Scryfall file {} not found", path);
+            std::process::exit(1);
+        }
+        SCRYFALL_FILE.set(PathBuf::from(path)).unwrap();
+    }
+    if args.colors.is_some() {
+        match resolve_color_identities(args.scryfall_bulk, args.unknown_colors) {
+            Ok(identities) => args.color_identities = Arc::new(identities),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Fetch the data repository if requested
+    if args.fetch {
+        if let Some(data_dir) = fetch_dir(&args.data_dir) {
+            if let Err(e) = fetch_data_repo(
+                &SystemGit,
+                &data_dir,
+                &args.data_repo,
+                args.data_branch.as_deref(),
+                args.sparse.as_deref(),
+            ) {
+                eprintln!("Error fetching data: {}", e);
+                std::process::exit(1);
+            }
+            if args.verify_fetch {
+                match verify_data_repo(&data_dir) {
+                    Ok(summary) => progress!("Verified data repository: {}", summary),
+                    Err(e) => {
+                        eprintln!("Error verifying data: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+    }
+
+    match &args.command {
+        _ if args.dry_run => {
+            run_dry_run(&args);
+        }
+        Some(Commands::TopCards(top_args)) => {
+            run_top_cards(&args, top_args);
+        }
+        Some(Commands::SearchDecks(search_args)) => {
+            run_search_decks(&args, search_args);
+        }
+        Some(Commands::CoOccur(co_args)) => {
+            run_co_occur(&args, co_args);
+        }
+        Some(Commands::ComparePlayers(cmp_args)) => {
+            run_compare_players(&args, cmp_args);
+        }
+        Some(Commands::PlayerStats(stats_args)) => {
+            run_player_stats(&args, stats_args);
+        }
+        Some(Commands::Conditional(cond_args)) => {
+            run_conditional(&args, cond_args);
+        }
+        Some(Commands::DeckDiff(diff_args)) => {
+            run_deck_diff(diff_args);
+        }
+        Some(Commands::Timeline(timeline_args)) => {
+            run_timeline(&args, timeline_args);
+        }
+        Some(Commands::Coverage(coverage_args)) => {
+            run_coverage(&args, coverage_args);
+        }
+        Some(Commands::Diff(diff_args)) => {
+            run_diff(diff_args);
+        }
+        None => unreachable!("args_with_config turns a run without a subcommand into top-cards"),
+    }
+
+    for line in unparseable_report(&UNPARSEABLE_FILES.lock().unwrap(), args.verbose) {
+        eprintln!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    // ==================== Unit Tests for parse_card_criterion ====================
+
+    #[test]
+    fn test_parse_card_criterion_with_count() {
+        let criterion = parse_card_criterion("4 Lightning Bolt");
+        assert_eq!(criterion.name, "Lightning Bolt");
+        assert_eq!(criterion.count, Some(4));
+    }
+
+    #[test]
+    fn test_parse_card_criterion_without_count() {
+        let criterion = parse_card_criterion("Lightning Bolt");
+        assert_eq!(criterion.name, "Lightning Bolt");
+        assert_eq!(criterion.count, None);
+    }
+
+    #[test]
+    fn test_parse_card_criterion_with_extra_whitespace() {
+        let criterion = parse_card_criterion("  4   Ragavan, Nimble Pilferer  ");
+        assert_eq!(criterion.name, "Ragavan, Nimble Pilferer");
+        assert_eq!(criterion.count, Some(4));
+    }
+
+    #[test]
+    fn test_parse_card_criterion_single_copy() {
+        let criterion = parse_card_criterion("1 Emrakul, the Aeons Torn");
+        assert_eq!(criterion.name, "Emrakul, the Aeons Torn");
+        assert_eq!(criterion.count, Some(1));
+    }
+
+    #[test]
+    fn test_parse_card_criterion_card_starting_with_number() {
+        // Card name that might look like it starts with a number
+        let criterion = parse_card_criterion("97th Regiment");
+        assert_eq!(criterion.name, "97th Regiment");
+        assert_eq!(criterion.count, None);
+    }
+
+    #[test]
+    fn test_parse_card_criterion_empty_string() {
+        let criterion = parse_card_criterion("");
+        assert_eq!(criterion.name, "");
+        assert_eq!(criterion.count, None);
+    }
+
+    #[test]
+    fn test_parse_card_criterion_excluded() {
+        let criterion = parse_card_criterion("!Ragavan, Nimble Pilferer");
+        assert_eq!(criterion.name, "Ragavan, Nimble Pilferer");
+        assert_eq!(criterion.count, None);
+        assert!(criterion.excluded);
+
+        let criterion = parse_card_criterion("Lightning Bolt");
+        assert!(!criterion.excluded);
+    }
+
+    // ==================== Unit Tests for deck_matches_criteria ====================
+
+    fn create_test_deck(mainboard: Vec<(&str, u32)>, sideboard: Vec<(&str, u32)>) -> Deck {
+        Deck {
+            player: Some("TestPlayer".to_string()),
+            result: Some("1st".to_string()),
+            url: Some("https://example.com/deck/123".to_string()),
+            mainboard: Some(
+                mainboard
+                    .into_iter()
+                    .map(|(name, count)| Card {
+                        name: name.to_string(),
+                        count,
+                    })
+                    .collect(),
+            ),
+            sideboard: Some(
+                sideboard
+                    .into_iter()
+                    .map(|(name, count)| Card {
+                        name: name.to_string(),
+                        count,
+                    })
+                    .collect(),
+            ),
+            commander: None,
+            wins: None,
+            match_record: None,
+        }
+    }
+
+    fn commander_tournament_json() -> &'static str {
+        r#"{
+            "tournament": {"name": "Commander Night", "format": "Duel Commander"},
+            "decks": [
+                {
+                    "player": "Alice",
+                    "result": "1st",
+                    "commanders": [{"count": 1, "name": "Atraxa, Praetors' Voice"}],
+                    "mainboard": [{"count": 1, "name": "Sol Ring"}, {"count": 30, "name": "Forest"}]
+                },
+                {
+                    "player": "Bob",
+                    "result": "2nd",
+                    "mainboard": [{"count": 1, "name": "Sol Ring"}, {"count": 1, "name": "Atraxa, Praetors' Voice"}]
+                }
+            ]
+        }"#
+    }
+
+    #[test]
+    fn test_commander_zone_counted() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/commander.json", commander_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/commander.json");
+        let scope = test_scope(&["Commander"]);
+
+        let counts = process_file(&path, &scope, &ProcessOptions::default(), None);
+        assert_eq!(counts.cards["Atraxa, Praetors' Voice"], counts.cards["Sol Ring"]);
+        let skip_sideboard = ProcessOptions {
+            skip_sideboard: true,
+            ..Default::default()
+        };
+        let counts = process_file(&path, &scope, &skip_sideboard, None);
+        assert_eq!(counts.cards["Atraxa, Praetors' Voice"], counts.cards["Sol Ring"]);
+
+        let atraxa = vec![parse_card_criterion("Atraxa, Praetors' Voice")];
+        let matches = search_file_for_decks(&path, &scope, &atraxa, &MatchOptions::default());
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].commander.len(), 1);
+
+        let commander_only = MatchOptions {
+            commander_only: true,
+            ..Default::default()
+        };
+        let matches = search_file_for_decks(&path, &scope, &atraxa, &commander_only);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].player.as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn test_suggest_card_names_for_typo() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        let scope = test_scope(&["Modern"]);
+
+        let query = parse_card_criterion("Lightnig Bolt");
+        let mut names = HashSet::new();
+        let matches =
+            search_file_collecting_names(&files[0], &scope, &[query], &MatchOptions::default(), Some(&mut names));
+        assert!(matches.is_empty());
+
+        assert!(names.contains("Blood Moon"));
+        assert_eq!(suggest_card_names("Lightnig Bolt", &names), vec!["Lightning Bolt"]);
+        assert_eq!(suggest_card_names("thoughtsieze", &names), vec!["Thoughtseize"]);
+        assert!(suggest_card_names("Lightning Bolt", &names).is_empty());
+        assert!(suggest_card_names("Counterspell", &names).is_empty());
+    }
+
+    #[test]
+    fn test_commander_only_matches_partners() {
+        let mut deck = create_test_deck(vec![("Sol Ring", 1), ("Thrasios, Triton Hero", 1)], vec![]);
+        deck.commander = Some(vec![
+            Card {
+                name: "Tymna the Weaver".to_string(),
+                count: 1,
+            },
+            Card {
+                name: "Kraum, Ludevic's Opus".to_string(),
+                count: 1,
+            },
+        ]);
+        let commander_only = MatchOptions {
+            commander_only: true,
+            ..Default::default()
+        };
+        let criteria = |names: &[&str]| names.iter().map(|n| parse_card_criterion(n)).collect::<Vec<_>>();
+
+        assert!(deck_matches_criteria(&deck, &criteria(&["Tymna the Weaver"]), &commander_only).is_some());
+        assert!(deck_matches_criteria(&deck, &criteria(&["Tymna the Weaver", "Kraum, Ludevic's Opus"]), &commander_only).is_some());
+        // Thrasios is only in the mainboard here
+        assert!(deck_matches_criteria(&deck, &criteria(&["Tymna the Weaver", "Thrasios, Triton Hero"]), &commander_only).is_none());
+
+        let any_of = MatchOptions {
+            match_any: true,
+            ..commander_only
+        };
+        let matched = deck_matches_criteria(&deck, &criteria(&["Thrasios, Triton Hero", "Kraum, Ludevic's Opus"]), &any_of).unwrap();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].name, "Kraum, Ludevic's Opus");
+    }
+
+    #[test]
+    fn test_deck_matches_single_card_present() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4), ("Mountain", 20)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: None,
+            excluded: false,
+            pattern: None,
+        }];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_some());
+        let matches = result.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].found_main, 4);
+    }
+
+    #[test]
+    fn test_deck_matches_single_card_missing() {
+        let deck = create_test_deck(
+            vec![("Mountain", 20)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: None,
+            excluded: false,
+            pattern: None,
+        }];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_deck_matches_with_count_satisfied() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: Some(4),
+            excluded: false,
+            pattern: None,
+        }];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_some());
+    }
+
+    #[test]
+    fn test_deck_matches_with_count_not_satisfied() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 2)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: Some(4),
+            excluded: false,
+            pattern: None,
+        }];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_deck_matches_exact_count_satisfied() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 2)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: Some(2),
+            excluded: false,
+            pattern: None,
+        }];
+
+        let options = MatchOptions {
+            exact: true,
+            ..Default::default()
+        };
+        let result = deck_matches_criteria(&deck, &criteria, &options);
+        assert!(result.is_some());
+    }
+
+    #[test]
+    fn test_deck_matches_exact_count_not_satisfied() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: Some(2),
+            excluded: false,
+            pattern: None,
+        }];
+
+        // exact=true, so 4 != 2
+        let options = MatchOptions {
+            exact: true,
+            ..Default::default()
+        };
+        let result = deck_matches_criteria(&deck, &criteria, &options);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_deck_matches_at_most() {
+        let criteria = vec![parse_card_criterion("3 Lightning Bolt")];
+        let options = MatchOptions {
+            at_most: true,
+            ..Default::default()
+        };
+
+        let deck = create_test_deck(vec![("Lightning Bolt", 2)], vec![]);
+        assert!(deck_matches_criteria(&deck, &criteria, &options).is_some());
+
+        let deck = create_test_deck(vec![("Lightning Bolt", 4)], vec![]);
+        assert!(deck_matches_criteria(&deck, &criteria, &options).is_none());
+
+        // Decks not running the card at all are not "low-count builds"
+        let deck = create_test_deck(vec![("Mountain", 20)], vec![]);
+        assert!(deck_matches_criteria(&deck, &criteria, &options).is_none());
+    }
+
+    #[test]
+    fn test_deck_matches_regex_sums_all_matching_cards() {
+        let deck = create_test_deck(
+            vec![("Urza's Tower", 4), ("Urza's Mine", 4), ("Urza's Power Plant", 3), ("Urza, Lord High Artificer", 1)],
+            vec![],
+        );
+        let mut criteria = vec![parse_card_criterion("11 urza's .*")];
+        compile_name_patterns(&mut criteria).unwrap();
+
+        let matches = deck_matches_criteria(&deck, &criteria, &MatchOptions::default()).unwrap();
+        assert_eq!(matches[0].found_main, 11);
+
+        let exact = MatchOptions {
+            exact: true,
+            ..Default::default()
+        };
+        let mut criteria = vec![parse_card_criterion("8 Urza's (Tower|Mine)")];
+        compile_name_patterns(&mut criteria).unwrap();
+        assert!(deck_matches_criteria(&deck, &criteria, &exact).is_some());
+
+        // The pattern must match the whole name
+        let mut criteria = vec![parse_card_criterion("Tower")];
+        compile_name_patterns(&mut criteria).unwrap();
+        assert!(deck_matches_criteria(&deck, &criteria, &MatchOptions::default()).is_none());
+    }
+
+    #[test]
+    fn test_compile_name_patterns_rejects_invalid_regex() {
+        let mut criteria = vec![parse_card_criterion("Urza's (Tower")];
+        let err = compile_name_patterns(&mut criteria).unwrap_err();
+        assert!(err.contains("Urza's (Tower"));
+    }
+
+    #[test]
+    fn test_deck_matches_fuzzy_partial_name() {
+        let deck = create_test_deck(
+            vec![("Ragavan, Nimble Pilferer", 4), ("Mountain", 20)],
+            vec![],
+        );
+        let criteria = vec![parse_card_criterion("4 ragavan")];
+
+        assert!(deck_matches_criteria(&deck, &criteria, &MatchOptions::default()).is_none());
+
+        let options = MatchOptions {
+            fuzzy: true,
+            ..Default::default()
+        };
+        let matches = deck_matches_criteria(&deck, &criteria, &options).unwrap();
+        assert_eq!(matches[0].found_main, 4);
+    }
+
+    #[test]
+    fn test_deck_matches_fuzzy_sums_all_matching_cards() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4), ("Bolt of Keranos", 2)],
+            vec![("Firebolt", 1)],
+        );
+        let options = MatchOptions {
+            fuzzy: true,
+            include_sideboard: true,
+            ..Default::default()
+        };
+
+        let criteria = vec![parse_card_criterion("6 bolt")];
+        let matches = deck_matches_criteria(&deck, &criteria, &options).unwrap();
+        assert_eq!(matches[0].found_main, 6);
+        assert_eq!(matches[0].found_side, 1);
+
+        // Exact count applies to the summed total
+        let options = MatchOptions {
+            exact: true,
+            ..options
+        };
+        let criteria = vec![parse_card_criterion("6 bolt")];
+        assert!(deck_matches_criteria(&deck, &criteria, &options).is_none());
+        let criteria = vec![parse_card_criterion("7 bolt")];
+        assert!(deck_matches_criteria(&deck, &criteria, &options).is_some());
+    }
+
+    #[test]
+    fn test_deck_matches_sideboard_included() {
+        let deck = create_test_deck(
+            vec![("Mountain", 20)],
+            vec![("Blood Moon", 2)],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Blood Moon".to_string(),
+            count: None,
+            excluded: false,
+            pattern: None,
+        }];
+
+        // Without sideboard
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_none());
+
+        // With sideboard
+        let options = MatchOptions {
+            include_sideboard: true,
+            ..Default::default()
+        };
+        let result = deck_matches_criteria(&deck, &criteria, &options);
+        assert!(result.is_some());
+        let matches = result.unwrap();
+        assert_eq!(matches[0].found_side, 2);
+    }
+
+    #[test]
+    fn test_deck_matches_multiple_criteria_all_match() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4), ("Ragavan, Nimble Pilferer", 4)],
+            vec![],
+        );
+        let criteria = vec![
+            CardCriterion {
+                name: "Lightning Bolt".to_string(),
+                count: Some(4),
+                excluded: false,
+                pattern: None,
+            },
+            CardCriterion {
+                name: "Ragavan, Nimble Pilferer".to_string(),
+                count: Some(4),
+                excluded: false,
+                pattern: None,
+            },
+        ];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_some());
+        let matches = result.unwrap();
+        assert_eq!(matches.len(), 2);
+    }
+
+    #[test]
+    fn test_deck_matches_multiple_criteria_one_missing() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4)],
+            vec![],
+        );
+        let criteria = vec![
+            CardCriterion {
+                name: "Lightning Bolt".to_string(),
+                count: Some(4),
+                excluded: false,
+                pattern: None,
+            },
+            CardCriterion {
+                name: "Ragavan, Nimble Pilferer".to_string(),
+                count: Some(4),
+                excluded: false,
+                pattern: None,
+            },
+        ];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_deck_matches_case_insensitive() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "LIGHTNING BOLT".to_string(),
+            count: None,
+            excluded: false,
+            pattern: None,
+        }];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_some());
+    }
+
+    #[test]
+    fn test_deck_matches_any_one_present() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4)],
+            vec![],
+        );
+        let criteria = vec![
+            CardCriterion {
+                name: "Lightning Bolt".to_string(),
+                count: None,
+                excluded: false,
+                pattern: None,
+            },
+            CardCriterion {
+                name: "Ragavan, Nimble Pilferer".to_string(),
+                count: None,
+                excluded: false,
+                pattern: None,
+            },
+        ];
+
+        // AND logic rejects the deck
+        assert!(deck_matches_criteria(&deck, &criteria, &MatchOptions::default()).is_none());
+
+        // OR logic accepts it and only reports the card that matched
+        let options = MatchOptions {
+            match_any: true,
+            ..Default::default()
+        };
+        let result = deck_matches_criteria(&deck, &criteria, &options);
+        assert!(result.is_some());
+        let matches = result.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, "Lightning Bolt");
+    }
+
+    #[test]
+    fn test_deck_matches_any_none_present() {
+        let deck = create_test_deck(
+            vec![("Mountain", 20)],
+            vec![],
+        );
+        let criteria = vec![
+            CardCriterion {
+                name: "Lightning Bolt".to_string(),
+                count: None,
+                excluded: false,
+                pattern: None,
+            },
+            CardCriterion {
+                name: "Ragavan, Nimble Pilferer".to_string(),
+                count: None,
+                excluded: false,
+                pattern: None,
+            },
+        ];
+
+        let options = MatchOptions {
+            match_any: true,
+            ..Default::default()
+        };
+        let result = deck_matches_criteria(&deck, &criteria, &options);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_deck_matches_any_partial_count() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 2)],
+            vec![],
+        );
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: Some(4),
+            excluded: false,
+            pattern: None,
+        }];
+
+        // 2 of a requested 4 fails under AND logic...
+        assert!(deck_matches_criteria(&deck, &criteria, &MatchOptions::default()).is_none());
+
+        // ...but counts as a partial match under OR logic
+        let options = MatchOptions {
+            match_any: true,
+            ..Default::default()
+        };
+        let result = deck_matches_criteria(&deck, &criteria, &options);
+        assert!(result.is_some());
+        let matches = result.unwrap();
+        assert_eq!(matches[0].requested, Some(4));
+        assert_eq!(matches[0].found_main, 2);
+    }
+
+    #[test]
+    fn test_match_any_conflicts_with_exact() {
+        // Partial matching leaves nothing for --exact to mean
+        let result = Args::command()
+            .try_get_matches_from(["top_cards", "search-decks", "--match-any", "--exact", "4 Lightning Bolt"]);
+        assert_eq!(result.unwrap_err().kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_deck_matches_excluded_card_present() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4), ("Ragavan, Nimble Pilferer", 4)],
+            vec![],
+        );
+        let criteria = vec![
+            parse_card_criterion("Lightning Bolt"),
+            parse_card_criterion("!Ragavan, Nimble Pilferer"),
+        ];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_deck_matches_excluded_card_absent() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4), ("Mountain", 20)],
+            vec![],
+        );
+        let criteria = vec![
+            parse_card_criterion("Lightning Bolt"),
+            parse_card_criterion("!Ragavan, Nimble Pilferer"),
+        ];
+
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_some());
+        // Exclusions are not reported as matched cards
+        let matches = result.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, "Lightning Bolt");
+    }
+
+    #[test]
+    fn test_deck_matches_excluded_card_respects_sideboard() {
+        let deck = create_test_deck(
+            vec![("Lightning Bolt", 4)],
+            vec![("Blood Moon", 2)],
+        );
+        let criteria = vec![
+            parse_card_criterion("Lightning Bolt"),
+            parse_card_criterion("!Blood Moon"),
+        ];
+
+        // Sideboard copies are ignored without --sideboard
+        let result = deck_matches_criteria(&deck, &criteria, &MatchOptions::default());
+        assert!(result.is_some());
+
+        let options = MatchOptions {
+            include_sideboard: true,
+            ..Default::default()
+        };
+        let result = deck_matches_criteria(&deck, &criteria, &options);
+        assert!(result.is_none());
+    }
+
+    // ==================== Integration Tests ====================
+
+    fn create_test_tournament_file(dir: &Path, date_path: &str, content: &str) {
+        let full_path = dir.join(date_path);
+        std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        let mut file = File::create(&full_path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    fn test_scope(formats: &[&str]) -> ScopeFilter {
+        ScopeFilter {
+            format_patterns: formats.iter().map(|f| f.to_string()).collect(),
+            exact_format: false,
+            infer_format: false,
+            today: today_days(),
+            max_age: 1825,
+            min_rounds: None,
+            min_deck_size: None,
+            players: Vec::new(),
+            future_dates: FutureDates::Skip,
+            colors: None,
+        }
+    }
+
+    fn sample_tournament_json() -> &'static str {
+        r#"{
+            "tournament": {
+                "name": "Test Tournament",
+                "format": "Modern",
+                "date": "2025-01-10"
+            },
+            "decks": [
+                {
+                    "player": "Alice",
+                    "result": "1st",
+                    "mainboard": [
+                        {"count": 4, "name": "Lightning Bolt"},
+                        {"count": 4, "name": "Ragavan, Nimble Pilferer"},
+                        {"count": 20, "name": "Mountain"}
+                    ],
+                    "sideboard": [
+                        {"count": 2, "name": "Blood Moon"}
+                    ]
+                },
+                {
+                    "player": "Bob",
+                    "result": "2nd",
+                    "mainboard": [
+                        {"count": 2, "name": "Lightning Bolt"},
+                        {"count": 4, "name": "Thoughtseize"},
+                        {"count": 20, "name": "Swamp"}
+                    ],
+                    "sideboard": []
+                }
+            ]
+        }"#
+    }
+
+    #[test]
+    fn test_rounds_array_does_not_break_parsing() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/dump.json",
+            r#"{
+                "tournament": {"name": "Challenge", "format": "Modern", "date": "2025-01-10"},
+                "decks": [{"player": "Alice", "result": "1st", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]}],
+                "rounds": [
+                    {"roundName": "Round 1", "matches": [{"player1": "Alice", "player2": "Bob", "result": "2-0-0"}]},
+                    {"roundName": "Quarterfinals", "matches": []}
+                ]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/dump.json");
+        let scope = test_scope(&["Modern"]);
+
+        let counts = process_file(&path, &scope, &ProcessOptions::default(), None);
+        assert_eq!(counts.decks, 1);
+        let bolt = vec![parse_card_criterion("Lightning Bolt")];
+        assert_eq!(search_file_for_decks(&path, &scope, &bolt, &MatchOptions::default()).len(), 1);
+        assert_eq!(dry_run_formats(std::slice::from_ref(&path), &scope)["Modern"], 1);
+
+        // At the top level, an array reads as an unknown round count
+        let flat: DecklistFile =
+            serde_json::from_str(r#"{"format": "Modern", "rounds": [{"roundName": "Round 1"}], "decks": []}"#).unwrap();
+        assert_eq!(flat.tournament.format.as_deref(), Some("Modern"));
+        assert_eq!(flat.tournament.rounds, None);
+        let nested: DecklistFile =
+            serde_json::from_str(r#"{"tournament": {"format": "Modern", "rounds": []}, "decks": []}"#).unwrap();
+        assert_eq!(nested.tournament.rounds, None);
+    }
+
+    #[test]
+    fn test_decklist_file_tournament_layouts() {
+        let flat: DecklistFile = serde_json::from_str(
+            r#"{"name": "Flat Open", "format": "Modern", "date": "2025-01-10", "rounds": 7, "decks": []}"#,
+        )
+        .unwrap();
+        assert_eq!(flat.tournament.name.as_deref(), Some("Flat Open"));
+        assert_eq!(flat.tournament.format.as_deref(), Some("Modern"));
+        assert_eq!(flat.tournament.date.as_deref(), Some("2025-01-10"));
+        assert_eq!(flat.tournament.rounds, Some(7));
+
+        let event: DecklistFile =
+            serde_json::from_str(r#"{"event": {"name": "Event Open", "format": "Legacy"}, "decks": []}"#).unwrap();
+        assert_eq!(event.tournament.name.as_deref(), Some("Event Open"));
+        assert_eq!(event.tournament.format.as_deref(), Some("Legacy"));
+
+        // A nested object wins over stray top-level keys
+        let nested: DecklistFile =
+            serde_json::from_str(r#"{"tournament": {"format": "Pioneer"}, "format": "Modern"}"#).unwrap();
+        assert_eq!(nested.tournament.format.as_deref(), Some("Pioneer"));
+    }
+
+    #[test]
+    fn test_summarize_flat_tournament_file() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/flat.json",
+            r#"{"format": "Modern", "decks": [{"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]}]}"#,
+        );
+        let summary =
+            summarize_file(&temp_dir.path().join("2025/01/10/flat.json"), None, &ProcessOptions::default()).unwrap();
+        assert_eq!(summary.format.as_deref(), Some("Modern"));
+        assert_eq!(summary.decks, 1);
+    }
+
+    #[test]
+    fn test_conditional_file_mountain_given_bolt() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+
+        let counts = conditional_file(
+            &path,
+            &test_scope(&["Modern"]),
+            &parse_card_criterion("Lightning Bolt"),
+            &parse_card_criterion("Mountain"),
+            &MatchOptions::default(),
+        );
+
+        assert_eq!(counts, ConditionalCounts { with_a: 2, with_both: 1 });
+        assert_eq!(counts.share(), Some(0.5));
+        assert_eq!(ConditionalCounts::default().share(), None);
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let before_path = temp_dir.path().join("before.txt");
+        let after_path = temp_dir.path().join("after.jsonl");
+        std::fs::write(&before_path, "10.00 Lightning Bolt\n8.50 Thoughtseize\n2.00 Mishra's Bauble\n").unwrap();
+        std::fs::write(
+            &after_path,
+            concat!(
+                r#"{"name": "Thoughtseize", "weight": 9.5}"#, "\n",
+                r#"{"name": "Lightning Bolt", "weight": 7.0}"#, "\n",
+                r#"{"name": "Orcish Bowmasters", "weight": 6.0}"#, "\n",
+            ),
+        )
+        .unwrap();
+
+        let before = parse_snapshot(&std::fs::read_to_string(&before_path).unwrap()).unwrap();
+        let after = parse_snapshot(&std::fs::read_to_string(&after_path).unwrap()).unwrap();
+        let shifts = diff_snapshots(&before, &after);
+
+        let summary: Vec<(&str, &str, f64)> = shifts.iter().map(|s| (s.name.as_str(), s.label(), s.delta())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Orcish Bowmasters", "new", 6.0),
+                ("Lightning Bolt", "lost", -3.0),
+                ("Mishra's Bauble", "dropped", -2.0),
+                ("Thoughtseize", "gained", 1.0),
+            ]
+        );
+        // Thoughtseize moved from rank 2 to rank 1
+        assert_eq!(shifts[3].before, Some((2, 8.5)));
+        assert_eq!(shifts[3].after, Some((1, 9.5)));
+        assert_eq!(shifts[0].before, None);
+
+        assert_eq!(parse_snapshot("12.5% Fire // Ice").unwrap(), vec![("Fire // Ice".to_string(), 12.5)]);
+        assert!(parse_snapshot("Lightning Bolt").is_err());
+
+        let same = diff_snapshots(&before, &before);
+        assert!(same.iter().all(|s| s.label() == "unchanged"));
+    }
+
+    #[test]
+    fn test_parse_snapshot_rejects_extra_columns() {
+        let rejected = |text: &str| {
+            let err = parse_snapshot(text).unwrap_err();
+            assert!(err.contains("only plain"), "{}", err);
+        };
+        // --show-raw
+        rejected("12.34 7 Lightning Bolt");
+        // --with-price, with and without a known price
+        rejected("12.34 Lightning Bolt $1.25");
+        rejected("12.34 Lightning Bolt -");
+        // --detailed
+        rejected("12.34 50.0% 4.00 Lightning Bolt");
+        // --dates
+        rejected("12.34 2025-01-10 2025-02-01 Lightning Bolt");
+        // --ranked, as ranked_text_lines lays it out
+        let ranked = ranked_text_lines(&[(1, "1234.56".to_string(), "  Lightning Bolt".to_string())]);
+        assert_eq!(ranked, vec!["  1.  1234.56  Lightning Bolt"]);
+        rejected(&ranked.join("\n"));
+
+        assert!(parse_snapshot("12.34 Fire // Ice").is_ok());
+    }
+
+    #[test]
+    fn test_deck_diff_reports_single_change() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/event.json",
+            r#"{
+                "tournament": {"name": "Updates", "format": "Modern"},
+                "decks": [
+                    {"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 4, "name": "Thoughtseize"}, {"count": 20, "name": "Mountain"}]},
+                    {"player": "Alice2", "mainboard": [{"count": 20, "name": "Mountain"}, {"count": 4, "name": "Lightning Bolt"}, {"count": 2, "name": "Thoughtseize"}]}
+                ]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/event.json");
+        let path = path.to_str().unwrap();
+
+        let a = load_deck_source(&format!("{}#1", path)).unwrap();
+        let b = load_deck_source(&format!("{}@alice2", path)).unwrap();
+        let diffs = diff_boards(a.mainboard.as_deref().unwrap(), b.mainboard.as_deref().unwrap());
+        assert_eq!(
+            diffs,
+            vec![CardDiff {
+                name: "Thoughtseize".to_string(),
+                before: 4,
+                after: 2,
+            }]
+        );
+
+        assert_eq!(load_deck_source(path).unwrap().player, Some("Alice".to_string()));
+        assert!(load_deck_source(&format!("{}#3", path)).is_err());
+        assert!(load_deck_source(&format!("{}#0", path)).is_err());
+    }
+
+    #[test]
+    fn test_search_file_for_decks_finds_matching_deck() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: Some(4),
+            excluded: false,
+            pattern: None,
+        }];
+
+        let matches = search_file_for_decks(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &criteria,
+            &MatchOptions::default(),
+        );
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].player, Some("Alice".to_string()));
+    }
+
+    #[test]
+    fn test_parse_placement() {
+        assert_eq!(parse_placement("1st"), Some(1));
+        assert_eq!(parse_placement("22nd Place"), Some(22));
+        assert_eq!(parse_placement("Top 8"), Some(8));
+        assert_eq!(parse_placement("5-0"), None);
+        assert_eq!(parse_placement(""), None);
+    }
+
+    #[test]
+    fn test_card_timeline_chronological() {
+        let temp_dir = TempDir::new().unwrap();
+        let event = |name: &str, bolt_result: &str| {
+            format!(
+                r#"{{
+                    "tournament": {{"name": "{}", "format": "Modern"}},
+                    "decks": [
+                        {{"player": "Alice", "result": "{}", "mainboard": [{{"count": 4, "name": "Lightning Bolt"}}]}},
+                        {{"player": "Bob", "result": "1st", "mainboard": [{{"count": 4, "name": "Thoughtseize"}}]}}
+                    ]
+                }}"#,
+                name, bolt_result
+            )
+        };
+        create_test_tournament_file(temp_dir.path(), "2025/03/01/late.json", &event("Late", "3rd"));
+        create_test_tournament_file(temp_dir.path(), "2025/01/15/early.json", &event("Early", "2nd"));
+        create_test_tournament_file(temp_dir.path(), "2025/02/01/mid.json", &event("Mid", "12th"));
+        create_test_tournament_file(temp_dir.path(), "2025/02/10/league.json", &event("League", "5-0"));
+
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        let scope = ScopeFilter {
+            today: days_since_epoch(2025, 3, 31),
+            ..test_scope(&["Modern"])
+        };
+        let options = MatchOptions {
+            max_placement: Some(8),
+            ..Default::default()
+        };
+        let entries = card_timeline(&files, &scope, &parse_card_criterion("Lightning Bolt"), &options);
+        let summary: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|m| (m.file_date.as_str(), m.tournament.name.as_deref().unwrap()))
+            .collect();
+        assert_eq!(summary, vec![("2025-01-15", "Early"), ("2025-03-01", "Late")]);
+        assert_eq!(entries[0].result.as_deref(), Some("2nd"));
+    }
+
+    #[test]
+    fn test_coverage_counts_per_day() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/03/01/first.json", sample_tournament_json());
+        create_test_tournament_file(temp_dir.path(), "2025/03/03/second.json", sample_tournament_json());
+        let legacy = sample_tournament_json().replace("\"Modern\"", "\"Legacy\"");
+        create_test_tournament_file(temp_dir.path(), "2025/03/03/legacy.json", &legacy);
+
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        let scope = ScopeFilter {
+            today: days_since_epoch(2025, 3, 31),
+            ..test_scope(&["Modern"])
+        };
+        let rows: Vec<(String, (u64, u64))> = coverage_counts(&files, &scope, Bucket::Day).into_iter().collect();
+        assert_eq!(
+            rows,
+            vec![("2025-03-01".to_string(), (2, 1)), ("2025-03-03".to_string(), (2, 1))]
+        );
+
+        // Saturday 1 March and Monday 3 March fall in different ISO weeks
+        let weeks: Vec<String> = coverage_counts(&files, &scope, Bucket::Week).into_keys().collect();
+        assert_eq!(weeks, vec!["2025-02-24", "2025-03-03"]);
+        let months = coverage_counts(&files, &scope, Bucket::Month);
+        assert_eq!(months.get("2025-03"), Some(&(4, 2)));
+        assert_eq!(bucket_label("2025-01-01", Bucket::Week), "2024-12-30");
+    }
+
+    #[test]
+    fn test_parse_format_patterns_aliases() {
+        let scope = |formats: &str| ScopeFilter {
+            format_patterns: parse_format_patterns(formats),
+            ..test_scope(&[])
+        };
+        let patterns = parse_format_patterns("pio, EDH,Std");
+        assert_eq!(patterns, vec!["pioneer", "commander", "standard"]);
+        let pioneer = scope("pio, EDH,Std");
+        assert!(pioneer.format_ok("Pioneer"));
+        assert!(pioneer.format_ok("Pioneer Challenge"));
+        assert!(!pioneer.format_ok("Modern"));
+
+        // Unknown tokens fall through to literal substring matching
+        let patterns = parse_format_patterns("Modern,premodern");
+        assert_eq!(patterns, vec!["modern", "premodern"]);
+        let modern = scope("Modern,premodern");
+        assert!(modern.format_ok("Modern Challenge"));
+        assert!(modern.format_ok("Premodern"));
+        assert!(!modern.format_ok("Pioneer"));
+    }
+
+    #[test]
+    fn test_exact_format_matching() {
+        let substring = test_scope(&["Modern"]);
+        assert!(substring.format_ok("Premodern"));
+        assert!(substring.format_ok("Modern"));
+
+        let exact = ScopeFilter {
+            exact_format: true,
+            ..test_scope(&["Modern"])
+        };
+        assert!(!exact.format_ok("Premodern"));
+        assert!(!exact.format_ok("Modern Challenge"));
+        assert!(exact.format_ok(" modern "));
+
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/premodern.json",
+            r#"{"tournament": {"name": "P", "format": "Premodern"}, "decks": [{"mainboard": [{"count": 4, "name": "Swords to Plowshares"}]}]}"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/premodern.json");
+        assert_eq!(process_file(&path, &substring, &ProcessOptions::default(), None).decks, 1);
+        assert_eq!(process_file(&path, &exact, &ProcessOptions::default(), None).decks, 0);
+    }
+
+    #[test]
+    fn test_search_file_for_decks_respects_format_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: None,
+            excluded: false,
+            pattern: None,
+        }];
+
+        // Search with wrong format
+        let matches = search_file_for_decks(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Standard"]),
+            &criteria,
+            &MatchOptions::default(),
+        );
+
+        assert_eq!(matches.len(), 0);
+    }
+
+    #[test]
+    fn test_search_file_for_decks_require_url() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"name": "Test Tournament", "format": "Modern"},
+                "decks": [
+                    {
+                        "player": "Alice",
+                        "anchor_uri": "https://example.com/deck/alice",
+                        "mainboard": [{"count": 4, "name": "Lightning Bolt"}]
+                    },
+                    {
+                        "player": "Bob",
+                        "mainboard": [{"count": 4, "name": "Lightning Bolt"}]
+                    }
+                ]
+            }"#,
+        );
+
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let criteria = vec![CardCriterion {
+            name: "Lightning Bolt".to_string(),
+            count: None,
+            excluded: false,
+            pattern: None,
+        }];
+
+        let matches = search_file_for_decks(
+            &path,
+            &test_scope(&["Modern"]),
+            &criteria,
+            &MatchOptions::default(),
+        );
+        assert_eq!(matches.len(), 2);
+
+        let options = MatchOptions {
+            require_url: true,
+            ..Default::default()
+        };
+        let matches = search_file_for_decks(
+            &path,
+            &test_scope(&["Modern"]),
+            &criteria,
+            &options,
+        );
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].player, Some("Alice".to_string()));
+        assert_eq!(matches[0].url, Some("https://example.com/deck/alice".to_string()));
+
+        // Only Alice's deck, the one with a URL, counts towards top-cards
+        let options = ProcessOptions {
+            use_weight: false,
+            require_url: true,
+            ..Default::default()
+        };
+        let counts = process_file(&path, &test_scope(&["Modern"]), &options, None);
+        assert_eq!(counts.decks, 1);
+        assert_eq!(counts.cards["Lightning Bolt"], 4.0);
+        let decks = collect_weighted_decks(&path, &test_scope(&["Modern"]), &options).unwrap();
+        assert_eq!(decks.len(), 1);
+    }
+
+    #[test]
+    fn test_process_file_aggregates_card_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+
+        let options = ProcessOptions {
+            use_weight: false, // no weight for easier testing
+            ..Default::default()
+        };
+        let counts = process_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &options,
+            None,
+        );
+
+        // Lightning Bolt: 4 (Alice) + 2 (Bob) = 6
+        assert_eq!(counts.cards.get("Lightning Bolt"), Some(&6.0));
+        // Mountain: 20 (Alice only)
+        assert_eq!(counts.cards.get("Mountain"), Some(&20.0));
+        // Swamp: 20 (Bob only)
+        assert_eq!(counts.cards.get("Swamp"), Some(&20.0));
+    }
+
+    #[test]
+    fn test_process_file_presence_counts_decks() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+
+        let options = ProcessOptions {
+            use_weight: false,
+            presence: true,
+            ..Default::default()
+        };
+        let counts = process_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &options,
+            None,
+        );
+
+        // Both decks run Lightning Bolt, as a 4-of and a 2-of
+        assert_eq!(counts.cards.get("Lightning Bolt"), Some(&2.0));
+        assert_eq!(counts.cards.get("Mountain"), Some(&1.0));
+        assert_eq!(counts.decks_by_format.get("Modern"), Some(&2.0));
+        assert_eq!(counts.total_decks(), 2.0);
+
+        assert_eq!(deck_share_percent(counts.cards["Lightning Bolt"], counts.total_decks()), 100.0);
+        assert_eq!(deck_share_percent(counts.cards["Mountain"], counts.total_decks()), 50.0);
+    }
+
+    #[test]
+    fn test_process_file_count_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+        let scope = ScopeFilter {
+            today: days_since_epoch(2025, 2, 25),
+            ..test_scope(&["Modern"])
+        };
+        let bolts = |mode: CountMode| {
+            let options = ProcessOptions::default().with_count_mode(mode);
+            let counts = process_file(&temp_dir.path().join("2025/01/10/tournament.json"), &scope, &options, None);
+            counts.cards["Lightning Bolt"]
+        };
+
+        // 45 days old at a 45-day half-life: weight 0.5. Alice runs 4, Bob 2
+        assert!((bolts(CountMode::Copies) - 3.0).abs() < 1e-9);
+        assert_eq!(bolts(CountMode::Decks), 2.0);
+        assert!((bolts(CountMode::Presence) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_deck_size_within_tolerance() {
+        assert!(deck_size_within_tolerance("Modern", 60));
+        assert!(deck_size_within_tolerance("Modern", 61));
+        assert!(!deck_size_within_tolerance("Modern", 40));
+        assert!(deck_size_within_tolerance("Sealed", 40));
+        assert!(deck_size_within_tolerance("Duel Commander", 100));
+        assert!(!deck_size_within_tolerance("Duel Commander", 60));
+    }
+
+    #[test]
+    fn test_process_file_flags_limited_deck_labeled_modern() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"name": "Mislabeled", "format": "Modern"},
+                "decks": [
+                    {"mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 56, "name": "Mountain"}]},
+                    {"mainboard": [{"count": 23, "name": "Shock"}, {"count": 17, "name": "Mountain"}]}
+                ]
+            }"#,
+        );
+
+        let counts = process_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &ProcessOptions::default(),
+            None,
+        );
+
+        assert_eq!(counts.deck_sizes["Modern"], SizeTally { within: 1, outside: 1 });
+    }
+
+    #[test]
+    fn test_rank_decay_weights_favor_most_recent() {
+        let weights = rank_decay_weights(&[5, 0, 40, 5]);
+        assert_eq!(weights, vec![0.5, 1.0, 0.25, 0.5]);
+
+        let counts_with = |card: &str| CardCounts {
+            cards: [(card.to_string(), 4.0)].into_iter().collect(),
+            decks_by_format: [("Modern".to_string(), 1.0)].into_iter().collect(),
+            ..Default::default()
+        };
+        let merged = merge_rank_decayed(vec![
+            (100, counts_with("Old Card")),
+            (3, counts_with("New Card")),
+        ]);
+        assert_eq!(merged.cards["New Card"], 4.0);
+        assert_eq!(merged.cards["Old Card"], 2.0);
+        assert_eq!(merged.total_decks(), 1.5);
+    }
+
+    #[test]
+    fn test_process_file_board_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let counts = |skip_sideboard, skip_mainboard| {
+            let options = ProcessOptions {
+                use_weight: false,
+                skip_sideboard,
+                skip_mainboard,
+                ..Default::default()
+            };
+            process_file(&path, &test_scope(&["Modern"]), &options, None).cards
+        };
+
+        let both = counts(false, false);
+        assert_eq!(both.get("Blood Moon"), Some(&2.0));
+        assert_eq!(both.get("Mountain"), Some(&20.0));
+
+        let main = counts(true, false);
+        assert!(!main.contains_key("Blood Moon"));
+        assert_eq!(main.get("Mountain"), Some(&20.0));
+
+        let side = counts(false, true);
+        assert_eq!(side.get("Blood Moon"), Some(&2.0));
+        assert!(!side.contains_key("Mountain"));
+        assert_eq!(side.len(), 1);
+    }
+
+    #[test]
+    fn test_process_file_max_decks_per_event() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"name": "T", "format": "Modern"},
+                "decks": [
+                    {"result": "3rd", "mainboard": [{"count": 4, "name": "Thoughtseize"}]},
+                    {"result": "1st", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]},
+                    {"result": "2nd", "mainboard": [{"count": 4, "name": "Fatal Push"}]}
+                ]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let options = ProcessOptions {
+            use_weight: false,
+            max_decks: Some(1),
+            ..Default::default()
+        };
+        let counts = process_file(&path, &test_scope(&["Modern"]), &options, None);
+        assert_eq!(counts.decks, 1);
+        assert_eq!(counts.cards.len(), 1);
+        assert_eq!(counts.cards.get("Lightning Bolt"), Some(&4.0));
+
+        let weighted = collect_weighted_decks(&path, &test_scope(&["Modern"]), &options).unwrap();
+        assert_eq!(weighted.len(), 1);
+        assert!(weighted[0].cards.iter().any(|(name, _)| name == "Lightning Bolt"));
+    }
+
+    #[test]
+    fn test_process_file_skip_sideboard_keeps_mainboard_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let scope = test_scope(&["Modern"]);
+
+        let full = process_file(&path, &scope, &ProcessOptions::default(), None);
+        let options = ProcessOptions {
+            skip_sideboard: true,
+            ..Default::default()
+        };
+        let mainboard_only = process_file(&path, &scope, &options, None);
+
+        assert!(full.cards.contains_key("Blood Moon"));
+        assert!(!mainboard_only.cards.contains_key("Blood Moon"));
+        for (name, count) in &mainboard_only.cards {
+            assert_eq!(full.cards.get(name), Some(count), "{}", name);
+        }
+        assert_eq!(mainboard_only.cards.len(), full.cards.len() - 1);
+        assert_eq!(mainboard_only.total_decks(), full.total_decks());
+    }
+
+    #[test]
+    fn test_cross_format_share_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/modern.json",
+            r#"{
+                "tournament": {"name": "M", "format": "Modern"},
+                "decks": [
+                    {"mainboard": [{"count": 4, "name": "Thoughtseize"}, {"count": 4, "name": "Lightning Bolt"}]},
+                    {"mainboard": [{"count": 4, "name": "Thoughtseize"}]}
+                ]
+            }"#,
+        );
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/pioneer.json",
+            r#"{
+                "tournament": {"name": "P", "format": "Pioneer"},
+                "decks": [
+                    {"mainboard": [{"count": 4, "name": "Thoughtseize"}]},
+                    {"mainboard": [{"count": 4, "name": "Thoughtseize"}, {"count": 4, "name": "Fatal Push"}]}
+                ]
+            }"#,
+        );
+        let scope = test_scope(&["Modern", "Pioneer"]);
+        let options = ProcessOptions {
+            use_weight: false,
+            by_format: true,
+            format_presence: true,
+            ..Default::default()
+        };
+        let counts = ["modern", "pioneer"]
+            .iter()
+            .map(|f| {
+                let path = temp_dir.path().join(format!("2025/01/10/{}.json", f));
+                process_file(&path, &scope, &options, None)
+            })
+            .fold(CardCounts::default(), CardCounts::merge);
+
+        // The main counts still sum copies
+        assert_eq!(counts.cards["Thoughtseize"], 16.0);
+
+        let names = vec!["Thoughtseize".to_string(), "Lightning Bolt".to_string()];
+        let stats = cross_format_share_stats(&counts, &names);
+        // Played in every deck of both formats
+        assert!((stats[0].mean - 1.0).abs() < 1e-9);
+        assert!(stats[0].stddev < 1e-9);
+        // Half of Modern, none of Pioneer
+        assert!((stats[1].mean - 0.25).abs() < 1e-9);
+        assert!((stats[1].stddev - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_split_by_format() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/modern.json",
+            r#"{
+                "tournament": {"name": "M", "format": "Modern"},
+                "decks": [
+                    {"mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 4, "name": "Mountain"}]},
+                    {"mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 2, "name": "Thoughtseize"}]}
+                ]
+            }"#,
+        );
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/legacy.json",
+            r#"{
+                "tournament": {"name": "L", "format": "Legacy"},
+                "decks": [
+                    {"mainboard": [{"count": 4, "name": "Brainstorm"}, {"count": 1, "name": "Thoughtseize"}]}
+                ]
+            }"#,
+        );
+        let scope = test_scope(&["Legacy", "Modern"]);
+        let options = ProcessOptions {
+            use_weight: false,
+            by_format: true,
+            ..Default::default()
+        };
+        let counts = ["modern", "legacy"]
+            .iter()
+            .map(|f| {
+                let path = temp_dir.path().join(format!("2025/01/10/{}.json", f));
+                process_file(&path, &scope, &options, None)
+            })
+            .fold(CardCounts::default(), CardCounts::merge);
+
+        let sections = split_by_format(&counts, &scope, true);
+        let names = |section: &FormatSection| -> Vec<String> { section.cards.iter().map(|(n, _)| n.clone()).collect() };
+        // Sections follow the requested order
+        assert_eq!(sections[0].format, "Legacy");
+        assert_eq!(sections[0].decks, 1.0);
+        assert_eq!(names(&sections[0]), vec!["Brainstorm", "Thoughtseize"]);
+        assert_eq!(sections[1].format, "Modern");
+        assert_eq!(sections[1].decks, 2.0);
+        assert_eq!(names(&sections[1]), vec!["Lightning Bolt", "Thoughtseize"]);
+        assert_eq!(sections[1].cards[0].1, 8.0);
+    }
+
+    #[test]
+    fn test_alphabetical_inventory() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let counts = process_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &options,
+            None,
+        );
+
+        let inventory = alphabetical(counts.cards.into_iter().collect());
+        let names: Vec<&str> = inventory.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Blood Moon", "Lightning Bolt", "Mountain", "Ragavan, Nimble Pilferer", "Swamp", "Thoughtseize"]
+        );
+        assert_eq!(inventory[1].1, 6.0);
+    }
+
+    fn sort_fixture() -> Vec<OutputLine> {
+        let line = |rank: usize, name: &str, count: f64| OutputLine {
+            rank,
+            name: name.to_string(),
+            count,
+            raw: None,
+            price: None,
+            dates: None,
+            presence: None,
+        };
+        vec![
+            line(1, "lightning Bolt", 10.0),
+            line(2, "Delver of Secrets", 6.0),
+            line(2, "Insectile Aberration", 6.0),
+            line(3, "Counterspell", 6.0),
+            line(4, "Ancestral Recall", 1.0),
+        ]
+    }
+
+    fn sorted_names(order: SortOrder) -> Vec<String> {
+        sort_output_lines(sort_fixture(), order).into_iter().map(|l| l.name).collect()
+    }
+
+    #[test]
+    fn test_sort_output_weight_desc() {
+        let expected: Vec<String> = sort_fixture().into_iter().map(|l| l.name).collect();
+        assert_eq!(sorted_names(SortOrder::WeightDesc), expected);
+    }
+
+    #[test]
+    fn test_sort_output_weight_asc() {
+        assert_eq!(
+            sorted_names(SortOrder::WeightAsc),
+            vec!["Ancestral Recall", "Delver of Secrets", "Insectile Aberration", "Counterspell", "lightning Bolt"]
+        );
+    }
+
+    #[test]
+    fn test_sort_output_name() {
+        // Case-insensitive, and the back face follows Delver rather than sorting under I
+        assert_eq!(
+            sorted_names(SortOrder::Name),
+            vec!["Ancestral Recall", "Counterspell", "Delver of Secrets", "Insectile Aberration", "lightning Bolt"]
+        );
+    }
+
+    #[test]
+    fn test_ranked_text_lines_align() {
+        let rows = vec![
+            (1, "1234.56".to_string(), "  Lightning Bolt".to_string()),
+            (2, "98.10".to_string(), "  Ragavan, Nimble Pilferer".to_string()),
+            (10, "7.00".to_string(), "  Mountain".to_string()),
+        ];
+        let lines = ranked_text_lines(&rows);
+        assert_eq!(lines[0], "  1.  1234.56  Lightning Bolt");
+        assert_eq!(lines[1], "  2.    98.10  Ragavan, Nimble Pilferer");
+        assert_eq!(lines[2], " 10.     7.00  Mountain");
+        let name_columns: HashSet<usize> = lines
+            .iter()
+            .zip(["Lightning Bolt", "Ragavan", "Mountain"])
+            .map(|(line, name)| line.find(name).unwrap())
+            .collect();
+        assert_eq!(name_columns.len(), 1);
+    }
+
+    #[test]
+    fn test_names_only_output() {
+        let temp_dir = TempDir::new().unwrap();
+        // Distinct counts, so the order does not depend on tie-breaking
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"format": "Modern"},
+                "decks": [{"mainboard": [
+                    {"count": 4, "name": "Lightning Bolt"},
+                    {"count": 1, "name": "Blood Moon"},
+                    {"count": 3, "name": "Ragavan, Nimble Pilferer"},
+                    {"count": 18, "name": "Mountain"},
+                    {"count": 2, "name": "Thoughtseize"}
+                ]}]
+            }"#,
+        );
+        let dir = temp_dir.path().to_str().unwrap();
+        let matches = Args::command()
+            .try_get_matches_from(["top_cards", "-d", dir, "-f", "Modern", "top-cards", "--card-list"])
+            .unwrap();
+        let args = args_with_config(&matches, &Config::default()).unwrap();
+        let Some(Commands::TopCards(top_args)) = &args.command else {
+            panic!("expected top-cards");
+        };
+        assert!(top_args.names_only);
+
+        let run = |names_only: bool, file: &str| {
+            let path = temp_dir.path().join(file);
+            let top_args = TopCardsArgs {
+                num: 4,
+                names_only,
+                no_cache: true,
+                resolve_faces: false,
+                output: Some(path.to_str().unwrap().to_string()),
+                ..default_top_cards_args()
+            };
+            run_top_cards(&args, &top_args);
+            std::fs::read_to_string(path).unwrap()
+        };
+        let weighted = run(false, "weighted.txt");
+        let names = run(true, "names.txt");
+
+        let names: Vec<&str> = names.lines().collect();
+        assert_eq!(names.len(), 4);
+        assert!(names.iter().all(|name| !name.starts_with(|c: char| c.is_ascii_digit())));
+        let weighted_names: Vec<&str> = weighted.lines().map(|line| line.split_once(' ').unwrap().1).collect();
+        assert_eq!(names, weighted_names);
+        assert_eq!(names, ["Mountain", "Lightning Bolt", "Ragavan, Nimble Pilferer", "Thoughtseize"]);
+    }
+
+    #[test]
+    fn test_jsonl_lines_parse_independently() {
+        let lines = [
+            OutputLine {
+                rank: 1,
+                name: "Lightning Bolt".to_string(),
+                count: 12.5,
+                raw: None,
+                price: None,
+                dates: None,
+                presence: None,
+            },
+            OutputLine {
+                rank: 2,
+                name: "Fire // Ice".to_string(),
+                count: 3.0,
+                raw: Some(4.0),
+                price: None,
+                dates: None,
+                presence: None,
+            },
+        ];
+        let output: String = lines.iter().map(|l| jsonl_line(l) + "\n").collect();
+
+        let parsed: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed.iter().all(|v| v.is_object()));
+        assert_eq!(parsed[1]["name"], "Fire // Ice");
+        assert_eq!(parsed[0]["weight"], 12.5);
+        assert!(parsed[0].get("raw").is_none());
+        assert_eq!(parsed[1]["raw"], 4.0);
+    }
+
+    #[test]
+    fn test_process_file_tracks_raw_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let mut scope = test_scope(&["Modern"]);
+        scope.today = days_since_epoch(2025, 1, 10) + 90;
+        let options = ProcessOptions {
+            track_raw: true,
+            ..Default::default()
+        };
+
+        let counts = process_file(&temp_dir.path().join("2025/01/10/tournament.json"), &scope, &options, None);
+
+        // 4 from Alice plus 2 from Bob, decayed over two half-lives
+        assert_eq!(counts.raw_cards["Lightning Bolt"], 6.0);
+        assert!((counts.cards["Lightning Bolt"] - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_process_file_tracks_presence() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let options = ProcessOptions {
+            use_weight: false,
+            track_presence: true,
+            ..Default::default()
+        };
+
+        let counts = process_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &options,
+            None,
+        );
+
+        // Alice runs 4 and Bob 2: 6 copies across both decks
+        assert_eq!(counts.cards["Lightning Bolt"], 6.0);
+        assert_eq!(counts.presence_cards["Lightning Bolt"], 2.0);
+        assert_eq!(counts.presence_cards["Thoughtseize"], 1.0);
+        assert_eq!(detailed_columns(6.0, 2.0, counts.total_decks()), " 100.0% 3.00");
+        assert_eq!(detailed_columns(4.0, 1.0, counts.total_decks()), " 50.0% 4.00");
+    }
+
+    #[test]
+    fn test_process_file_tracks_card_dates() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let later = sample_tournament_json().replace("Thoughtseize", "Duress");
+        create_test_tournament_file(temp_dir.path(), "2025/02/20/tournament.json", &later);
+        let options = ProcessOptions {
+            track_dates: true,
+            ..Default::default()
+        };
+
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        let counts = files
+            .iter()
+            .map(|path| process_file(path, &test_scope(&["Modern"]), &options, None))
+            .fold(CardCounts::default(), CardCounts::merge);
+
+        let dates = |card: &str| {
+            let (first, last) = &counts.card_dates[card];
+            (first.as_str(), last.as_str())
+        };
+        assert_eq!(dates("Lightning Bolt"), ("2025-01-10", "2025-02-20"));
+        assert_eq!(dates("Thoughtseize"), ("2025-01-10", "2025-01-10"));
+        assert_eq!(dates("Duress"), ("2025-02-20", "2025-02-20"));
+
+        // Untracked by default
+        let counts = process_file(&files[0], &test_scope(&["Modern"]), &ProcessOptions::default(), None);
+        assert!(counts.card_dates.is_empty());
+    }
+
+    #[test]
+    fn test_future_dated_file_handling() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2035/01/01/typo.json", sample_tournament_json());
+        let path = temp_dir.path().join("2035/01/01/typo.json");
+        let mut scope = test_scope(&["Modern"]);
+
+        // Skipped by default, for both commands
+        assert!(process_file(&path, &scope, &ProcessOptions::default(), None).cards.is_empty());
+        let criteria = vec![parse_card_criterion("Lightning Bolt")];
+        assert!(search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default()).is_empty());
+
+        scope.future_dates = FutureDates::Clamp;
+        let counts = process_file(&path, &scope, &ProcessOptions::default(), None);
+        assert_eq!(counts.cards["Lightning Bolt"], 6.0);
+
+        scope.future_dates = FutureDates::Allow;
+        let counts = process_file(&path, &scope, &ProcessOptions::default(), None);
+        assert!(counts.cards["Lightning Bolt"] > 6.0);
+    }
+
+    #[test]
+    fn test_remove_basic_lands() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+
+        let mut cards = process_file(&path, &test_scope(&["Modern"]), &ProcessOptions::default(), None).cards;
+        assert!(cards.contains_key("Mountain"));
+        cards.insert("Snow-Covered Island".to_string(), 1.0);
+
+        remove_basic_lands(&mut cards);
+        assert!(!cards.contains_key("Mountain"));
+        assert!(!cards.contains_key("Swamp"));
+        assert!(!cards.contains_key("Snow-Covered Island"));
+        assert!(cards.contains_key("Lightning Bolt"));
+        assert!(is_basic_land(" wastes "));
+    }
+
+    #[test]
+    fn test_process_file_dedupe_skips_repeated_deck() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"name": "Rescraped", "format": "Modern"},
+                "decks": [
+                    {"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 20, "name": "Mountain"}]},
+                    {"player": "Alice", "mainboard": [{"count": 20, "name": "Mountain"}, {"count": 4, "name": "Lightning Bolt"}]}
+                ]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let scope = test_scope(&["Modern"]);
+
+        let plain = process_file(&path, &scope, &ProcessOptions::default(), None);
+        let deduped = process_file(
+            &path,
+            &scope,
+            &ProcessOptions {
+                dedupe: true,
+                ..Default::default()
+            },
+            None,
+        );
+
+        let plain_bolt = plain.cards["Lightning Bolt"];
+        let deduped_bolt = deduped.cards["Lightning Bolt"];
+        assert!((plain_bolt - 2.0 * deduped_bolt).abs() < 1e-9);
+        let plain_decks = plain.decks_by_format["Modern"];
+        assert!((plain_decks - 2.0 * deduped.decks_by_format["Modern"]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_process_file_drops_short_card_names() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"name": "Junk", "format": "Modern"},
+                "decks": [
+                    {"mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 1, "name": "x"}, {"count": 1, "name": " "}]}
+                ]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let scope = test_scope(&["Modern"]);
+
+        let counts = process_file(&path, &scope, &ProcessOptions::default(), None);
+        assert!(counts.cards.contains_key("Lightning Bolt"));
+        assert!(!counts.cards.contains_key("x"));
+        assert_eq!(counts.dropped_names, 2);
+
+        // Empty names are dropped even with no minimum
+        let options = ProcessOptions {
+            min_name_length: 0,
+            ..Default::default()
+        };
+        let counts = process_file(&path, &scope, &options, None);
+        assert!(counts.cards.contains_key("x"));
+        assert_eq!(counts.dropped_names, 1);
+    }
+
+    #[test]
+    fn test_process_file_cache_hit_matches_cold_parse() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let cache_path = temp_dir.path().join("cache/index.json");
+        let scope = test_scope(&["Modern"]);
+        let options = ProcessOptions::default();
+
+        let cold = process_file(&path, &scope, &options, None);
+
+        // First run populates the cache
+        let cache = FileCache::load(cache_path.clone());
+        assert!(cache.get(&path).is_none());
+        let first = process_file(&path, &scope, &options, Some(&cache));
+        cache.save().unwrap();
+
+        // Second run is served from the cache
+        let cache = FileCache::load(cache_path);
+        assert!(cache.get(&path).is_some());
+        let cached = process_file(&path, &scope, &options, Some(&cache));
+
+        assert_eq!(first.cards, cold.cards);
+        assert_eq!(cached.cards, cold.cards);
+        assert_eq!(cached.decks_by_format, cold.decks_by_format);
+        assert_eq!(cached.deck_sizes, cold.deck_sizes);
+    }
+
+    #[test]
+    fn test_file_cache_discards_other_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let cache_path = temp_dir.path().join("cache/index.json");
+
+        let cache = FileCache::load(cache_path.clone());
+        process_file(&path, &test_scope(&["Modern"]), &ProcessOptions::default(), Some(&cache));
+        cache.save().unwrap();
+        assert!(FileCache::load(cache_path.clone()).get(&path).is_some());
+
+        let text = std::fs::read_to_string(&cache_path).unwrap();
+        let mut index: serde_json::Value = serde_json::from_str(&text).unwrap();
+        index["version"] = serde_json::json!(FILE_CACHE_VERSION + 1);
+        std::fs::write(&cache_path, index.to_string()).unwrap();
+        assert!(FileCache::load(cache_path.clone()).get(&path).is_none());
+
+        // Unversioned caches predate the version field
+        std::fs::write(&cache_path, index["entries"].to_string()).unwrap();
+        assert!(FileCache::load(cache_path).get(&path).is_none());
+    }
+
+    #[test]
+    fn test_bootstrap_ranks_dominant_card_is_stable() {
+        let mut decks = Vec::new();
+        for i in 0..20 {
+            decks.push(WeightedDeck {
+                weight: 1.0,
+                cards: vec![
+                    ("Lightning Bolt".to_string(), 4),
+                    (format!("Filler {}", i), 1),
+                ],
+                format: "Modern".to_string(),
+                fingerprint: i,
+                multiplier: 1,
+                within_size: true,
+                file: Arc::from(Path::new("event.json")),
+                date: "2025-01-10".to_string(),
+            });
+        }
+        let top = vec!["Lightning Bolt".to_string(), "Filler 0".to_string()];
+
+        let stats = bootstrap_ranks(&decks, &top, 50, &mut StdRng::seed_from_u64(7));
+
+        assert_eq!(stats[0].mean, 1.0);
+        assert_eq!(stats[0].stddev, 0.0);
+        // A 1-of in a single deck bounces around depending on the resample
+        assert!(stats[1].stddev > 0.0);
+    }
+
+    #[test]
+    fn test_collect_weighted_decks_merges_boards() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let decks = collect_weighted_decks(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &options,
+        ).unwrap();
+
+        assert_eq!(decks.len(), 2);
+        assert_eq!(decks[0].weight, 1.0);
+        assert!(decks[0].cards.contains(&("Blood Moon".to_string(), 2)));
+    }
+
+    #[test]
+    fn test_counts_from_decks_matches_process_file() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/12/league.json",
+            r#"{
+                "tournament": {"name": "League", "format": "Modern", "date": "2025-01-12"},
+                "decks": [
+                    {"player": "Carol", "wins": 3, "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 56, "name": "Mountain"}], "sideboard": [{"count": 1, "name": "X"}]},
+                    {"player": "Carol", "wins": 3, "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 56, "name": "Mountain"}]},
+                    {"player": "Dave", "mainboard": [{"count": 4, "name": "Thoughtseize"}]}
+                ]
+            }"#,
+        );
+        let scope = ScopeFilter {
+            min_deck_size: Some(20),
+            ..test_scope(&["Modern"])
+        };
+        let options = ProcessOptions {
+            use_weight: false,
+            dedupe: true,
+            win_weight: true,
+            min_name_length: 2,
+            track_raw: true,
+            track_presence: true,
+            track_dates: true,
+            by_format: true,
+            ..Default::default()
+        };
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+
+        let expected = par_merge_counts(&files, |path| process_file(path, &scope, &options, None));
+        let decks: Vec<WeightedDeck> = files
+            .iter()
+            .flat_map(|path| collect_weighted_decks(path, &scope, &options).unwrap())
+            .collect();
+        let counts = counts_from_decks(&decks, &options);
+
+        assert_eq!(counts.decks, 3);
+        assert_eq!(counts.cards, expected.cards);
+        assert_eq!(counts.decks_by_format, expected.decks_by_format);
+        assert_eq!(counts.decks, expected.decks);
+        assert_eq!(counts.files, expected.files);
+        assert_eq!(counts.deck_sizes, expected.deck_sizes);
+        assert_eq!(counts.card_dates, expected.card_dates);
+        assert_eq!(counts.raw_cards, expected.raw_cards);
+        assert_eq!(counts.presence_cards, expected.presence_cards);
+        assert_eq!(counts.cards_by_format, expected.cards_by_format);
+        assert_eq!(counts.dropped_names, expected.dropped_names);
+    }
+
+    #[test]
+    fn test_summarize_file_streaming_matches_full_parse() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut decks = Vec::new();
+        for i in 0..2000 {
+            decks.push(format!(
+                r#"{{"player": "P{i}", "result": {i}, "mainboard": [{{"count": {n}, "name": "Lightning Bolt"}}, {{"count": 20, "name": "Card {m}"}}], "sideboard": [{{"count": 1, "name": "Card {k}"}}], "extra": {{"ignored": [1, 2]}}}}"#,
+                i = i,
+                n = i % 4 + 1,
+                m = i % 37,
+                k = i % 11,
+            ));
+        }
+        // Decks come before the tournament header to exercise key ordering
+        let content = format!(
+            r#"{{"decks": [{}], "tournament": {{"name": "Big", "format": "Modern"}}}}"#,
+            decks.join(",")
+        );
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/big.json", &content);
+
+        let streamed = summarize_file(&temp_dir.path().join("2025/01/10/big.json"), None, &ProcessOptions::default()).unwrap();
+
+        // Reference: materialize the whole file and tally it by hand
+        let data: DecklistFile = serde_json::from_str(&content).unwrap();
+        let mut copies: HashMap<String, u32> = HashMap::new();
+        let mut presence: HashMap<String, u32> = HashMap::new();
+        let mut sizes = SizeTally::default();
+        for deck in data.decks.unwrap() {
+            let main_size: u32 = deck.mainboard.iter().flatten().map(|c| c.count).sum();
+            if deck_size_within_tolerance("Modern", main_size) {
+                sizes.within += 1;
+            } else {
+                sizes.outside += 1;
+            }
+            let mut names = HashSet::new();
+            for card in deck.mainboard.into_iter().chain(deck.sideboard).flatten() {
+                *copies.entry(card.name.clone()).or_insert(0) += card.count;
+                names.insert(card.name);
+            }
+            for name in names {
+                *presence.entry(name).or_insert(0) += 1;
+            }
+        }
+
+        assert_eq!(streamed.format, Some("Modern".to_string()));
+        assert_eq!(streamed.decks, 2000);
+        assert_eq!(streamed.copies, copies);
+        assert_eq!(streamed.presence, presence);
+        assert_eq!(streamed.sizes, sizes);
+    }
+
+    #[test]
+    fn test_process_file_counts_decks_and_files() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+
+        let counts = process_file(&path, &test_scope(&["Modern"]), &ProcessOptions::default(), None);
+        assert_eq!(counts.decks, 2);
+        assert_eq!(counts.files, 1);
+
+        let merged = counts.merge(process_file(&path, &test_scope(&["Modern"]), &ProcessOptions::default(), None));
+        assert_eq!((merged.decks, merged.files), (4, 2));
+
+        let filtered = process_file(&path, &test_scope(&["Standard"]), &ProcessOptions::default(), None);
+        assert_eq!((filtered.decks, filtered.files), (0, 0));
+    }
+
+    #[test]
+    fn test_size_weight_scales_small_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let event = |num_decks: usize| {
+            let deck = r#"{"player": "P", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]}"#;
+            format!(
+                r#"{{"tournament": {{"format": "Modern"}}, "decks": [{}]}}"#,
+                vec![deck; num_decks].join(",")
+            )
+        };
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/local.json", &event(8));
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/open.json", &event(100));
+        let options = ProcessOptions {
+            use_weight: false,
+            size_cap: Some(64),
+            ..Default::default()
+        };
+        let scope = test_scope(&["Modern"]);
+
+        // 8 decks x 4 copies at 8/64, 100 decks x 4 copies at full weight
+        let local = process_file(&temp_dir.path().join("2025/01/10/local.json"), &scope, &options, None);
+        let open = process_file(&temp_dir.path().join("2025/01/10/open.json"), &scope, &options, None);
+        assert_eq!(local.cards["Lightning Bolt"], 4.0);
+        assert_eq!(open.cards["Lightning Bolt"], 400.0);
+        assert_eq!(local.total_decks(), 1.0);
+
+        assert_eq!(event_size_weight(8, None), 1.0);
+        assert_eq!(event_size_weight(32, Some(64)), 0.5);
+    }
+
+    #[test]
+    fn test_aggregate_merges_across_runs() {
+        let temp_dir = TempDir::new().unwrap();
+        let aggregate_path = temp_dir.path().join("since.json");
+        create_test_tournament_file(temp_dir.path(), "data/2025/01/10/first.json", sample_tournament_json());
+        let scope = ScopeFilter {
+            today: days_since_epoch(2025, 1, 20),
+            ..test_scope(&["Modern"])
+        };
+        let options = ProcessOptions::default();
+        let settings = AggregateSettings::new(&scope, &options, &[], &[]);
+        let data_dir = temp_dir.path().join("data");
+        let process = |files: &[PathBuf]| {
+            files
+                .iter()
+                .map(|path| process_file(path, &scope, &options, None))
+                .fold(CardCounts::default(), CardCounts::merge)
+        };
+
+        // First run: no aggregate yet, everything is processed
+        assert!(Aggregate::load(&aggregate_path, &settings).is_none());
+        let files = collect_json_files(data_dir.to_str().unwrap(), None, None);
+        let first = process(&files);
+        let marker = files.iter().filter_map(|path| file_day(path)).max().unwrap();
+        Aggregate::from_counts(&first, settings.clone(), scope.today, marker)
+            .save(&aggregate_path)
+            .unwrap();
+
+        // Second run on the same day only picks up the new file
+        create_test_tournament_file(temp_dir.path(), "data/2025/01/15/second.json", sample_tournament_json());
+        let aggregate = Aggregate::load(&aggregate_path, &settings).unwrap();
+        let all_files = collect_json_files(data_dir.to_str().unwrap(), None, None);
+        let new_files = files_after(all_files.clone(), aggregate.marker);
+        assert_eq!(new_files.len(), 1);
+        let merged = process(&new_files).merge(aggregate.decayed_counts(scope.today));
+        let full = process(&all_files);
+        assert!((merged.cards["Lightning Bolt"] - full.cards["Lightning Bolt"]).abs() < 1e-9);
+        assert_eq!(merged.decks, 4);
+        assert_eq!(merged.files, 2);
+
+        // Stored weights decay as days pass, and other settings start over
+        let later = aggregate.decayed_counts(scope.today + options.half_life as i64);
+        assert!((later.cards["Lightning Bolt"] - first.cards["Lightning Bolt"] / 2.0).abs() < 1e-9);
+        let other = AggregateSettings {
+            half_life: 30.0,
+            ..settings.clone()
+        };
+        assert!(Aggregate::load(&aggregate_path, &other).is_none());
+        let board = ProcessOptions {
+            skip_sideboard: true,
+            ..options.clone()
+        };
+        assert!(Aggregate::load(&aggregate_path, &AggregateSettings::new(&scope, &board, &[], &[])).is_none());
+        let players = ScopeFilter {
+            players: vec!["Alice".to_string()],
+            ..scope.clone()
+        };
+        assert!(Aggregate::load(&aggregate_path, &AggregateSettings::new(&players, &options, &[], &[])).is_none());
+        let globs = ["*challenge*".to_string()];
+        assert!(Aggregate::load(&aggregate_path, &AggregateSettings::new(&scope, &options, &[], &globs)).is_none());
+        assert!(Aggregate::load(&aggregate_path, &settings).is_some());
+    }
+
+    #[test]
+    fn test_quantity_card_schema_matches_count_name() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/count.json",
+            r#"{"tournament": {"format": "Modern"}, "decks": [{
+                "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 20, "name": "Mountain"}],
+                "sideboard": [{"count": 2, "name": "Blood Moon"}]
+            }]}"#,
+        );
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/quantity.json",
+            r#"{"tournament": {"format": "Modern"}, "decks": [{
+                "cards": [{"quantity": 4, "card": "Lightning Bolt"}, {"quantity": "20", "card": "Mountain"}],
+                "sideboard": [{"quantity": 2, "card": "Blood Moon"}]
+            }]}"#,
+        );
+        let scope = test_scope(&["Modern"]);
+        let options = ProcessOptions::default();
+
+        let count = process_file(&temp_dir.path().join("2025/01/10/count.json"), &scope, &options, None);
+        let quantity = process_file(&temp_dir.path().join("2025/01/10/quantity.json"), &scope, &options, None);
+        assert_eq!(count.cards, quantity.cards);
+        assert_eq!(quantity.cards["Mountain"], count.cards["Mountain"]);
+        assert_eq!(quantity.cards.len(), 3);
+        assert!(!UNPARSEABLE_FILES.lock().unwrap().contains(&temp_dir.path().join("2025/01/10/quantity.json")));
+    }
+
+    #[test]
+    fn test_process_file_counts_unparseable_files() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/broken.json", r#"{"tournament": {"format": "Mod"#);
+        let path = temp_dir.path().join("2025/01/10/broken.json");
+        let counts = process_file(&path, &test_scope(&["Modern"]), &ProcessOptions::default(), None);
+        assert!(UNPARSEABLE_FILES.lock().unwrap().contains(&path));
+        assert_eq!(counts.files, 0);
+    }
+
+    #[test]
+    fn test_skipped_file_tally() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/good.json", sample_tournament_json());
+        create_test_tournament_file(temp_dir.path(), "2025/01/11/truncated.json", r#"{"decks": [{"mainboard": ["#);
+        create_test_tournament_file(temp_dir.path(), "2025/01/12/garbage.json", "not json at all");
+        create_test_tournament_file(temp_dir.path(), "2025/01/13/good.json", sample_tournament_json());
+
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        let counts = files
+            .iter()
+            .map(|path| process_file(path, &test_scope(&["Modern"]), &ProcessOptions::default(), None))
+            .fold(CardCounts::default(), CardCounts::merge);
+        assert_eq!(counts.files, 2);
+        // Other tests note their own files concurrently, so only look for ours
+        let skipped: Vec<PathBuf> = UNPARSEABLE_FILES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|path| path.starts_with(temp_dir.path()))
+            .cloned()
+            .collect();
+        assert_eq!(
+            unparseable_report(&skipped, true),
+            vec![
+                "Warning: Skipped 2 unparseable files".to_string(),
+                format!("  {}", temp_dir.path().join("2025/01/11/truncated.json").display()),
+                format!("  {}", temp_dir.path().join("2025/01/12/garbage.json").display()),
+            ]
+        );
+
+        let paths = vec![PathBuf::from("b.json"), PathBuf::from("a.json"), PathBuf::from("b.json")];
+        assert_eq!(unparseable_report(&paths, false), vec!["Warning: Skipped 2 unparseable files"]);
+        assert_eq!(
+            unparseable_report(&paths, true),
+            vec!["Warning: Skipped 2 unparseable files", "  a.json", "  b.json"]
+        );
+        assert!(unparseable_report(&[], true).is_empty());
+    }
+
+    #[test]
+    fn test_write_progress_quiet() {
+        let mut out = Vec::new();
+        write_progress(&mut out, false, format_args!("Processing {} files...", 3));
+        assert_eq!(String::from_utf8(out).unwrap(), "Processing 3 files...\n");
+
+        let mut out = Vec::new();
+        write_progress(&mut out, true, format_args!("Processing {} files...", 3));
+        write_progress(&mut out, true, format_args!("Loading card type data..."));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_unique_decks_counts_reposted_list_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let league = |player: &str, bolts: u32| {
+            format!(
+                r#"{{"tournament": {{"format": "Modern"}}, "decks": [{{
+                    "player": "{}",
+                    "mainboard": [{{"count": {}, "name": "Lightning Bolt"}}, {{"count": 20, "name": "Mountain"}}],
+                    "sideboard": [{{"count": 2, "name": "Blood Moon"}}]
+                }}]}}"#,
+                player, bolts
+            )
+        };
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/league.json", &league("Alice", 4));
+        create_test_tournament_file(temp_dir.path(), "2025/01/12/repost.json", &league("alice_mtgo", 4));
+        create_test_tournament_file(temp_dir.path(), "2025/01/12/other.json", &league("Bob", 3));
+        let scope = ScopeFilter {
+            today: days_since_epoch(2025, 1, 20),
+            ..test_scope(&["Modern"])
+        };
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+
+        let decks: Vec<WeightedDeck> = collect_json_files(temp_dir.path().to_str().unwrap(), None, None)
+            .iter()
+            .flat_map(|path| collect_weighted_decks(path, &scope, &options).unwrap())
+            .collect();
+        assert_eq!(decks.len(), 3);
+        let counts = counts_from_decks(&unique_decks(decks), &options);
+        assert_eq!(counts.decks, 2);
+        assert_eq!(counts.cards["Lightning Bolt"], 7.0);
+        assert_eq!(counts.cards["Blood Moon"], 4.0);
+        assert_eq!(counts.total_decks(), 2.0);
+    }
+
+    #[test]
+    fn test_summarize_file_rejects_missing_tournament() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/bad.json", r#"{"decks": null}"#);
+        assert!(summarize_file(&temp_dir.path().join("2025/01/10/bad.json"), None, &ProcessOptions::default()).is_none());
+    }
+
+    #[test]
+    fn test_unmatched_format_patterns() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let scope = test_scope(&["Modern", "Moderne"]);
+        let counts = process_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &scope,
+            &ProcessOptions::default(),
+            None,
+        );
+        assert_eq!(unmatched_format_patterns(&scope, &counts), vec!["Moderne"]);
+        assert_eq!(
+            unmatched_format_patterns(&scope, &CardCounts::default()),
+            vec!["Modern", "Moderne"]
+        );
+
+        // Only formats the user asked for are worth a warning
+        let parse = |argv: &[&str], config: &Config| {
+            let matches = Args::command().try_get_matches_from(argv).unwrap();
+            args_with_config(&matches, config).unwrap().formats_set
+        };
+        assert!(!parse(&["top_cards"], &Config::default()));
+        assert!(parse(&["top_cards", "-f", "Moderne"], &Config::default()));
+        assert!(parse(&["top_cards", "top-cards", "-f", "Moderne"], &Config::default()));
+        let config = Config {
+            formats: Some("Modern".to_string()),
+            ..Default::default()
+        };
+        assert!(parse(&["top_cards"], &config));
+    }
+
+    #[test]
+    fn test_card_counts_merge_sums_deck_totals() {
+        let mut a = CardCounts::default();
+        a.cards.insert("Lightning Bolt".to_string(), 1.0);
+        a.decks_by_format.insert("Modern".to_string(), 2.0);
+        let mut b = CardCounts::default();
+        b.cards.insert("Lightning Bolt".to_string(), 1.0);
+        b.decks_by_format.insert("Modern".to_string(), 1.0);
+        b.decks_by_format.insert("Legacy".to_string(), 1.0);
+
+        let merged = a.merge(b);
+        assert_eq!(merged.cards["Lightning Bolt"], 2.0);
+        assert_eq!(merged.total_decks(), 4.0);
+        assert_eq!(deck_share_percent(merged.cards["Lightning Bolt"], merged.total_decks()), 50.0);
+        assert_eq!(deck_share_percent(1.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_run_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let matches = Args::command()
+            .try_get_matches_from(["top_cards", "-f", "Modern,Legacy", "top-cards", "-l", "30"])
+            .unwrap();
+        let args = args_with_config(&matches, &Config::default()).unwrap();
+        let top_args = match &args.command {
+            Some(Commands::TopCards(a)) => a,
+            _ => panic!("expected top-cards"),
+        };
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        let counts = process_file(&files[0], &test_scope(&["Modern"]), &ProcessOptions::default(), None);
+
+        let created = UNIX_EPOCH + Duration::from_secs(1_736_497_800);
+        let manifest = run_manifest(&args, top_args, &ScopeFilter::from_args(&args), &counts, created);
+        assert_eq!(manifest["formats"], serde_json::json!(["modern", "legacy"]));
+        assert_eq!(manifest["decks"], 2);
+        assert_eq!(manifest["files"], 1);
+        assert_eq!(manifest["half_life"], 30.0);
+        assert_eq!(manifest["weight_function"], "exp");
+        assert_eq!(manifest["created"], "2025-01-10T08:30:00Z");
+        assert_eq!(manifest["scryfall"]["bulk"], "oracle_cards");
+    }
+
+    #[test]
+    fn test_player_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/17/tournament.json",
+            r#"{
+                "tournament": {"name": "Weekly", "format": "Modern"},
+                "decks": [
+                    {"player": "alice", "result": "1st", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 20, "name": "Mountain"}]}
+                ]
+            }"#,
+        );
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+
+        let stats = player_stats(&files, &test_scope(&["Modern"]));
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name.to_lowercase(), "alice");
+        assert_eq!(stats[0].decks, 2);
+        assert_eq!(stats[0].best_placement, Some(1));
+        // Basic lands never count as the most played card
+        assert_eq!(stats[0].top_card.as_deref(), Some("Lightning Bolt"));
+        assert_eq!(stats[1].name, "Bob");
+        assert_eq!(stats[1].decks, 1);
+        assert_eq!(stats[1].best_placement, Some(2));
+        assert_eq!(stats[1].top_card.as_deref(), Some("Thoughtseize"));
+    }
+
+    #[test]
+    fn test_co_play_matrix_is_symmetric() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+
+        let names: Vec<String> = ["Lightning Bolt", "Mountain", "Swamp", "Blood Moon"]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        let index: HashMap<String, usize> = names.iter().enumerate().map(|(i, n)| (n.clone(), i)).collect();
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let matrix = co_play_matrix_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &[parse_card_criterion("Lightning Bolt")],
+            &MatchOptions::default(),
+            &options,
+            &index,
+        );
+
+        let cell = |a: usize, b: usize| matrix[a * names.len() + b];
+        for a in 0..names.len() {
+            for b in 0..names.len() {
+                assert_eq!(cell(a, b), cell(b, a));
+            }
+        }
+        // Both decks run Bolt; only Alice's runs Mountain and Blood Moon
+        assert_eq!(cell(0, 0), 2.0);
+        assert_eq!(cell(0, 1), 1.0);
+        assert_eq!(cell(0, 2), 1.0);
+        assert_eq!(cell(1, 2), 0.0);
+        assert_eq!(cell(1, 3), 1.0);
+
+        let mut csv = Vec::new();
+        write_matrix_csv(&mut csv, &names[..2], &[2.0, 1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            ",Lightning Bolt,Mountain\nLightning Bolt,2.00,1.00\nMountain,1.00,1.00\n"
+        );
+        assert_eq!(csv_field("Ragavan, Nimble Pilferer"), "\"Ragavan, Nimble Pilferer\"");
+    }
+
+    #[test]
+    fn test_co_occur_file_counts_cards_alongside_seed() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+
+        let seeds = vec![parse_card_criterion("Lightning Bolt")];
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let counts = co_occur_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &seeds,
+            &MatchOptions::default(),
+            &options,
+        );
+
+        assert_eq!(counts.get("Mountain"), Some(&20.0));
+        assert_eq!(counts.get("Ragavan, Nimble Pilferer"), Some(&4.0));
+        assert_eq!(counts.get("Blood Moon"), Some(&2.0));
+        // The seed itself is not reported
+        assert_eq!(counts.get("Lightning Bolt"), None);
+
+        // Only Bob's deck runs Thoughtseize
+        let seeds = vec![parse_card_criterion("Thoughtseize")];
+        let counts = co_occur_file(
+            &temp_dir.path().join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &seeds,
+            &MatchOptions::default(),
+            &options,
+        );
+        assert_eq!(counts.get("Swamp"), Some(&20.0));
+        assert_eq!(counts.get("Mountain"), None);
+    }
+
+    #[test]
+    fn test_inferred_rounds() {
+        assert_eq!(inferred_rounds(0), 0);
+        assert_eq!(inferred_rounds(1), 0);
+        assert_eq!(inferred_rounds(2), 1);
+        assert_eq!(inferred_rounds(8), 3);
+        assert_eq!(inferred_rounds(9), 4);
+        assert_eq!(inferred_rounds(32), 5);
+    }
+
+    #[test]
+    fn test_min_rounds_skips_small_events() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/small.json",
+            r#"{
+                "tournament": {"name": "Small", "format": "Modern", "rounds": 2},
+                "decks": [{"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]}]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/small.json");
+        let criteria = vec![parse_card_criterion("Lightning Bolt")];
+
+        let mut scope = test_scope(&["Modern"]);
+        scope.min_rounds = Some(2);
+        assert_eq!(process_file(&path, &scope, &ProcessOptions::default(), None).cards.len(), 1);
+        assert_eq!(search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default()).len(), 1);
+
+        scope.min_rounds = Some(4);
+        assert!(process_file(&path, &scope, &ProcessOptions::default(), None).cards.is_empty());
+        assert!(search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_dry_run_counts_in_scope_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let recent = days_to_civil(today_days() - 10);
+        let recent = format!("{:04}/{:02}/{:02}", recent.0, recent.1, recent.2);
+        let fixtures = [
+            (format!("modern/{}/a.json", recent), sample_tournament_json().to_string()),
+            (
+                format!("legacy/{}/b.json", recent),
+                r#"{"event": {"format": "Legacy"}, "decks": []}"#.to_string(),
+            ),
+            (format!("pioneer/{}/c.json", recent), r#"{"format": "Modern", "decks": []}"#.to_string()),
+            (format!("pauper/{}/d.json", recent), r#"{"format": "Pauper", "decks": []}"#.to_string()),
+            ("modern/2001/01/10/old.json".to_string(), sample_tournament_json().to_string()),
+            (format!("modern/{}/bare.json", recent), "[]".to_string()),
+            (format!("modern/{}/broken.json", recent), "{\"tournament\": ".to_string()),
+        ];
+        for (path, content) in &fixtures {
+            create_test_tournament_file(temp_dir.path(), path, content);
+        }
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        assert_eq!(files.len(), 7);
+
+        let tournament = peek_tournament(&temp_dir.path().join(format!("legacy/{}/b.json", recent))).unwrap();
+        assert_eq!(tournament.format.as_deref(), Some("Legacy"));
+
+        let scope = test_scope(&["Modern", "Legacy"]);
+        let formats = dry_run_formats(&files, &scope);
+        assert_eq!(formats.values().sum::<usize>(), 3);
+        assert_eq!(formats["Modern"], 2);
+        assert_eq!(formats["Legacy"], 1);
+        let loaded = files.iter().filter(|path| load_scoped_file(path, &scope).is_some()).count();
+        assert_eq!(loaded, 3);
+
+        let scope = ScopeFilter {
+            infer_format: true,
+            ..test_scope(&["Modern", "Legacy"])
+        };
+        assert_eq!(dry_run_formats(&files, &scope)["modern"], 1);
+    }
+
+    #[test]
+    fn test_par_merge_counts_matches_pairwise_reduce() {
+        let temp_dir = TempDir::new().unwrap();
+        let cards = ["Lightning Bolt", "Thoughtseize", "Ragavan, Nimble Pilferer", "Blood Moon", "Mountain"];
+        for i in 0..60 {
+            let decks: Vec<String> = (0..=i % 4)
+                .map(|d| {
+                    let card = cards[(i + d) % cards.len()];
+                    format!(
+                        r#"{{"player": "P{}", "mainboard": [{{"count": {}, "name": "{}"}}, {{"count": 1, "name": "Card {}"}}]}}"#,
+                        d,
+                        d + 1,
+                        card,
+                        i
+                    )
+                })
+                .collect();
+            let format = if i % 3 == 0 { "Legacy" } else { "Modern" };
+            create_test_tournament_file(
+                temp_dir.path(),
+                &format!("2025/01/{:02}/event{}.json", i % 28 + 1, i),
+                &format!(r#"{{"tournament": {{"format": "{}"}}, "decks": [{}]}}"#, format, decks.join(",")),
+            );
+        }
+        let files = collect_json_files(temp_dir.path().to_str().unwrap(), None, None);
+        let scope = test_scope(&["Modern", "Legacy"]);
+        let options = ProcessOptions {
+            use_weight: false,
+            presence: true,
+            track_dates: true,
+            ..Default::default()
+        };
+
+        let pairwise = files
+            .par_iter()
+            .map(|path| process_file(path, &scope, &options, None))
+            .reduce(CardCounts::default, CardCounts::merge);
+        let folded = par_merge_counts(&files, |path| process_file(path, &scope, &options, None));
+
+        assert_eq!(folded.cards.len(), 65);
+        assert_eq!(folded.cards, pairwise.cards);
+        assert_eq!(folded.decks_by_format, pairwise.decks_by_format);
+        assert_eq!(folded.card_dates, pairwise.card_dates);
+        assert_eq!((folded.decks, folded.files), (pairwise.decks, pairwise.files));
+        assert_eq!((folded.decks, folded.files), (150, 60));
+    }
+
+    #[test]
+    fn test_win_weight() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/league.json",
+            r#"{
+                "tournament": {"name": "League", "format": "Modern"},
+                "decks": [
+                    {"player": "Alice", "wins": 5, "mainboard": [{"count": 4, "name": "Lightning Bolt"}]},
+                    {"player": "Bob", "record": "3-2", "mainboard": [{"count": 2, "name": "Lightning Bolt"}, {"count": 4, "name": "Thoughtseize"}]},
+                    {"player": "Carol", "mainboard": [{"count": 1, "name": "Thoughtseize"}]}
+                ]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/league.json");
+        let options = ProcessOptions {
+            use_weight: false,
+            win_weight: true,
+            ..Default::default()
+        };
+
+        let counts = process_file(&path, &test_scope(&["Modern"]), &options, None);
+        // Alice counts 6 times, Bob 4 times and Carol, with no record, once
+        assert_eq!(counts.cards["Lightning Bolt"], 4.0 * 6.0 + 2.0 * 4.0);
+        assert_eq!(counts.cards["Thoughtseize"], 4.0 * 4.0 + 1.0);
+        assert_eq!(counts.total_decks(), 11.0);
+        assert_eq!(counts.decks, 3);
+
+        let decks = collect_weighted_decks(&path, &test_scope(&["Modern"]), &options).unwrap();
+        assert_eq!(
+            counts_from_decks(&decks, &options).cards["Lightning Bolt"],
+            32.0
+        );
+
+        let unweighted = process_file(
+            &path,
+            &test_scope(&["Modern"]),
+            &ProcessOptions {
+                use_weight: false,
+                ..Default::default()
+            },
+            None,
+        );
+        assert_eq!(unweighted.cards["Lightning Bolt"], 6.0);
+        assert_eq!(unweighted.total_decks(), 3.0);
+    }
+
+    #[test]
+    fn test_bare_deck_array_files() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "modern/2025/01/10/bare.json",
+            r#"[
+                {"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]},
+                {"player": "Bob", "mainboard": [{"count": 2, "name": "Lightning Bolt"}]}
+            ]"#,
+        );
+        let path = temp_dir.path().join("modern/2025/01/10/bare.json");
+        let criteria = vec![parse_card_criterion("Lightning Bolt")];
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+
+        // Parsed, but without a format the filter skips it
+        let mut scope = test_scope(&["Modern"]);
+        let counts = process_file(&path, &scope, &options, None);
+        assert!(!UNPARSEABLE_FILES.lock().unwrap().contains(&path));
+        assert_eq!(counts.decks, 0);
+        assert!(search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default()).is_empty());
+
+        scope.infer_format = true;
+        assert_eq!(infer_format_from_path(&path).as_deref(), Some("modern"));
+        let counts = process_file(&path, &scope, &options, None);
+        assert_eq!(counts.decks, 2);
+        assert_eq!(counts.cards["Lightning Bolt"], 6.0);
+        assert_eq!(search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default()).len(), 2);
+
+        // The cache keeps the summary as parsed, inferring on every use
+        let cache_path = temp_dir.path().join("cache.json");
+        let cache = FileCache::load(cache_path.clone());
+        process_file(&path, &test_scope(&["Modern"]), &options, Some(&cache));
+        cache.save().unwrap();
+        let cache = FileCache::load(cache_path);
+        assert!(cache.get(&path).is_some_and(|summary| summary.format.is_none()));
+        assert_eq!(process_file(&path, &scope, &options, Some(&cache)).decks, 2);
+    }
+
+    #[test]
+    fn test_min_deck_size_skips_junk_decks() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"name": "Scraped", "format": "Modern"},
+                "decks": [
+                    {"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 56, "name": "Mountain"}]},
+                    {"player": "Junk", "mainboard": [{"count": 4, "name": "Thoughtseize"}, {"count": 1, "name": "Swamp"}]}
+                ]
+            }"#,
+        );
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let criteria = vec![parse_card_criterion("Lightning Bolt")];
+
+        let mut scope = test_scope(&["Modern"]);
+        assert_eq!(process_file(&path, &scope, &ProcessOptions::default(), None).decks, 2);
+
+        scope.min_deck_size = Some(40);
+        let counts = process_file(&path, &scope, &ProcessOptions::default(), None);
+        assert_eq!(counts.decks, 1);
+        assert!(!counts.cards.contains_key("Thoughtseize"));
+        assert_eq!(search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default()).len(), 1);
+
+        let matches = Args::command()
+            .try_get_matches_from(["top_cards", "top-cards", "--min-deck-size"])
+            .unwrap();
+        assert_eq!(args_with_config(&matches, &Config::default()).unwrap().min_deck_size, Some(60));
+    }
+
+    #[test]
+    fn test_min_rounds_infers_from_deck_count() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            sample_tournament_json(),
+        );
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+
+        // Two decks imply a single round
+        let mut scope = test_scope(&["Modern"]);
+        scope.min_rounds = Some(1);
+        assert!(!process_file(&path, &scope, &ProcessOptions::default(), None).cards.is_empty());
+        scope.min_rounds = Some(2);
+        assert!(process_file(&path, &scope, &ProcessOptions::default(), None).cards.is_empty());
+    }
+
+    fn player_tournament_json() -> &'static str {
+        r#"{
+            "tournament": {"name": "Players", "format": "Modern"},
+            "decks": [
+                {"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]},
+                {"player": "Bob", "mainboard": [{"count": 4, "name": "Thoughtseize"}]},
+                {"mainboard": [{"count": 4, "name": "Counterspell"}]}
+            ]
+        }"#
+    }
+
+    #[test]
+    fn test_player_filter_process_file() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/players.json", player_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/players.json");
+
+        let mut scope = test_scope(&["Modern"]);
+        let counts = process_file(&path, &scope, &ProcessOptions::default(), None);
+        assert_eq!(counts.cards.len(), 3);
+
+        // Case-insensitive, and the player-less deck is skipped
+        scope.players = vec!["alice".to_string()];
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let counts = process_file(&path, &scope, &options, None);
+        assert_eq!(counts.cards.len(), 1);
+        assert_eq!(counts.cards.get("Lightning Bolt"), Some(&4.0));
+        assert_eq!(counts.total_decks(), 1.0);
+
+        scope.players = vec!["alice".to_string(), "BOB".to_string()];
+        let counts = process_file(&path, &scope, &options, None);
+        assert_eq!(counts.cards.len(), 2);
+        assert_eq!(counts.cards.get("Counterspell"), None);
+    }
+
+    #[test]
+    fn test_compare_players_attributes_unique_cards() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/players.json",
+            r#"{
+                "tournament": {"name": "Players", "format": "Modern"},
+                "decks": [
+                    {"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 4, "name": "Ragavan, Nimble Pilferer"}]},
+                    {"player": "Alice", "mainboard": [{"count": 4, "name": "Lightning Bolt"}]},
+                    {"player": "Bob", "mainboard": [{"count": 2, "name": "Lightning Bolt"}, {"count": 4, "name": "Thoughtseize"}]}
+                ]
+            }"#,
+        );
+        let files = vec![temp_dir.path().join("2025/01/10/players.json")];
+        let scope = test_scope(&["Modern"]);
+        let options = ProcessOptions::default();
+
+        let rates_a = player_play_rates(&files, &scope, "alice", &options);
+        let rates_b = player_play_rates(&files, &scope, "bob", &options);
+        let comparison = compare_play_rates(&rates_a, &rates_b);
+
+        assert_eq!(comparison.only_a.len(), 1);
+        assert_eq!(comparison.only_a[0].0, "Ragavan, Nimble Pilferer");
+        assert!((comparison.only_a[0].1 - 0.5).abs() < 1e-9);
+        assert_eq!(comparison.only_b.len(), 1);
+        assert_eq!(comparison.only_b[0].0, "Thoughtseize");
+        assert_eq!(comparison.shared.len(), 1);
+        let (name, rate_a, rate_b) = &comparison.shared[0];
+        assert_eq!(name, "Lightning Bolt");
+        assert!((rate_a - 1.0).abs() < 1e-9);
+        assert!((rate_b - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_summarize_matches() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let criteria = vec![parse_card_criterion("Lightning Bolt"), parse_card_criterion("Blood Moon")];
+        let options = MatchOptions {
+            match_any: true,
+            include_sideboard: true,
+            ..Default::default()
+        };
+        let matches = search_file_for_decks(&path, &test_scope(&["Modern"]), &criteria, &options);
+        assert_eq!(matches.len(), 2);
+
+        let summaries = summarize_matches(&matches);
+        assert_eq!(
+            summaries,
+            vec![
+                CriterionSummary {
+                    name: "Lightning Bolt".to_string(),
+                    decks: 2,
+                    avg_main: 3.0,
+                    avg_side: 0.0,
+                },
+                CriterionSummary {
+                    name: "Blood Moon".to_string(),
+                    decks: 1,
+                    avg_main: 0.0,
+                    avg_side: 2.0,
+                },
+            ]
+        );
+        assert!(summarize_matches(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_loose_name_matching() {
+        assert_eq!(loose_name("Lim-Dûl's Vault"), "lim dul s vault");
+        assert_eq!(loose_name("Lim-Dul’s  Vault"), "lim dul s vault");
+        assert_eq!(loose_name("Séance"), "seance");
+
+        let deck: Deck = serde_json::from_str(
+            r#"{"mainboard": [{"count": 2, "name": "Lim-Dûl's Vault"}, {"count": 4, "name": "Jötun Grunt"}]}"#,
+        )
+        .unwrap();
+        let criteria = vec![parse_card_criterion("2 Lim-Dul's Vault"), parse_card_criterion("jotun")];
+        let strict = MatchOptions {
+            fuzzy: true,
+            ..Default::default()
+        };
+        assert!(deck_matches_criteria(&deck, &criteria, &strict).is_none());
+
+        let loose = MatchOptions {
+            loose: true,
+            ..strict
+        };
+        let matched = deck_matches_criteria(&deck, &criteria, &loose).unwrap();
+        assert_eq!(matched[0].found_main, 2);
+        assert_eq!(matched[1].found_main, 4);
+    }
+
+    #[test]
+    fn test_archetype_label() {
+        let archetypes = parse_archetypes(
+            r#"
+            [[archetypes]]
+            name = "Rakdos Midrange"
+            cards = ["Thoughtseize", "Lightning Bolt"]
+
+            [[archetypes]]
+            name = "Boros Energy"
+            cards = ["4 Ragavan, Nimble Pilferer"]
+
+            [[archetypes]]
+            name = "Red Aggro"
+            cards = ["4 Lightning Bolt"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(archetypes.len(), 3);
+
+        let file: DecklistFile = serde_json::from_str(sample_tournament_json()).unwrap();
+        let decks = file.decks.unwrap();
+        // Alice also matches Red Aggro, but the first matching archetype wins
+        assert_eq!(archetype_label(&archetypes, &decks[0]), Some("Boros Energy"));
+        assert_eq!(archetype_label(&archetypes, &decks[1]), Some("Rakdos Midrange"));
+        assert_eq!(archetype_label(&archetypes[1..2], &decks[1]), None);
+
+        assert!(parse_archetypes("[[archetypes]]\nname = \"Burn\"\n").is_err());
+        assert!(parse_archetypes("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_commander_key_merges_partners() {
+        let card = |name: &str| Card {
+            count: 1,
+            name: name.to_string(),
+        };
+        let tymna_first = [card("Tymna the Weaver"), card("Thrasios, Triton Hero")];
+        let thrasios_first = [card("Thrasios, Triton Hero"), card("Tymna the Weaver")];
+
+        assert_eq!(
+            commander_key(&tymna_first).as_deref(),
+            Some("Thrasios, Triton Hero + Tymna the Weaver")
+        );
+        assert_eq!(commander_key(&tymna_first), commander_key(&thrasios_first));
+        assert_eq!(commander_key(&[card("Atraxa, Praetors' Voice")]).as_deref(), Some("Atraxa, Praetors' Voice"));
+        assert_eq!(commander_key(&[]), None);
+
+        assert!(is_commander_format("Duel Commander"));
+        assert!(is_commander_format("cEDH"));
+        assert!(!is_commander_format("Modern"));
+    }
+
+    #[test]
+    fn test_player_filter_search_file_for_decks() {
+        let temp_dir = TempDir::new().unwrap();
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/players.json", player_tournament_json());
+        let path = temp_dir.path().join("2025/01/10/players.json");
+        let criteria = vec![parse_card_criterion("!Mox Opal")];
+
+        let mut scope = test_scope(&["Modern"]);
+        assert_eq!(search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default()).len(), 3);
+
+        scope.players = vec!["BOB".to_string()];
+        let matches = search_file_for_decks(&path, &scope, &criteria, &MatchOptions::default());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].player, Some("Bob".to_string()));
+    }
+
+    #[test]
+    fn test_decklist_export_url() {
+        assert_eq!(
+            decklist_export_url("https://www.mtggoldfish.com/deck/6123456#paper"),
+            Some("https://www.mtggoldfish.com/deck/download/6123456".to_string())
+        );
+        assert_eq!(
+            decklist_export_url("https://mtgtop8.com/event?e=123&d=456789&f=MO"),
+            Some("https://mtgtop8.com/mtgo?d=456789".to_string())
+        );
+        assert_eq!(decklist_export_url("https://example.com/deck/123"), None);
+    }
+
+    #[test]
+    fn test_fill_deck_from_text_response() {
+        // Body as returned by an MTGO-style export endpoint
+        let response = "4 Lightning Bolt\r\n4x Ragavan, Nimble Pilferer\r\n20 Mountain\r\n\r\n2 Blood Moon\r\n";
+        let mut deck = Deck {
+            player: Some("Alice".to_string()),
+            result: None,
+            url: Some("https://mtgtop8.com/event?e=1&d=2".to_string()),
+            mainboard: Some(vec![]),
+            sideboard: None,
+            commander: None,
+            wins: None,
+            match_record: None,
+        };
+
+        fill_deck_from_text(&mut deck, response);
+
+        let mainboard = deck.mainboard.unwrap();
+        assert_eq!(mainboard.len(), 3);
+        assert_eq!(mainboard[1].name, "Ragavan, Nimble Pilferer");
+        assert_eq!(mainboard[1].count, 4);
+        let sideboard = deck.sideboard.unwrap();
+        assert_eq!(sideboard.len(), 1);
+        assert_eq!(sideboard[0].name, "Blood Moon");
+    }
+
+    #[test]
+    fn test_parse_text_decklist_sideboard_header() {
+        let (main, side) = parse_text_decklist("Deck\n4 Thoughtseize\n// comment\nSideboard\n3 Fatal Push\n");
+        assert_eq!(main.len(), 1);
+        assert_eq!(side.len(), 1);
+        assert_eq!(side[0].count, 3);
+    }
+
+    #[test]
+    fn test_extract_date_from_path() {
+        let date = extract_date_from_path("/data/2025/01/15/tournament.json");
+        assert_eq!(date, Some((2025, 1, 15)));
 
-    // Take top N cards
-    let top_cards: Vec<_> = sorted.into_iter().take(top_args.num).collect();
-
-    // Resolve back faces if requested
-    let back_faces = if top_args.resolve_faces {
-        eprintln!("Loading double-faced card data...");
-        let faces = resolve_back_faces();
-        eprintln!("Loaded {} double-faced cards", faces.len());
-        faces
-    } else {
-        HashMap::new()
-    };
+        let no_date = extract_date_from_path("/data/tournament.json");
+        assert_eq!(no_date, None);
+    }
 
-    // Build final output: each card, plus back face if it has one
-    let mut final_cards: Vec<(String, f64)> = Vec::new();
-    for (name, count) in top_cards {
-        final_cards.push((name.clone(), count));
-        if let Some(back_face) = back_faces.get(&name) {
-            final_cards.push((back_face.clone(), count));
+    #[test]
+    fn test_weight_table_decreases_from_one() {
+        let table = weight_table(&ProcessOptions::default());
+        assert_eq!(table.len(), DEBUG_WEIGHT_AGES.len());
+        assert_eq!(table[0], (0, 1.0));
+        for pair in table.windows(2) {
+            assert!(pair[1].1 < pair[0].1);
         }
+
+        // Half-life age gets exactly half the weight
+        let at_half_life = table.iter().find(|(age, _)| *age == 45).unwrap();
+        assert!((at_half_life.1 - 0.5).abs() < 1e-9);
     }
 
-    // Output results
-    let output: Box<dyn Write> = match &top_args.output {
-        Some(path) => {
-            let file = File::create(path).expect("Failed to create output file");
-            Box::new(BufWriter::new(file))
-        }
-        None => Box::new(std::io::stdout()),
-    };
-    let mut writer = std::io::BufWriter::new(output);
+    #[test]
+    fn test_weight_functions() {
+        let options = |weight_function| ProcessOptions {
+            weight_function,
+            half_life: 45.0,
+            window: 90.0,
+            ..Default::default()
+        };
 
-    for (card, count) in final_cards {
-        writeln!(writer, "{:.2} {}", count, card).unwrap();
+        let exp = options(WeightFunction::Exp);
+        assert_eq!(exp.weight(0), 1.0);
+        assert!((exp.weight(45) - 0.5).abs() < 1e-9);
+        assert!(exp.weight(120) > 0.0);
+
+        let linear = options(WeightFunction::Linear);
+        assert_eq!(linear.weight(0), 1.0);
+        assert!((linear.weight(45) - 0.5).abs() < 1e-9);
+        assert_eq!(linear.weight(90), 0.0);
+        assert_eq!(linear.weight(120), 0.0);
+
+        let step = options(WeightFunction::Step);
+        assert_eq!(step.weight(0), 1.0);
+        assert_eq!(step.weight(45), 1.0);
+        assert_eq!(step.weight(89), 1.0);
+        assert_eq!(step.weight(90), 0.0);
+        assert_eq!(step.weight(120), 0.0);
+
+        let unweighted = ProcessOptions {
+            use_weight: false,
+            ..options(WeightFunction::Step)
+        };
+        assert_eq!(unweighted.weight(120), 1.0);
     }
 
-    if let Some(path) = &top_args.output {
-        eprintln!("Output written to {}", path);
+    #[test]
+    fn test_weight_table_unweighted() {
+        let table = weight_table(&ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        });
+        assert!(table.iter().all(|(_, w)| *w == 1.0));
     }
-}
 
-/// Run the search-decks command
-fn run_search_decks(args: &Args, search_args: &SearchDecksArgs) {
-    let search_dir = args.dir.clone().unwrap_or_else(|| {
-        if args.fetch {
-            args.data_dir.clone()
-        } else {
-            ".".to_string()
+    #[test]
+    fn test_collect_json_files_include_glob() {
+        let temp_dir = TempDir::new().unwrap();
+        for path in [
+            "2025/01/10/modern-challenge.json",
+            "2025/01/11/pioneer-challenge.json",
+            "modern/2025/01/12/league.json",
+            "2025/01/12/modern-notes.txt",
+        ] {
+            create_test_tournament_file(temp_dir.path(), path, "{}");
         }
-    });
+        let dir = temp_dir.path().to_str().unwrap();
 
-    let format_patterns: Vec<String> = args
-        .formats
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect();
-    let today = today_days();
+        assert_eq!(collect_json_files(dir, None, None).len(), 3);
 
-    // Parse card criteria
-    let criteria: Vec<CardCriterion> = search_args
-        .cards
-        .iter()
-        .map(|s| parse_card_criterion(s))
-        .collect();
+        let globs = build_globs("--include-glob", &["*modern*".to_string()]).unwrap();
+        let mut files: Vec<String> = collect_json_files(dir, globs.as_ref(), None)
+            .iter()
+            .map(|p| p.strip_prefix(dir).unwrap().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["2025/01/10/modern-challenge.json", "modern/2025/01/12/league.json"]);
 
-    eprintln!("Searching for decks containing:");
-    for c in &criteria {
-        match c.count {
-            Some(n) => eprintln!("  - {} {} ({})", n, c.name, if search_args.exact { "exact" } else { "at least" }),
-            None => eprintln!("  - {} (any count)", c.name),
-        }
+        // Multiple globs OR together
+        let globs = build_globs("--include-glob", &["*modern-*".to_string(), "*pioneer*".to_string()]).unwrap();
+        assert_eq!(collect_json_files(dir, globs.as_ref(), None).len(), 2);
+
+        assert!(build_globs("--include-glob", &["[".to_string()]).is_err());
     }
 
-    let files = collect_json_files(&search_dir);
-    eprintln!("Searching {} files...", files.len());
+    #[test]
+    fn test_collect_json_files_exclude_glob() {
+        let temp_dir = TempDir::new().unwrap();
+        for path in [
+            "2025/01/10/modern-challenge.json",
+            "2025/01/11/modern-league.json",
+            "2025/01/11/index.json",
+            "schema.json",
+            "meta/formats.json",
+        ] {
+            create_test_tournament_file(temp_dir.path(), path, "{}");
+        }
+        let dir = temp_dir.path().to_str().unwrap();
+        let relative = |files: Vec<PathBuf>| {
+            let mut files: Vec<String> = files
+                .iter()
+                .map(|p| p.strip_prefix(dir).unwrap().to_string_lossy().into_owned())
+                .collect();
+            files.sort();
+            files
+        };
 
-    // Search files in parallel
-    let mut all_matches: Vec<DeckMatch> = files
-        .par_iter()
-        .flat_map(|path| {
-            search_file_for_decks(
-                path,
-                &format_patterns,
-                today,
-                args.max_age,
-                &criteria,
-                search_args.exact,
-                search_args.sideboard,
-            )
-        })
-        .collect();
+        // Undated files are skipped by default
+        assert_eq!(
+            relative(collect_json_files(dir, None, None)),
+            vec!["2025/01/10/modern-challenge.json", "2025/01/11/index.json", "2025/01/11/modern-league.json"]
+        );
 
-    // Sort by date (most recent first)
-    all_matches.sort_by(|a, b| b.file_date.cmp(&a.file_date));
+        let exclude = build_globs("--exclude-glob", &["*/index.json".to_string(), "*league*".to_string()]).unwrap();
+        assert_eq!(
+            relative(collect_json_files(dir, None, exclude.as_ref())),
+            vec!["2025/01/10/modern-challenge.json"]
+        );
 
-    // Limit results
-    all_matches.truncate(search_args.num);
+        // Exclusion wins over inclusion
+        let include = build_globs("--include-glob", &["*modern*".to_string()]).unwrap();
+        assert_eq!(collect_json_files(dir, include.as_ref(), exclude.as_ref()).len(), 1);
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let deck = |card: &str| {
+            format!(
+                r#"{{"tournament": {{"name": "T", "format": "Modern"}}, "decks": [{{"mainboard": [{{"count": 4, "name": "{}"}}]}}]}}"#,
+                card
+            )
+        };
+        for (path, card) in [
+            ("2025/01/10/a.json", "Lightning Bolt"),
+            ("2025/01/11/b.json", "Thoughtseize"),
+            ("2025/01/12/c.json", "Fatal Push"),
+        ] {
+            create_test_tournament_file(temp_dir.path(), path, &deck(card));
+        }
+        let dir = temp_dir.path().display();
+        let list = format!("{dir}/2025/01/10/a.json\n\n  {dir}/2025/01/12/c.json  \n{dir}/2025/01/10/a.json\n{dir}/notes.json\n");
+
+        let files = read_file_list(std::io::Cursor::new(list), None, None);
+        assert_eq!(files.len(), 2);
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let counts = files
+            .iter()
+            .map(|path| process_file(path, &test_scope(&["Modern"]), &options, None))
+            .fold(CardCounts::default(), CardCounts::merge);
+        assert_eq!(counts.cards.get("Lightning Bolt"), Some(&4.0));
+        assert_eq!(counts.cards.get("Fatal Push"), Some(&4.0));
+        assert!(!counts.cards.contains_key("Thoughtseize"));
+
+        let exclude = build_globs("--exclude-glob", &["*/c.json".to_string()]).unwrap();
+        let list = format!("{dir}/2025/01/10/a.json\n{dir}/2025/01/12/c.json\n");
+        assert_eq!(read_file_list(list.as_bytes(), None, exclude.as_ref()).len(), 1);
+    }
 
-    eprintln!("Found {} matching decks", all_matches.len());
+    #[test]
+    fn test_sample_files() {
+        let files: Vec<PathBuf> = (1..=28)
+            .map(|day| PathBuf::from(format!("data/2025/02/{:02}/event.json", day)))
+            .collect();
+
+        let first = sample_files(files.clone(), 0.5, 42);
+        assert_eq!(sample_files(files.clone(), 0.5, 42), first);
+        assert!(!first.is_empty() && first.len() < files.len());
+        // Order doesn't matter, only the path and seed
+        let reversed: Vec<PathBuf> = files.iter().rev().cloned().collect();
+        let mut again = sample_files(reversed, 0.5, 42);
+        again.reverse();
+        assert_eq!(again, first);
+
+        assert_eq!(sample_files(files.clone(), 1.0, 7), files);
+
+        // Published FNV-1a test vectors, so the sample can't drift between builds
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+
+        assert_eq!(parse_fraction("0.25"), Ok(0.25));
+        assert!(parse_fraction("0").is_err());
+        assert!(parse_fraction("1.5").is_err());
+        assert!(parse_fraction("half").is_err());
+    }
 
-    if all_matches.is_empty() {
-        return;
+    #[test]
+    fn test_multiple_search_dirs_contribute() {
+        let public = TempDir::new().unwrap();
+        let private = TempDir::new().unwrap();
+        create_test_tournament_file(public.path(), "2025/01/10/a.json", sample_tournament_json());
+        create_test_tournament_file(private.path(), "2025/01/11/b.json", player_tournament_json());
+        let public_dir = public.path().to_str().unwrap().to_string();
+        let private_dir = private.path().to_str().unwrap().to_string();
+
+        let dirs = split_dirs(&format!("{}, {}", public_dir, private_dir));
+        assert_eq!(dirs, vec![public_dir.clone(), private_dir.clone()]);
+
+        let files = collect_json_files_from(&dirs, None, None);
+        assert_eq!(files.len(), 2);
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let counts = files
+            .iter()
+            .map(|path| process_file(path, &test_scope(&["Modern"]), &options, None))
+            .fold(CardCounts::default(), CardCounts::merge);
+        assert!(counts.cards.contains_key("Thoughtseize"));
+        assert!(counts.cards.contains_key("Counterspell"));
+        assert_eq!(counts.cards["Lightning Bolt"], 10.0);
+
+        // The same tree reached twice is only listed once
+        let overlapping = vec![public_dir.clone(), format!("{}/2025/../2025", public_dir)];
+        assert_eq!(collect_json_files_from(&overlapping, None, None).len(), 1);
     }
 
-    // Output results
-    println!();
-    for (i, deck_match) in all_matches.iter().enumerate() {
-        println!("=== Deck {} ===", i + 1);
-        println!("Date: {}", deck_match.file_date);
-        if let Some(name) = &deck_match.tournament.name {
-            println!("Tournament: {}", name);
-        }
-        if let Some(format) = &deck_match.tournament.format {
-            println!("Format: {}", format);
-        }
-        if let Some(player) = &deck_match.player {
-            println!("Player: {}", player);
-        }
-        if let Some(result) = &deck_match.result {
-            println!("Result: {}", result);
-        }
-        if let Some(url) = &deck_match.url {
-            println!("URL: {}", url);
-        }
+    #[test]
+    fn test_fetch_updates_only_first_data_dir() {
+        let matches = Args::command()
+            .try_get_matches_from(["top_cards", "--fetch", "--data-dir", "public, private"])
+            .unwrap();
+        let args = args_with_config(&matches, &Config::default()).unwrap();
+
+        assert_eq!(fetch_dir(&args.data_dir), Some("public".to_string()));
+        assert_eq!(resolve_search_dirs(&args), vec!["public".to_string(), "private".to_string()]);
+        assert_eq!(fetch_dir(" ,"), None);
+    }
 
-        println!("\nMatched cards:");
-        for m in &deck_match.matched_cards {
-            let req = match m.requested {
-                Some(n) => format!(" (requested: {})", n),
-                None => String::new(),
-            };
-            println!("  {} (main: {}, side: {}){}", m.name, m.found_main, m.found_side, req);
-        }
+    #[test]
+    fn test_git_clone_args() {
+        let args = git_clone_args("https://example.com/repo.git", "./data", None, false);
+        assert_eq!(args, vec!["clone", "--depth=1", "https://example.com/repo.git", "./data"]);
+
+        let args = git_clone_args("https://example.com/repo.git", "./data", Some("extra-data"), false);
+        assert_eq!(
+            args,
+            vec!["clone", "--depth=1", "--branch", "extra-data", "https://example.com/repo.git", "./data"]
+        );
+    }
 
-        println!("\nMainboard ({} cards):", deck_match.mainboard.iter().map(|c| c.count).sum::<u32>());
-        for card in &deck_match.mainboard {
-            println!("  {} {}", card.count, card.name);
-        }
+    /// Records git invocations instead of running them, failing any whose
+    /// first argument is in `failing`
+    #[derive(Default)]
+    struct MockGit {
+        calls: std::cell::RefCell<Vec<String>>,
+        failing: Vec<&'static str>,
+    }
 
-        if !deck_match.sideboard.is_empty() {
-            println!("\nSideboard ({} cards):", deck_match.sideboard.iter().map(|c| c.count).sum::<u32>());
-            for card in &deck_match.sideboard {
-                println!("  {} {}", card.count, card.name);
+    impl GitRunner for MockGit {
+        fn run(&self, args: &[&str], _cwd: &Path) -> Result<(), String> {
+            self.calls.borrow_mut().push(args.join(" "));
+            if self.failing.contains(&args[0]) {
+                return Err(format!("git {} failed", args[0]));
             }
+            Ok(())
         }
-        println!();
     }
-}
 
-fn main() {
-    let args = Args::parse();
+    #[test]
+    fn test_fetch_data_repo_clones_without_git_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join("data");
+        let data_dir = data_dir.to_str().unwrap();
+        let git = MockGit::default();
 
-    // Fetch data repository if requested
-    if args.fetch {
-        if let Err(e) = fetch_data_repo(&args.data_dir, &args.data_repo) {
-            eprintln!("Error fetching data: {}", e);
-            std::process::exit(1);
-        }
-    }
+        fetch_data_repo(&git, data_dir, "https://example.com/repo.git", None, None).unwrap();
 
-    match &args.command {
-        Some(Commands::TopCards(top_args)) => {
-            run_top_cards(&args, top_args);
-        }
-        Some(Commands::SearchDecks(search_args)) => {
-            run_search_decks(&args, search_args);
-        }
-        None => {
-            // Default to top-cards with default arguments
-            let default_args = TopCardsArgs {
-                num: 5000,
-                output: None,
-                half_life: 45.0,
-                no_weight: false,
-                resolve_faces: true,
-            };
-            run_top_cards(&args, &default_args);
-        }
+        let calls = git.calls.borrow();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[1], format!("clone --depth=1 https://example.com/repo.git {}", data_dir));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::TempDir;
+    #[test]
+    fn test_fetch_data_repo_pulls_with_git_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+        let git = MockGit::default();
+
+        fetch_data_repo(&git, temp_dir.path().to_str().unwrap(), "unused", Some("main"), None).unwrap();
+
+        assert_eq!(
+            *git.calls.borrow(),
+            vec![
+                "--version",
+                "rev-parse --verify HEAD",
+                "fetch --depth=1 origin +refs/heads/main:refs/remotes/origin/main",
+                "remote set-branches --add origin main",
+                "checkout -B main --track origin/main",
+            ]
+        );
 
-    // ==================== Unit Tests for parse_card_criterion ====================
+        let git = MockGit::default();
+        fetch_data_repo(&git, temp_dir.path().to_str().unwrap(), "unused", None, None).unwrap();
+        assert_eq!(git.calls.borrow()[2..], ["pull --ff-only"]);
+    }
 
     #[test]
-    fn test_parse_card_criterion_with_count() {
-        let criterion = parse_card_criterion("4 Lightning Bolt");
-        assert_eq!(criterion.name, "Lightning Bolt");
-        assert_eq!(criterion.count, Some(4));
+    fn test_fetch_data_repo_switches_branch_of_existing_clone() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+
+        let git = MockGit::default();
+        fetch_data_repo(&git, dir, "unused", Some("archive"), None).unwrap();
+        let calls = git.calls.borrow();
+        assert_eq!(calls[2], "fetch --depth=1 origin +refs/heads/archive:refs/remotes/origin/archive");
+        assert_eq!(calls[3..], ["remote set-branches --add origin archive", "checkout -B archive --track origin/archive"]);
+
+        let git = MockGit {
+            failing: vec!["fetch"],
+            ..Default::default()
+        };
+        let err = fetch_data_repo(&git, dir, "unused", Some("missing"), None).unwrap_err();
+        assert!(err.contains("Failed to fetch branch 'missing'"));
+        assert_eq!(git.calls.borrow().len(), 3);
     }
 
     #[test]
-    fn test_parse_card_criterion_without_count() {
-        let criterion = parse_card_criterion("Lightning Bolt");
-        assert_eq!(criterion.name, "Lightning Bolt");
-        assert_eq!(criterion.count, None);
+    fn test_fetch_data_repo_reclones_incomplete_clone() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join("data");
+        std::fs::create_dir_all(data_dir.join(".git")).unwrap();
+        std::fs::write(
+            data_dir.join(".git").join("config"),
+            "[remote \"origin\"]\n\turl = https://example.com/repo.git\n",
+        )
+        .unwrap();
+        let data_dir = data_dir.to_str().unwrap();
+        let git = MockGit {
+            failing: vec!["rev-parse"],
+            ..Default::default()
+        };
+
+        fetch_data_repo(&git, data_dir, "https://example.com/repo.git", None, None).unwrap();
+
+        let calls = git.calls.borrow();
+        assert_eq!(calls[1], "rev-parse --verify HEAD");
+        assert_eq!(calls[2], format!("clone --depth=1 https://example.com/repo.git {}", data_dir));
+        assert!(!calls.iter().any(|c| c.starts_with("pull")));
+        assert!(!Path::new(data_dir).exists());
     }
 
     #[test]
-    fn test_parse_card_criterion_with_extra_whitespace() {
-        let criterion = parse_card_criterion("  4   Ragavan, Nimble Pilferer  ");
-        assert_eq!(criterion.name, "Ragavan, Nimble Pilferer");
-        assert_eq!(criterion.count, Some(4));
+    fn test_fetch_data_repo_keeps_foreign_incomplete_repo() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+        let git = MockGit {
+            failing: vec!["rev-parse"],
+            ..Default::default()
+        };
+
+        let err = fetch_data_repo(&git, temp_dir.path().to_str().unwrap(), "repo", None, None).unwrap_err();
+        assert!(err.contains("remove it manually"));
+        assert!(temp_dir.path().join(".git").exists());
     }
 
     #[test]
-    fn test_parse_card_criterion_single_copy() {
-        let criterion = parse_card_criterion("1 Emrakul, the Aeons Torn");
-        assert_eq!(criterion.name, "Emrakul, the Aeons Torn");
-        assert_eq!(criterion.count, Some(1));
+    fn test_fetch_data_repo_sparse_falls_back_to_full_checkout() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join("data");
+        let git = MockGit {
+            failing: vec!["sparse-checkout"],
+            ..Default::default()
+        };
+
+        fetch_data_repo(&git, data_dir.to_str().unwrap(), "repo", None, Some("Modern")).unwrap();
+
+        let calls = git.calls.borrow();
+        assert_eq!(calls[2..], ["sparse-checkout init --cone", "sparse-checkout disable", "checkout"]);
     }
 
     #[test]
-    fn test_parse_card_criterion_card_starting_with_number() {
-        // Card name that might look like it starts with a number
-        let criterion = parse_card_criterion("97th Regiment");
-        assert_eq!(criterion.name, "th Regiment");
-        assert_eq!(criterion.count, Some(97));
+    fn test_fetch_data_repo_reports_missing_git() {
+        struct NoGit;
+        impl GitRunner for NoGit {
+            fn run(&self, _args: &[&str], _cwd: &Path) -> Result<(), String> {
+                Err("git not found on PATH".to_string())
+            }
+        }
+
+        let err = fetch_data_repo(&NoGit, "./unused", "repo", Some("main"), None).unwrap_err();
+        assert_eq!(err, "git not found on PATH");
     }
 
     #[test]
-    fn test_parse_card_criterion_empty_string() {
-        let criterion = parse_card_criterion("");
-        assert_eq!(criterion.name, "");
-        assert_eq!(criterion.count, None);
+    fn test_git_sparse_clone_args() {
+        let args = git_clone_args("https://example.com/repo.git", "./data", None, true);
+        assert_eq!(args, vec!["clone", "--depth=1", "--no-checkout", "https://example.com/repo.git", "./data"]);
+
+        assert_eq!(
+            git_sparse_checkout_args("Modern"),
+            vec![vec!["sparse-checkout", "init", "--cone"], vec!["sparse-checkout", "set", "Modern"]]
+        );
     }
 
-    // ==================== Unit Tests for deck_matches_criteria ====================
+    #[test]
+    fn test_no_files_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let parse = |argv: &[&str]| {
+            let matches = Args::command().try_get_matches_from(argv).unwrap();
+            args_with_config(&matches, &Config::default()).unwrap()
+        };
 
-    fn create_test_deck(mainboard: Vec<(&str, u32)>, sideboard: Vec<(&str, u32)>) -> Deck {
-        Deck {
-            player: Some("TestPlayer".to_string()),
-            result: Some("1st".to_string()),
-            url: Some("https://example.com/deck/123".to_string()),
-            mainboard: Some(
-                mainboard
-                    .into_iter()
-                    .map(|(name, count)| Card {
-                        name: name.to_string(),
-                        count,
-                    })
-                    .collect(),
-            ),
-            sideboard: Some(
-                sideboard
-                    .into_iter()
-                    .map(|(name, count)| Card {
-                        name: name.to_string(),
-                        count,
-                    })
-                    .collect(),
-            ),
-        }
+        let args = parse(&["top_cards", "-d", dir, "top-cards"]);
+        let files = listed_files(&args);
+        assert!(files.is_empty());
+        let message = no_files_error(&args, &files).unwrap();
+        assert!(message.contains(dir));
+
+        let args = parse(&["top_cards", "-d", dir, "top-cards", "--allow-empty"]);
+        assert_eq!(no_files_error(&args, &files), None);
+
+        create_test_tournament_file(temp_dir.path(), "2025/01/10/tournament.json", sample_tournament_json());
+        let args = parse(&["top_cards", "-d", dir, "top-cards"]);
+        let files = listed_files(&args);
+        assert_eq!(no_files_error(&args, &files), None);
     }
 
     #[test]
-    fn test_deck_matches_single_card_present() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 4), ("Mountain", 20)],
-            vec![],
-        );
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: None,
-        }];
+    fn test_config_half_life_overridden_by_flag() {
+        let config: Config = toml::from_str(
+            r#"
+            formats = "Pioneer"
+            [top-cards]
+            half_life = 30.0
+            "#,
+        )
+        .unwrap();
+        let half_life = |argv: &[&str]| {
+            let matches = Args::command().try_get_matches_from(argv).unwrap();
+            let args = args_with_config(&matches, &config).unwrap();
+            assert_eq!(args.formats, "Pioneer");
+            match args.command {
+                Some(Commands::TopCards(a)) => a.half_life,
+                _ => panic!("expected top-cards"),
+            }
+        };
 
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_some());
-        let matches = result.unwrap();
-        assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].found_main, 4);
+        assert_eq!(half_life(&["top_cards", "top-cards"]), 30.0);
+        assert_eq!(half_life(&["top_cards", "top-cards", "-l", "10"]), 10.0);
+        assert_eq!(half_life(&["top_cards"]), 30.0);
+
+        // Global flags given after the subcommand still win
+        let matches = Args::command()
+            .try_get_matches_from(["top_cards", "top-cards", "-f", "Modern"])
+            .unwrap();
+        assert_eq!(args_with_config(&matches, &config).unwrap().formats, "Modern");
     }
 
     #[test]
-    fn test_deck_matches_single_card_missing() {
-        let deck = create_test_deck(
-            vec![("Mountain", 20)],
-            vec![],
-        );
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: None,
-        }];
+    fn test_detailed_conflicts_with_since_file() {
+        // Aggregates don't store per-card deck presence
+        let result = Args::command().try_get_matches_from(["top_cards", "top-cards", "--detailed", "--since-file", "a.json"]);
+        assert_eq!(result.unwrap_err().kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
 
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_none());
+    #[test]
+    fn test_implicit_top_cards_takes_top_level_flags() {
+        let top_args = |argv: &[&str], config: &Config| {
+            let matches = Args::command().try_get_matches_from(argv).unwrap();
+            match args_with_config(&matches, config).unwrap().command {
+                Some(Commands::TopCards(a)) => a,
+                _ => panic!("expected top-cards"),
+            }
+        };
+
+        let defaults = top_args(&["top_cards"], &Config::default());
+        assert_eq!((defaults.num, defaults.num_set, defaults.half_life), (5000, false, 45.0));
+        assert!(defaults.resolve_faces);
+        assert_eq!(defaults.size_cap, 64);
+
+        let a = top_args(&["top_cards", "--half-life", "30", "-n", "20", "--jsonl"], &Config::default());
+        assert_eq!((a.half_life, a.num, a.num_set, a.jsonl), (30.0, 20, true, true));
+
+        let config: Config = toml::from_str("[top-cards]\nhalf_life = 10.0\nnum = 7").unwrap();
+        let a = top_args(&["top_cards", "--half-life", "30"], &config);
+        assert_eq!((a.half_life, a.num, a.num_set), (30.0, 7, true));
+    }
+
+    #[test]
+    fn test_top_level_top_cards_flags_need_no_subcommand() {
+        let parse = |argv: &[&str]| {
+            let matches = cli().try_get_matches_from(argv).unwrap();
+            args_with_config(&matches, &Config::default()).map(|_| ())
+        };
+
+        assert!(parse(&["top_cards", "-n", "10"]).is_ok());
+        assert!(parse(&["top_cards", "-f", "Modern", "search-decks", "Lightning Bolt"]).is_ok());
+        let err = parse(&["top_cards", "-n", "10", "search-decks", "Lightning Bolt"]).unwrap_err();
+        assert_eq!(err, "--num is a top-cards option; pass it after the search-decks subcommand");
+        assert!(parse(&["top_cards", "--jsonl", "top-cards"]).is_err());
+
+        // Hidden from the top-level help, but still listed under top-cards
+        let help = cli().render_long_help().to_string();
+        assert!(!help.contains("--half-life"));
+        assert!(help.contains("--formats"));
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("half_lfie = 3").is_err());
     }
 
     #[test]
-    fn test_deck_matches_with_count_satisfied() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 4)],
-            vec![],
-        );
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: Some(4),
-        }];
+    fn test_check_data_repo() {
+        assert_eq!(check_data_repo("abc123", Ok(()), 42), Ok("42 JSON files at abc123".to_string()));
+        assert!(check_data_repo("abc123", Ok(()), 0).is_err());
+        assert!(check_data_repo("", Ok(()), 42).is_err());
+        let err = check_data_repo("abc123", Err("missing blob".to_string()), 42).unwrap_err();
+        assert!(err.contains("missing blob"));
+    }
 
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_some());
+    fn sample_scryfall_json() -> &'static str {
+        r#"[
+            {"name": "Lightning Bolt", "layout": "normal", "colors": ["R"], "color_identity": ["R"], "rarity": "common", "type_line": "Instant", "cmc": 1.0, "prices": {"usd": "1.25", "eur": null}},
+            {"name": "Counterspell", "layout": "normal", "colors": ["U"], "color_identity": ["U"], "rarity": "uncommon", "type_line": "Instant", "cmc": 2.0, "prices": {"usd": null}},
+            {"name": "Lightning Helix", "layout": "normal", "colors": ["R", "W"], "color_identity": ["R", "W"], "rarity": "uncommon", "type_line": "Instant", "cmc": 2.0},
+            {"name": "Ornithopter", "layout": "normal", "colors": [], "color_identity": [], "rarity": "mythic", "type_line": "Artifact Creature — Thopter", "cmc": 0.0},
+            {"name": "Emrakul, the Aeons Torn", "layout": "normal", "colors": [], "color_identity": [], "rarity": "mythic", "type_line": "Legendary Creature — Eldrazi", "cmc": 15.0},
+            {"name": "Mountain", "layout": "normal", "colors": [], "color_identity": ["R"], "rarity": "common", "type_line": "Basic Land — Mountain", "cmc": 0.0},
+            {"name": "Delver of Secrets // Insectile Aberration", "layout": "transform", "color_identity": ["U"], "rarity": "common", "cmc": 1.0,
+             "card_faces": [
+                {"name": "Delver of Secrets", "colors": ["U"], "type_line": "Creature — Human Wizard"},
+                {"name": "Insectile Aberration", "colors": ["U"], "type_line": "Creature — Human Insect"}
+             ]}
+        ]"#
+    }
+
+    fn write_scryfall_fixture(dir: &Path) -> PathBuf {
+        let path = dir.join("oracle-cards.json");
+        std::fs::write(&path, sample_scryfall_json()).unwrap();
+        path
+    }
+
+    /// Serve one canned HTTP response per connection, in order, and return the URL
+    fn serve_responses(responses: Vec<&'static str>) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/bulk-data", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = std::io::Read::read(&mut stream, &mut buf).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
     }
 
     #[test]
-    fn test_deck_matches_with_count_not_satisfied() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 2)],
-            vec![],
+    fn test_colors_filter_mono_red() {
+        let temp_dir = TempDir::new().unwrap();
+        let identities = load_color_identities_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert_eq!(identities["Lightning Helix"], 0b01001);
+        assert_eq!(identities["Insectile Aberration"], 0b00010);
+        assert_eq!(identities["Ornithopter"], 0);
+
+        create_test_tournament_file(
+            temp_dir.path(),
+            "2025/01/10/tournament.json",
+            r#"{
+                "tournament": {"format": "Modern"},
+                "decks": [
+                    {"player": "Red", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 20, "name": "Mountain"}]},
+                    {"player": "Boros", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 4, "name": "Lightning Helix"}]},
+                    {"player": "Splash", "mainboard": [{"count": 4, "name": "Lightning Bolt"}], "sideboard": [{"count": 2, "name": "Counterspell"}]},
+                    {"player": "Artifacts", "mainboard": [{"count": 4, "name": "Ornithopter"}]},
+                    {"player": "Unknown", "mainboard": [{"count": 4, "name": "Lightning Bolt"}, {"count": 4, "name": "Goblin Guide"}]}
+                ]
+            }"#,
         );
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: Some(4),
-        }];
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let players = |colors: &str, exact: bool, unknown: UnknownColors| {
+            let scope = ScopeFilter {
+                colors: Some(ColorFilter {
+                    colors: parse_color_mask(colors).unwrap(),
+                    exact,
+                    unknown,
+                    identities: Arc::new(identities.clone()),
+                }),
+                ..test_scope(&["Modern"])
+            };
+            let data = load_scoped_file(&path, &scope).unwrap().data;
+            data.decks.unwrap().into_iter().map(|d| d.player.unwrap()).collect::<Vec<_>>()
+        };
 
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_none());
+        assert_eq!(players("R", false, UnknownColors::Skip), ["Red", "Artifacts"]);
+        assert_eq!(players("r", false, UnknownColors::Ignore), ["Red", "Artifacts", "Unknown"]);
+        assert_eq!(players("R", true, UnknownColors::Skip), ["Red"]);
+        assert_eq!(players("RW", false, UnknownColors::Skip), ["Red", "Boros", "Artifacts"]);
+        assert_eq!(players("C", true, UnknownColors::Skip), ["Artifacts"]);
+
+        let scope = ScopeFilter {
+            colors: Some(ColorFilter {
+                colors: parse_color_mask("R").unwrap(),
+                exact: false,
+                unknown: UnknownColors::Skip,
+                identities: Arc::new(identities),
+            }),
+            ..test_scope(&["Modern"])
+        };
+        let options = ProcessOptions {
+            use_weight: false,
+            ..Default::default()
+        };
+        let counts = process_file(&path, &scope, &options, None);
+        assert_eq!(counts.decks, 2);
+        assert_eq!(counts.cards["Lightning Bolt"], 4.0);
+        assert!(!counts.cards.contains_key("Lightning Helix"));
     }
 
     #[test]
-    fn test_deck_matches_exact_count_satisfied() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 2)],
-            vec![],
-        );
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: Some(2),
-        }];
+    fn test_parse_color_mask() {
+        assert_eq!(parse_color_mask("WUBRG"), Ok(0b11111));
+        assert_eq!(parse_color_mask("rg"), Ok(0b11000));
+        assert_eq!(parse_color_mask("C"), Ok(0));
+        assert!(parse_color_mask("RX").is_err());
+        assert!(parse_color_mask("").is_err());
+    }
 
-        let result = deck_matches_criteria(&deck, &criteria, true, false);
-        assert!(result.is_some());
+    #[test]
+    fn test_get_with_retries_recovers_from_503() {
+        let unavailable = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let url = serve_responses(vec![
+            unavailable,
+            unavailable,
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ]);
+        let agent = scryfall_agent();
+        let response = get_with_retries(&agent, &url, 3, Duration::from_millis(1)).unwrap();
+        assert_eq!(response.into_string().unwrap(), "ok");
     }
 
     #[test]
-    fn test_deck_matches_exact_count_not_satisfied() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 4)],
-            vec![],
-        );
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: Some(2),
-        }];
+    fn test_get_with_retries_gives_up() {
+        let url = serve_responses(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let agent = scryfall_agent();
+        let err = get_with_retries(&agent, &url, 1, Duration::from_millis(1)).unwrap_err();
+        assert!(matches!(*err, ureq::Error::Status(503, _)));
+    }
 
-        // exact=true, so 4 != 2
-        let result = deck_matches_criteria(&deck, &criteria, true, false);
-        assert!(result.is_none());
+    #[test]
+    fn test_backoff_delay_is_capped() {
+        let base = Duration::from_secs(1);
+        assert_eq!(backoff_delay(base, 0), base);
+        assert_eq!(backoff_delay(base, 3), Duration::from_secs(8));
+        assert_eq!(backoff_delay(base, 6), SCRYFALL_MAX_BACKOFF);
+        assert_eq!(backoff_delay(base, u32::MAX), SCRYFALL_MAX_BACKOFF);
     }
 
     #[test]
-    fn test_deck_matches_sideboard_included() {
-        let deck = create_test_deck(
-            vec![("Mountain", 20)],
-            vec![("Blood Moon", 2)],
+    fn test_scryfall_bulk_selection() {
+        assert!(scryfall_cache_path(ScryfallBulk::OracleCards).ends_with(".scryfall/oracle-cards.json"));
+        assert_ne!(scryfall_cache_path(ScryfallBulk::OracleCards), scryfall_cache_path(ScryfallBulk::DefaultCards));
+
+        let matches = Args::command().try_get_matches_from(["top_cards", "--scryfall-bulk", "all_cards"]).unwrap();
+        let args = args_with_config(&matches, &Config::default()).unwrap();
+        assert_eq!(args.scryfall_bulk, ScryfallBulk::AllCards);
+        assert!(Args::command().try_get_matches_from(["top_cards", "--scryfall-bulk", "../x"]).is_err());
+
+        let index: ScryfallBulkDataResponse = serde_json::from_str(
+            r#"{"object": "list", "data": [
+                {"type": "oracle_cards", "download_uri": "https://example.com/oracle.json"},
+                {"type": "default_cards", "download_uri": "https://example.com/default.json"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            select_bulk_entry(&index, "default_cards").unwrap().download_uri,
+            "https://example.com/default.json"
         );
-        let criteria = vec![CardCriterion {
-            name: "Blood Moon".to_string(),
-            count: None,
-        }];
+        let err = select_bulk_entry(&index, "all_cards").unwrap_err();
+        assert!(err.contains("all_cards"));
+        assert!(err.contains("oracle_cards, default_cards"));
+    }
 
-        // Without sideboard
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_none());
+    #[test]
+    fn test_load_back_faces_from_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let back_faces = load_back_faces_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert_eq!(back_faces.len(), 1);
+        assert_eq!(back_faces.get("Delver of Secrets"), Some(&"Insectile Aberration".to_string()));
+    }
 
-        // With sideboard
-        let result = deck_matches_criteria(&deck, &criteria, false, true);
-        assert!(result.is_some());
-        let matches = result.unwrap();
-        assert_eq!(matches[0].found_side, 2);
+    #[test]
+    fn test_back_faces_from_local_scryfall_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let local = temp_dir.path().join("snapshot.json");
+        std::fs::write(&local, sample_scryfall_json()).unwrap();
+
+        // A bulk type with no cache would otherwise need a download
+        let path = scryfall_data_path(ScryfallBulk::AllCards, Some(&local)).unwrap();
+        assert_eq!(path, local);
+        let back_faces = load_back_faces_from_cache(&path);
+        assert_eq!(back_faces.get("Delver of Secrets"), Some(&"Insectile Aberration".to_string()));
     }
 
     #[test]
-    fn test_deck_matches_multiple_criteria_all_match() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 4), ("Ragavan, Nimble Pilferer", 4)],
-            vec![],
-        );
-        let criteria = vec![
-            CardCriterion {
-                name: "Lightning Bolt".to_string(),
-                count: Some(4),
-            },
-            CardCriterion {
-                name: "Ragavan, Nimble Pilferer".to_string(),
-                count: Some(4),
-            },
+    fn test_unknown_card_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let known = load_oracle_names_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert!(known.contains("Insectile Aberration"));
+        assert!(known.contains("Delver of Secrets // Insectile Aberration"));
+
+        let cards = vec![
+            ("Lightning Bolt".to_string(), 10.0),
+            ("Lightnig Bolt".to_string(), 2.0),
+            ("Delver of Secrets".to_string(), 1.0),
+            ("Rayo".to_string(), 1.0),
         ];
-
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_some());
-        let matches = result.unwrap();
-        assert_eq!(matches.len(), 2);
+        assert_eq!(unknown_card_names(&cards, &known), vec!["Lightnig Bolt", "Rayo"]);
     }
 
     #[test]
-    fn test_deck_matches_multiple_criteria_one_missing() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 4)],
-            vec![],
-        );
-        let criteria = vec![
-            CardCriterion {
-                name: "Lightning Bolt".to_string(),
-                count: Some(4),
-            },
-            CardCriterion {
-                name: "Ragavan, Nimble Pilferer".to_string(),
-                count: Some(4),
-            },
-        ];
+    fn test_price_annotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let prices = load_prices_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert_eq!(prices.get("Lightning Bolt"), Some(&1.25));
+        assert_eq!(prices.get("Counterspell"), None);
+        assert_eq!(prices.get("Ornithopter"), None);
+
+        assert_eq!(price_suffix(Some(prices.get("Lightning Bolt").copied())), " $1.25");
+        assert_eq!(price_suffix(Some(prices.get("Counterspell").copied())), " -");
+        assert_eq!(price_suffix(None), "");
+
+        let line = OutputLine {
+            rank: 1,
+            name: "Counterspell".to_string(),
+            count: 4.0,
+            raw: None,
+            price: Some(None),
+            dates: None,
+            presence: None,
+        };
+        let parsed: serde_json::Value = serde_json::from_str(&jsonl_line(&line)).unwrap();
+        assert!(parsed["price_usd"].is_null());
+        assert!(parsed.get("price_usd").is_some());
+    }
 
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_none());
+    #[test]
+    fn test_load_colors_from_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let colors = load_colors_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert_eq!(colors.get("Lightning Bolt"), Some(&vec!['R']));
+        assert_eq!(colors.get("Lightning Helix"), Some(&vec!['R', 'W']));
+        assert_eq!(colors.get("Ornithopter"), Some(&vec![]));
+        assert_eq!(colors.get("Delver of Secrets"), Some(&vec!['U']));
     }
 
     #[test]
-    fn test_deck_matches_case_insensitive() {
-        let deck = create_test_deck(
-            vec![("Lightning Bolt", 4)],
-            vec![],
-        );
-        let criteria = vec![CardCriterion {
-            name: "LIGHTNING BOLT".to_string(),
-            count: None,
-        }];
+    fn test_group_by_color() {
+        let temp_dir = TempDir::new().unwrap();
+        let colors = load_colors_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        let cards = vec![
+            ("Lightning Bolt".to_string(), 10.0),
+            ("Counterspell".to_string(), 4.0),
+            ("Delver of Secrets".to_string(), 2.0),
+            ("Lightning Helix".to_string(), 3.0),
+            ("Ornithopter".to_string(), 1.0),
+            ("Not A Real Card".to_string(), 5.0),
+        ];
 
-        let result = deck_matches_criteria(&deck, &criteria, false, false);
-        assert!(result.is_some());
+        let buckets: HashMap<&str, f64> = group_by_color(&cards, &colors).into_iter().collect();
+        assert_eq!(buckets["Red"], 10.0);
+        assert_eq!(buckets["Blue"], 6.0);
+        assert_eq!(buckets["Multicolor"], 3.0);
+        assert_eq!(buckets["Colorless"], 1.0);
+        assert_eq!(buckets["Unknown"], 5.0);
+        assert_eq!(buckets["White"], 0.0);
     }
 
-    // ==================== Integration Tests ====================
+    #[test]
+    fn test_group_by_rarity() {
+        let temp_dir = TempDir::new().unwrap();
+        let rarities = load_rarities_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert_eq!(rarities.get("Delver of Secrets"), Some(&"common".to_string()));
+
+        let cards = vec![
+            ("Lightning Bolt".to_string(), 10.0),
+            ("Delver of Secrets".to_string(), 2.0),
+            ("Counterspell".to_string(), 4.0),
+            ("Lightning Helix".to_string(), 3.0),
+            ("Ornithopter".to_string(), 1.0),
+            ("Not A Real Card".to_string(), 5.0),
+        ];
 
-    fn create_test_tournament_file(dir: &Path, date_path: &str, content: &str) {
-        let full_path = dir.join(date_path);
-        std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
-        let mut file = File::create(&full_path).unwrap();
-        file.write_all(content.as_bytes()).unwrap();
+        let buckets = group_by_rarity(&cards, &rarities);
+        assert_eq!(
+            buckets,
+            vec![
+                ("Common", 12.0),
+                ("Uncommon", 7.0),
+                ("Rare", 0.0),
+                ("Mythic", 1.0),
+                ("Special", 0.0),
+                ("Unknown", 5.0),
+            ]
+        );
     }
 
-    fn sample_tournament_json() -> &'static str {
-        r#"{
-            "tournament": {
-                "name": "Test Tournament",
-                "format": "Modern",
-                "date": "2025-01-10"
-            },
-            "decks": [
-                {
-                    "player": "Alice",
-                    "result": "1st",
-                    "mainboard": [
-                        {"count": 4, "name": "Lightning Bolt"},
-                        {"count": 4, "name": "Ragavan, Nimble Pilferer"},
-                        {"count": 20, "name": "Mountain"}
-                    ],
-                    "sideboard": [
-                        {"count": 2, "name": "Blood Moon"}
-                    ]
-                },
-                {
-                    "player": "Bob",
-                    "result": "2nd",
-                    "mainboard": [
-                        {"count": 2, "name": "Lightning Bolt"},
-                        {"count": 4, "name": "Thoughtseize"},
-                        {"count": 20, "name": "Swamp"}
-                    ],
-                    "sideboard": []
-                }
-            ]
-        }"#
+    #[test]
+    fn test_group_by_type() {
+        let temp_dir = TempDir::new().unwrap();
+        let type_lines = load_type_lines_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        let cards = vec![
+            ("Lightning Bolt".to_string(), 10.0),
+            ("Counterspell".to_string(), 4.0),
+            ("Ornithopter".to_string(), 1.0),
+            ("Delver of Secrets".to_string(), 2.0),
+            ("Mountain".to_string(), 20.0),
+            ("Not A Real Card".to_string(), 5.0),
+        ];
+
+        let buckets: HashMap<&str, f64> = group_by_type(&cards, &type_lines).into_iter().collect();
+        assert_eq!(buckets["Instant"], 14.0);
+        // "Artifact Creature" counts as a creature
+        assert_eq!(buckets["Creature"], 3.0);
+        assert_eq!(buckets["Artifact"], 0.0);
+        assert_eq!(buckets["Land"], 20.0);
+        assert_eq!(buckets["Unknown"], 5.0);
+
+        assert_eq!(type_bucket(Some(&"Artifact Land — Mountain".to_string())), "Artifact");
+        assert_eq!(type_bucket(Some(&"Sorcery // Land".to_string())), "Sorcery");
+        assert_eq!(type_bucket(Some(&"Kindred Instant — Goblin".to_string())), "Instant");
     }
 
     #[test]
-    fn test_search_file_for_decks_finds_matching_deck() {
+    fn test_fold_basics() {
+        let renames = CardRenames {
+            fold_basics: true,
+            ..Default::default()
+        };
+        assert_eq!(renames.rename("Snow-Covered Mountain".to_string()), "Mountain");
+        assert_eq!(renames.rename("snow-covered island".to_string()), "Island");
+        assert_eq!(renames.rename("Mountain".to_string()), "Mountain");
+        assert_eq!(renames.rename("Lightning Bolt".to_string()), "Lightning Bolt");
+
+        // Both variants in one deck count once towards presence
         let temp_dir = TempDir::new().unwrap();
         create_test_tournament_file(
             temp_dir.path(),
             "2025/01/10/tournament.json",
-            sample_tournament_json(),
-        );
-
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: Some(4),
-        }];
-
-        let matches = search_file_for_decks(
-            &temp_dir.path().join("2025/01/10/tournament.json"),
-            &["Modern".to_string()],
-            today_days(),
-            1825,
-            &criteria,
-            false,
-            false,
+            r#"{
+                "tournament": {"format": "Modern"},
+                "decks": [
+                    {"mainboard": [{"count": 10, "name": "Mountain"}, {"count": 4, "name": "Snow-Covered Mountain"}]},
+                    {"mainboard": [{"count": 4, "name": "Lightning Bolt"}]}
+                ]
+            }"#,
         );
-
-        assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].player, Some("Alice".to_string()));
+        let path = temp_dir.path().join("2025/01/10/tournament.json");
+        let options = ProcessOptions {
+            use_weight: false,
+            track_presence: true,
+            renames: Some(Arc::new(renames)),
+            ..Default::default()
+        };
+        let counts = process_file(&path, &test_scope(&["Modern"]), &options, None);
+        assert_eq!(counts.cards["Mountain"], 14.0);
+        assert_eq!(counts.presence_cards["Mountain"], 1.0);
+        assert!(!counts.cards.contains_key("Snow-Covered Mountain"));
+        assert_eq!(detailed_columns(counts.cards["Mountain"], counts.presence_cards["Mountain"], 2.0), " 50.0% 14.00");
+
+        let decks = collect_weighted_decks(&path, &test_scope(&["Modern"]), &options).unwrap();
+        let counts = counts_from_decks(&decks, &options);
+        assert_eq!(counts.presence_cards["Mountain"], 1.0);
     }
 
     #[test]
-    fn test_search_file_for_decks_respects_format_filter() {
+    fn test_canonicalize_names() {
         let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("all-cards.json");
+        std::fs::write(
+            &path,
+            r#"[
+                {"name": "Lightning Bolt", "layout": "normal"},
+                {"name": "Lightning Bolt", "printed_name": "Blitzschlag", "layout": "normal"},
+                {"name": "Delver of Secrets // Insectile Aberration", "layout": "transform",
+                 "card_faces": [{"name": "Delver of Secrets"}, {"name": "Insectile Aberration"}]},
+                {"name": "Fire // Ice", "layout": "split", "card_faces": [{"name": "Fire"}, {"name": "Ice"}]}
+            ]"#,
+        )
+        .unwrap();
+        let canonical = load_canonical_names_from_cache(&path);
+        assert_eq!(canonical.get("Blitzschlag").map(String::as_str), Some("Lightning Bolt"));
+        assert_eq!(
+            canonical.get("Delver of Secrets // Insectile Aberration").map(String::as_str),
+            Some("Delver of Secrets")
+        );
+        assert_eq!(canonical.get("Insectile Aberration").map(String::as_str), Some("Delver of Secrets"));
+        assert!(!canonical.contains_key("Lightning Bolt"));
+        assert!(!canonical.contains_key("Fire"));
+
+        // Alternate names in one deck merge, and the deck counts once towards presence
+        let data_dir = temp_dir.path().join("data");
         create_test_tournament_file(
-            temp_dir.path(),
+            &data_dir,
             "2025/01/10/tournament.json",
-            sample_tournament_json(),
+            r#"{
+                "tournament": {"format": "Modern"},
+                "decks": [
+                    {"mainboard": [
+                        {"count": 4, "name": "Lightning Bolt"},
+                        {"count": 2, "name": "Blitzschlag"},
+                        {"count": 3, "name": "Delver of Secrets // Insectile Aberration"},
+                        {"count": 1, "name": "Delver of Secrets"},
+                        {"count": 1, "name": "Homebrew Card"}
+                    ]}
+                ]
+            }"#,
         );
+        let options = ProcessOptions {
+            use_weight: false,
+            track_presence: true,
+            renames: Some(Arc::new(CardRenames {
+                canonical,
+                fold_basics: false,
+            })),
+            ..Default::default()
+        };
+        let counts = process_file(
+            &data_dir.join("2025/01/10/tournament.json"),
+            &test_scope(&["Modern"]),
+            &options,
+            None,
+        );
+        assert_eq!(counts.cards.len(), 3);
+        assert_eq!(counts.cards["Lightning Bolt"], 6.0);
+        assert_eq!(counts.cards["Delver of Secrets"], 4.0);
+        assert_eq!(counts.cards["Homebrew Card"], 1.0);
+        assert_eq!(counts.presence_cards["Lightning Bolt"], 1.0);
+        assert_eq!(counts.presence_cards["Delver of Secrets"], 1.0);
+    }
 
-        let criteria = vec![CardCriterion {
-            name: "Lightning Bolt".to_string(),
-            count: None,
-        }];
+    #[test]
+    fn test_land_ratio() {
+        let temp_dir = TempDir::new().unwrap();
+        let type_lines = load_type_lines_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        let cards: HashMap<String, f64> = [
+            ("Lightning Bolt", 10.0),
+            ("Counterspell", 4.0),
+            ("Mountain", 6.0),
+            ("Not A Real Card", 5.0),
+        ]
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
 
-        // Search with wrong format
-        let matches = search_file_for_decks(
-            &temp_dir.path().join("2025/01/10/tournament.json"),
-            &["Standard".to_string()],
-            today_days(),
-            1825,
-            &criteria,
-            false,
-            false,
+        let ratio = land_ratio(&cards, &type_lines);
+        assert_eq!(
+            ratio,
+            LandRatio {
+                lands: 6.0,
+                spells: 14.0,
+                missing: 1,
+            }
         );
-
-        assert_eq!(matches.len(), 0);
+        assert!((ratio.land_share() - 0.3).abs() < 1e-9);
+        assert_eq!(LandRatio::default().land_share(), 0.0);
     }
 
     #[test]
-    fn test_process_file_aggregates_card_counts() {
+    fn test_group_by_mana_value() {
         let temp_dir = TempDir::new().unwrap();
-        create_test_tournament_file(
-            temp_dir.path(),
-            "2025/01/10/tournament.json",
-            sample_tournament_json(),
-        );
+        let mana_values = load_mana_values_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert_eq!(mana_values.get("Insectile Aberration"), Some(&1.0));
+        assert!(!mana_values.contains_key("Mountain"));
+
+        let cards = vec![
+            ("Lightning Bolt".to_string(), 10.0),
+            ("Delver of Secrets".to_string(), 2.0),
+            ("Counterspell".to_string(), 4.0),
+            ("Lightning Helix".to_string(), 3.0),
+            ("Ornithopter".to_string(), 1.0),
+            ("Emrakul, the Aeons Torn".to_string(), 0.5),
+            ("Mountain".to_string(), 20.0),
+            ("Not A Real Card".to_string(), 5.0),
+        ];
 
-        let counts = process_file(
-            &temp_dir.path().join("2025/01/10/tournament.json"),
-            &["Modern".to_string()],
-            today_days(),
-            45.0,
-            1825,
-            false, // no weight for easier testing
+        let buckets = group_by_mana_value(&cards, &mana_values);
+        assert_eq!(
+            buckets,
+            vec![
+                ("0", 1.0),
+                ("1", 12.0),
+                ("2", 7.0),
+                ("3", 0.0),
+                ("4", 0.0),
+                ("5", 0.0),
+                ("6", 0.0),
+                ("7+", 0.5),
+                ("N/A", 25.0),
+            ]
         );
+    }
 
-        // Lightning Bolt: 4 (Alice) + 2 (Bob) = 6
-        assert_eq!(counts.get("Lightning Bolt"), Some(&6.0));
-        // Mountain: 20 (Alice only)
-        assert_eq!(counts.get("Mountain"), Some(&20.0));
-        // Swamp: 20 (Bob only)
-        assert_eq!(counts.get("Swamp"), Some(&20.0));
+    #[test]
+    fn test_progress_reader_reports_increasing_counts() {
+        let data = vec![7u8; 100_000];
+        let mut reported = Vec::new();
+        let mut reader = ProgressReader::new(std::io::Cursor::new(&data), |n| reported.push(n));
+        let mut sink = Vec::new();
+        std::io::copy(&mut reader, &mut sink).unwrap();
+
+        assert_eq!(sink.len(), data.len());
+        assert!(!reported.is_empty());
+        assert!(reported.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(*reported.last().unwrap(), data.len() as u64);
     }
 
     #[test]
-    fn test_extract_date_from_path() {
-        let date = extract_date_from_path("/data/2025/01/15/tournament.json");
-        assert_eq!(date, Some((2025, 1, 15)));
+    fn test_render_template() {
+        let template = parse_template("{rank}. {name} ({count:.0})").unwrap();
+        let line = OutputLine {
+            rank: 3,
+            name: "Lightning Bolt".to_string(),
+            count: 123.456,
+            raw: None,
+            price: None,
+            dates: None,
+            presence: None,
+        };
+        assert_eq!(render_template(&template, &line, 0.0, None), "3. Lightning Bolt (123)");
 
-        let no_date = extract_date_from_path("/data/tournament.json");
-        assert_eq!(no_date, None);
+        let template = parse_template("{{{share:.2}%}} {count} {name} [{type}]").unwrap();
+        assert_eq!(
+            render_template(&template, &line, 34.5, Some("Instant")),
+            "{34.50%} 123.46 Lightning Bolt [Instant]"
+        );
+        assert_eq!(
+            render_template(&template, &line, 34.5, None),
+            "{34.50%} 123.46 Lightning Bolt [-]"
+        );
+    }
+
+    #[test]
+    fn test_parse_template_errors() {
+        assert!(parse_template("{rank} {price}").unwrap_err().contains("unknown placeholder"));
+        assert!(parse_template("{name").is_err());
+        assert!(parse_template("name}").is_err());
+        assert!(parse_template("{count:3}").is_err());
+        assert!(parse_template("{name:.2}").is_err());
+    }
+
+    #[test]
+    fn test_load_type_lines_from_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let type_lines = load_type_lines_from_cache(&write_scryfall_fixture(temp_dir.path()));
+        assert_eq!(type_lines.get("Lightning Bolt"), Some(&"Instant".to_string()));
+        assert_eq!(type_lines.get("Delver of Secrets"), Some(&"Creature — Human Wizard".to_string()));
     }
 
     #[test]